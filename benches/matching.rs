@@ -0,0 +1,139 @@
+//! Benchmarks for the matcher, run with `cargo bench`.
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use pyglob::{filter, is_wildcard_match, MatchOptions, WildcardPattern};
+
+/// Patterns like the ones found in `.gitignore` files and build scripts
+const GLOBS: [(&str, &str); 6] = [
+    ("*.rs", "src/pattern.rs"),
+    ("src/*.rs", "src/lib.rs"),
+    ("*.[ch]", "include/matcher.h"),
+    ("test_*.py", "test_pyglob.py"),
+    ("*~", "notes.txt"),
+    ("build-[0-9]*.log", "build-2023-04-01.log"),
+];
+
+fn short_patterns(c: &mut Criterion) {
+    let mut group = c.benchmark_group("short");
+    group.bench_function("literal", |b| {
+        b.iter(|| is_wildcard_match(black_box("Cargo.toml"), black_box("Cargo.toml")))
+    });
+    for (pattern, text) in GLOBS {
+        group.bench_with_input(BenchmarkId::new("glob", pattern), text, |b, text| {
+            b.iter(|| is_wildcard_match(black_box(text), black_box(pattern)))
+        });
+    }
+    group.finish();
+}
+
+fn many_stars(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stars");
+    let text = "a".repeat(1_000) + "b";
+    for stars in [1, 4, 16] {
+        let pattern = "*a".repeat(stars) + "*c";
+        group.bench_with_input(
+            BenchmarkId::from_parameter(stars),
+            &pattern,
+            |b, pattern| b.iter(|| is_wildcard_match(black_box(&text), black_box(pattern))),
+        );
+    }
+    group.finish();
+}
+
+fn long_texts(c: &mut Criterion) {
+    let mut group = c.benchmark_group("long_text");
+    for length in [100, 10_000] {
+        let text = "ab".repeat(length / 2);
+        let pattern = WildcardPattern::new("*b?b*ab");
+        group.bench_with_input(BenchmarkId::from_parameter(length), &text, |b, text| {
+            b.iter(|| pattern.matches(black_box(text)))
+        });
+    }
+    // Outside of ASCII, where each grapheme is compared by its number
+    let text = "\u{e9}\u{e8}".repeat(5_000);
+    let pattern = WildcardPattern::new("*\u{e9}[!x]\u{e9}*\u{e9}\u{e9}?*\u{fc}*");
+    group.bench_function("unicode", |b| b.iter(|| pattern.matches(black_box(&text))));
+    group.finish();
+}
+
+fn ascii_and_unicode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("unicode");
+    let ascii = "cafe-".repeat(200);
+    let unicode = "caf\u{e9}-".repeat(200);
+    let decomposed = "cafe\u{301}-".repeat(200);
+    let pattern = WildcardPattern::new("caf?-*-caf?-");
+    group.bench_function("ascii", |b| b.iter(|| pattern.matches(black_box(&ascii))));
+    group.bench_function("composed", |b| {
+        b.iter(|| pattern.matches(black_box(&unicode)))
+    });
+    group.bench_function("decomposed", |b| {
+        b.iter(|| pattern.matches(black_box(&decomposed)))
+    });
+    let insensitive =
+        WildcardPattern::with_options("CAF?-*-CAF?-", &MatchOptions::new().case_insensitive(true));
+    group.bench_function("case_insensitive", |b| {
+        b.iter(|| insensitive.matches(black_box(&unicode)))
+    });
+    group.finish();
+}
+
+fn filtering(c: &mut Criterion) {
+    let names = (0..10_000)
+        .map(|i| match i % 3 {
+            0 => format!("src/module_{}.rs", i),
+            1 => format!("tests/test_{}.py", i),
+            _ => format!("docs/page_{}.md", i),
+        })
+        .collect::<Vec<String>>();
+    let names = names.iter().map(String::as_str).collect::<Vec<&str>>();
+    let mut group = c.benchmark_group("filter");
+    group.bench_function("greedy", |b| {
+        b.iter(|| filter(black_box(&names), black_box("src/*.rs")))
+    });
+    // A class needs the matching table, whose rows are reused from one name to the next
+    group.bench_function("table", |b| {
+        b.iter(|| filter(black_box(&names), black_box("*/*_[0-9]*.[pr]?")))
+    });
+    group.finish();
+}
+
+fn shared_prefixes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("shared_prefix");
+    // Paths which all start the same way, and are told apart by how they end
+    let ascii = (0..1_000)
+        .map(|i| {
+            format!(
+                "logs/2023/service-{}/worker-{}/run-{}.log",
+                i % 7,
+                i % 13,
+                i
+            )
+        })
+        .collect::<Vec<String>>();
+    let unicode = ascii
+        .iter()
+        .map(|path| path.replace("service", "s\u{e9}rvice"))
+        .collect::<Vec<String>>();
+    let pattern = WildcardPattern::new("logs/*/worker-1/run-999.log");
+    for (name, paths) in [("ascii", &ascii), ("unicode", &unicode)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                paths
+                    .iter()
+                    .filter(|path| pattern.matches(black_box(path)))
+                    .count()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    short_patterns,
+    many_stars,
+    long_texts,
+    ascii_and_unicode,
+    filtering,
+    shared_prefixes
+);
+criterion_main!(benches);
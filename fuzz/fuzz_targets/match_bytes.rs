@@ -0,0 +1,11 @@
+//! Matches random bytes, which need not be valid UTF-8, against random byte patterns, and
+//! checks that nothing panics.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pyglob::is_wildcard_match_bytes;
+
+fuzz_target!(|input: (&[u8], &[u8])| {
+    let (text, pattern) = input;
+    let _ = is_wildcard_match_bytes(text, pattern);
+});
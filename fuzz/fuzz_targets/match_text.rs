@@ -0,0 +1,57 @@
+//! Matches random patterns against random texts, with every combination of options, and
+//! checks that nothing panics.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pyglob::{
+    translate_to_regex, validate, Granularity, MatchOptions, Normalization, WildcardPattern,
+};
+
+fuzz_target!(|input: (u32, &str, &str)| {
+    let (flags, text, pattern) = input;
+    let flag = |bit: u32| flags & (1 << bit) != 0;
+
+    let mut options = MatchOptions::new()
+        .escapes(flag(0))
+        .case_insensitive(flag(1))
+        .case_folding(flag(15))
+        .braces(flag(2))
+        .lazy_captures(flag(3))
+        .optional_question_marks(flag(4))
+        .literal(flag(5))
+        .anchor_start(flag(6))
+        .anchor_end(flag(7))
+        .collapse_whitespace(flag(12))
+        .fnmatch(flag(16))
+        .multiline(flag(17))
+        .trim(flag(18))
+        .forward_slashes(flag(19))
+        .alternation(!flag(20));
+    if flag(8) {
+        options = options.normalization(Normalization::Nfc);
+    } else if flag(9) {
+        options = options.normalization(Normalization::Nfd);
+    }
+    if flag(13) {
+        options = options.granularity(Granularity::Scalars);
+    } else if flag(14) {
+        options = options.granularity(Granularity::Bytes);
+    }
+    if flag(10) {
+        options = options.separator(if flag(11) { '\\' } else { '/' });
+    }
+    if flag(21) {
+        options = options.wildcards('%', '_').unwrap_or(options);
+    }
+
+    let _ = validate(pattern);
+    let _ = translate_to_regex(pattern);
+    let compiled = WildcardPattern::with_options(pattern, &options);
+    let matches = compiled.matches(text);
+    if let Ok(within_budget) = compiled.try_matches(text) {
+        assert_eq!(matches, within_budget);
+    }
+    assert_eq!(matches, compiled.captures(text).is_some());
+    assert_eq!(matches, compiled.matches_within_distance(text, 0));
+    let _ = compiled.to_string();
+});
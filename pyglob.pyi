@@ -0,0 +1,83 @@
+"""Type stubs for the functions and classes exported by the Rust extension module.
+
+These have to be kept in step with `src/python.rs` and the `#[pymethods]` in
+`src/pattern.rs` by hand, and `tests/test_pyglob.py` checks that every exported name is
+declared here.
+"""
+from typing import Iterable, Iterator, Literal, Optional, Union
+
+_Normalization = Literal["NFC", "NFD"]
+
+def is_wildcard_match(
+    text: str,
+    pattern: Union[str, WildcardPattern],
+    *,
+    escapes: bool = True,
+    case_insensitive: bool = False,
+    normalization: Optional[_Normalization] = None,
+    separator: Optional[str] = None,
+    braces: bool = False,
+    optional_question_marks: bool = False,
+    literal: bool = False,
+) -> bool: ...
+def is_wildcard_search(text: str, pattern: str) -> bool: ...
+def find(text: str, pattern: str) -> Optional[tuple[int, int]]: ...
+def is_wildcard_match_without_escapes(text: str, pattern: str) -> bool: ...
+def is_wildcard_match_case_insensitive(text: str, pattern: str) -> bool: ...
+def is_wildcard_match_normalized(text: str, pattern: str) -> bool: ...
+def is_wildcard_match_path(text: str, pattern: str, separator: str = "/") -> bool: ...
+def is_wildcard_match_bytes(text: bytes, pattern: bytes) -> bool: ...
+def is_wildcard_match_fnmatch(text: str, pattern: str) -> bool: ...
+def captures(text: str, pattern: str, *, lazy: bool = False) -> Optional[list[str]]: ...
+def compile(
+    pattern: str,
+    *,
+    escapes: bool = True,
+    case_insensitive: bool = False,
+    normalization: Optional[_Normalization] = None,
+    separator: Optional[str] = None,
+    braces: bool = False,
+    optional_question_marks: bool = False,
+    literal: bool = False,
+    strict: bool = False,
+) -> WildcardPattern: ...
+def purge() -> None: ...
+def filter(names: list[str], pattern: str) -> list[str]: ...
+def ifilter(names: Iterable[str], pattern: str) -> Iterator[str]: ...
+def filter_indices(names: list[str], pattern: str) -> list[int]: ...
+def partition(names: list[str], pattern: str) -> tuple[list[str], list[str]]: ...
+def count_matches(texts: list[str], pattern: str) -> int: ...
+def matches_all_texts(texts: list[str], pattern: str) -> list[bool]: ...
+def match_any(text: str, patterns: list[str]) -> bool: ...
+def match_all(text: str, patterns: list[str]) -> bool: ...
+def matches_at_least_one_of_each(text: str, groups: list[list[str]]) -> bool: ...
+def matches_filtered(text: str, includes: list[str], excludes: list[str]) -> bool: ...
+def first_match(text: str, patterns: list[str]) -> Optional[int]: ...
+def matching_patterns(text: str, patterns: list[str]) -> list[str]: ...
+def match_each(text: str, patterns: list[str]) -> list[bool]: ...
+def validate(pattern: str) -> None: ...
+def escape(literal: str) -> str: ...
+def unescape(pattern: str) -> str: ...
+def translate(pattern: str) -> str: ...
+
+class WildcardPattern:
+    def __init__(self, pattern: str) -> None: ...
+    def matches(self, text: str) -> bool: ...
+    def captures(self, text: str) -> Optional[list[str]]: ...
+    def prefix(self) -> str: ...
+    def suffix(self) -> str: ...
+    def literal_segments(self) -> list[str]: ...
+    def is_literal(self) -> bool: ...
+    def has_wildcards(self) -> bool: ...
+    def num_stars(self) -> int: ...
+    def num_wildcards(self) -> int: ...
+    def min_len(self) -> int: ...
+    def max_len(self) -> Optional[int]: ...
+    def complexity_hint(self) -> int: ...
+    def specificity(self) -> int: ...
+    def explain(self) -> str: ...
+    def canonical_string(self) -> str: ...
+    def __contains__(self, text: str) -> bool: ...
+    def __eq__(self, other: object) -> bool: ...
+    def __ne__(self, other: object) -> bool: ...
+    def __hash__(self) -> int: ...
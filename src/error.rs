@@ -0,0 +1,66 @@
+use std::error::Error;
+use std::fmt;
+
+/// A problem with a pattern, found by `validate`, or a match that would take too long.
+///
+/// Positions count graphemes from the start of the pattern, starting at zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternError {
+    /// A `[` which is never closed by a `]`
+    UnterminatedClass { pos: usize },
+    /// A `\` at the end of the pattern, with nothing after it to escape
+    DanglingEscape { pos: usize },
+    /// Matching would take more work than the budget in `MatchOptions` allows
+    OverBudget { cost: usize, budget: usize },
+    /// The same grapheme was given for both wildcards to `MatchOptions::wildcards`
+    SameWildcards { wildcard: char },
+    /// A wildcard given to `MatchOptions::wildcards` already means something else in the
+    /// pattern, like `[` or the separator
+    ReservedWildcard { wildcard: char },
+    /// The pattern has more wildcards than `WildcardPattern::new_limited` allows
+    TooManyWildcards { count: usize, limit: usize },
+    /// The pattern is empty, which `WildcardPattern::new_strict` doesn't allow
+    EmptyPattern,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatternError::UnterminatedClass { pos } => {
+                write!(f, "unterminated class starting at position {}", pos)
+            }
+            PatternError::DanglingEscape { pos } => {
+                write!(f, "dangling escape at position {}", pos)
+            }
+            PatternError::OverBudget { cost, budget } => {
+                write!(
+                    f,
+                    "matching would cost {} steps, over the budget of {}",
+                    cost, budget
+                )
+            }
+            PatternError::SameWildcards { wildcard } => {
+                write!(f, "{:?} can't be both of the wildcards", wildcard)
+            }
+            PatternError::ReservedWildcard { wildcard } => {
+                write!(
+                    f,
+                    "{:?} already means something in the pattern, so it can't be a wildcard",
+                    wildcard
+                )
+            }
+            PatternError::TooManyWildcards { count, limit } => {
+                write!(
+                    f,
+                    "the pattern has {} wildcards, more than the limit of {}",
+                    count, limit
+                )
+            }
+            PatternError::EmptyPattern => {
+                write!(f, "the pattern is empty, so it only matches an empty text")
+            }
+        }
+    }
+}
+
+impl Error for PatternError {}
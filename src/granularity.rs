@@ -0,0 +1,107 @@
+use std::str::CharIndices;
+use unicode_segmentation::{Graphemes, UnicodeSegmentation};
+
+/// What a pattern and a text are split in to, so what a `?` or a class matches one of.
+///
+/// A grapheme is what someone reading the text would call a single character, which can
+/// be made of several unicode scalar values, the code points that a `char` holds. For
+/// example the family emoji `👨‍👩‍👧` is three people joined by two zero width joiners, so it
+/// is one grapheme, five scalars, and eighteen bytes of UTF-8. `?` matches all of it by
+/// graphemes, `?????` by scalars, and eighteen question marks by bytes.
+///
+/// # Examples
+/// ```
+/// use pyglob::{is_wildcard_match_with, Granularity, MatchOptions};
+/// let family = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+/// let scalars = MatchOptions::new().granularity(Granularity::Scalars);
+/// assert_eq!(is_wildcard_match_with(family, "?", &MatchOptions::new()), true);
+/// assert_eq!(is_wildcard_match_with(family, "?????", &scalars), true);
+/// assert_eq!(is_wildcard_match_with(family, "\u{1f468}*", &scalars), true);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Granularity {
+    /// Extended grapheme clusters, as unicode segments text for people to read
+    #[default]
+    Graphemes,
+    /// Unicode scalar values, one `char` each
+    Scalars,
+    /// The bytes of the text encoded as UTF-8. A non-ASCII character in a class or a range
+    /// is a run of bytes as well, so those are best kept to ASCII.
+    Bytes,
+}
+
+impl Granularity {
+    /// What one of the units is called, in the singular
+    pub(crate) fn noun(self) -> &'static str {
+        match self {
+            Granularity::Graphemes => "grapheme",
+            Granularity::Scalars => "scalar",
+            Granularity::Bytes => "byte",
+        }
+    }
+
+    /// Split a text in to graphemes, or in to scalars if those are what's matched.
+    ///
+    /// A text matched by bytes is matched as its bytes rather than split, but a pattern
+    /// matched by bytes is also split in to scalars, so that it can be written back out.
+    pub(crate) fn split(self, text: &str) -> Units<'_> {
+        match self {
+            Granularity::Graphemes => Units::Graphemes(text.graphemes(true)),
+            Granularity::Scalars | Granularity::Bytes => Units::Scalars(text, text.char_indices()),
+        }
+    }
+}
+
+/// The pieces of a text that `Granularity::split` gives
+#[derive(Clone)]
+pub(crate) enum Units<'t> {
+    Graphemes(Graphemes<'t>),
+    Scalars(&'t str, CharIndices<'t>),
+}
+
+impl<'t> Iterator for Units<'t> {
+    type Item = &'t str;
+
+    fn next(&mut self) -> Option<&'t str> {
+        match self {
+            Units::Graphemes(graphemes) => graphemes.next(),
+            Units::Scalars(text, chars) => chars
+                .next()
+                .map(|(start, c)| &text[start..start + c.len_utf8()]),
+        }
+    }
+}
+
+impl<'t> DoubleEndedIterator for Units<'t> {
+    fn next_back(&mut self) -> Option<&'t str> {
+        match self {
+            Units::Graphemes(graphemes) => graphemes.next_back(),
+            Units::Scalars(text, chars) => chars
+                .next_back()
+                .map(|(start, c)| &text[start..start + c.len_utf8()]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_split_by_graphemes_or_scalars() {
+        let text = "ae\u{301}\r\n";
+        assert_eq!(
+            vec!["a", "e\u{301}", "\r\n"],
+            Granularity::Graphemes.split(text).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["a", "e", "\u{301}", "\r", "\n"],
+            Granularity::Scalars.split(text).collect::<Vec<_>>()
+        );
+        assert_eq!(0, Granularity::Scalars.split("").count());
+        assert_eq!(
+            vec!["\r\n", "e\u{301}", "a"],
+            Granularity::Graphemes.split(text).rev().collect::<Vec<_>>()
+        );
+    }
+}
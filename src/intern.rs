@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use crate::token::{Token, Unit};
+
+/// The tokens of a pattern with each distinct grapheme replaced by a number, so that the
+/// matcher compares numbers rather than strings.
+///
+/// The text is numbered the same way before matching. Most of the work of matching is
+/// comparing every grapheme of the pattern with many graphemes of the text, while each
+/// grapheme of the text only has to be looked up once.
+#[derive(Debug, Clone)]
+pub(crate) struct Interned {
+    ids: HashMap<String, u32>,
+    pub(crate) tokens: Vec<Token<u32>>,
+}
+
+/// A grapheme of the text, by its number, together with the scalar value that ranges and
+/// named classes compare if it's a single one
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Id {
+    id: u32,
+    scalar: Option<char>,
+}
+
+/// The number of every grapheme of the text which isn't in the pattern, so it can't be the
+/// same as any literal
+const UNKNOWN: u32 = u32::MAX;
+
+impl Interned {
+    pub(crate) fn new(tokens: &[Token]) -> Self {
+        let mut ids = HashMap::new();
+        let tokens = tokens
+            .iter()
+            .map(|token| {
+                token.map_units(&mut |grapheme: &String| {
+                    let next = ids.len() as u32;
+                    *ids.entry(grapheme.clone()).or_insert(next)
+                })
+            })
+            .collect();
+        Interned { ids, tokens }
+    }
+
+    /// Number the graphemes of a text the same way as those of the pattern
+    pub(crate) fn ids(&self, text: &[&str]) -> Vec<Id> {
+        text.iter()
+            .map(|grapheme| Id {
+                id: self.ids.get(*grapheme).copied().unwrap_or(UNKNOWN),
+                scalar: grapheme.scalar(),
+            })
+            .collect()
+    }
+}
+
+impl Unit for Id {
+    type Owned = u32;
+
+    fn to_owned_unit(&self) -> u32 {
+        self.id
+    }
+
+    fn is(&self, owned: &u32) -> bool {
+        self.id == *owned
+    }
+
+    fn scalar(&self) -> Option<char> {
+        self.scalar
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::{self, ClassItem};
+    use unicode_segmentation::UnicodeSegmentation;
+
+    #[test]
+    fn same_graphemes_get_the_same_number() {
+        let graphemes = "ae\u{301}[ae\u{301}]a"
+            .graphemes(true)
+            .collect::<Vec<&str>>();
+        let interned = Interned::new(&token::parse(&graphemes, true));
+        let class = Token::Class {
+            negated: false,
+            items: vec![ClassItem::Single(0), ClassItem::Single(1)],
+        };
+        assert_eq!(
+            vec![
+                Token::Literal(0),
+                Token::Literal(1),
+                class,
+                Token::Literal(0)
+            ],
+            interned.tokens
+        );
+
+        let ids = interned.ids(&["e\u{301}", "b", "a"]);
+        assert_eq!(
+            vec![1, UNKNOWN, 0],
+            ids.iter().map(Id::to_owned_unit).collect::<Vec<u32>>()
+        );
+        assert_eq!(Some('b'), ids[1].scalar());
+    }
+}
@@ -1,6 +1,33 @@
+mod casefold;
+mod error;
+mod granularity;
+mod intern;
+mod macros;
+mod matcher;
+mod normalization;
+mod options;
+mod pattern;
+mod posix;
+mod python;
+#[cfg(feature = "serde")]
+mod serialize;
+mod token;
+mod translate;
+mod walk;
 
-use pyo3::prelude::*;
-use std::collections::HashMap;
+pub use error::PatternError;
+pub use granularity::Granularity;
+#[doc(hidden)]
+pub use macros::__check_pattern;
+pub use normalization::Normalization;
+pub use options::MatchOptions;
+pub use pattern::WildcardPattern;
+pub use posix::PosixClass;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use token::Unit;
+pub use token::{ClassItem, Token};
 use unicode_segmentation::UnicodeSegmentation;
 
 /// Checks if a pattern containing wildcards matches a given string.
@@ -9,252 +36,3602 @@ use unicode_segmentation::UnicodeSegmentation;
 /// The valid wildcards are:
 /// `*`, which matches any number of characters, including none.
 /// `?`, which matches exactly one of any characters.
+/// `[abc]`, which matches exactly one of the characters inside the brackets.
+/// `[a-z]`, which matches exactly one character in the range, by unicode scalar value.
+/// `[!abc]`, which matches exactly one character that is not inside the brackets.
+/// `[[:digit:]]`, which matches exactly one character in a named class from POSIX.
+///
+/// The named classes are `alnum`, `alpha`, `blank`, `cntrl`, `digit`, `graph`, `lower`,
+/// `print`, `punct`, `space`, `upper` and `xdigit`, and can be mixed with other members,
+/// as in `[[:digit:]abc]`. They only match ASCII characters, as in the POSIX locale.
+///
+/// `^` can be used in place of `!`, but only directly after the `[` does either negate.
+/// A `-` at the start or end of a class is matched literally.
+/// An unterminated `[` is matched as a literal `[`, and an empty `[]` matches nothing.
+///
+/// A `|` splits the whole pattern in to alternatives, and the pattern matches if any one
+/// of them matches the whole text, so `foo|bar*` matches `foo` and `bartender`. Inside of
+/// brackets it's matched literally. See `MatchOptions::alternation` to turn this off.
+///
+/// A backslash escapes the character after it, so `\*` matches a literal `*`, and `\\`
+/// matches a literal backslash. A trailing backslash is matched literally. To treat
+/// backslashes as ordinary characters, see `is_wildcard_match_without_escapes`.
+///
+/// A character is an extended grapheme cluster, which is what a reader would see as one
+/// character. So `?` matches an `e` with a combining accent, a flag, an emoji with a skin
+/// tone, or a family emoji made of several joined by zero width joiners, as a whole.
+///
+/// The text comes first and the pattern second, the same as every other function in this
+/// crate that takes both.
+///
+/// An empty pattern matches only an empty text, and an empty text is matched only by a
+/// pattern that can match nothing, like `*`. Neither is an error. To reject empty patterns,
+/// which are often a mistake when patterns are built up from pieces, compile them with
+/// `WildcardPattern::new_strict`.
 ///
 /// # Arguments
 ///
-/// * `pattern` - The pattern to check for.
 /// * `text` - The text to check the pattern on.
+/// * `pattern` - The pattern to check for.
 ///
 /// # Examples
 /// ```
 /// use pyglob::is_wildcard_match;
-/// let does_match = is_wildcard_match("aplbq", "a*b?");
-/// assert_eq!(does_match, true);
-/// let doesnt_match = is_wildcard_match("abc", "a*b");
-/// assert_eq!(doesnt_match, false);
+/// let (text, pattern) = ("aplbq", "a*b?");
+/// assert_eq!(is_wildcard_match(text, pattern), true);
+/// let (text, pattern) = ("abc", "a*b");
+/// assert_eq!(is_wildcard_match(text, pattern), false);
 /// ```
-#[pyfunction]
 pub fn is_wildcard_match(text: &str, pattern: &str) -> bool {
-    // Convert the pattern and text in to vectors of graphemes
-    let pattern_graphemes = pattern.graphemes(true).collect::<Vec<&str>>();
-    let text_graphemes = text.graphemes(true).collect::<Vec<&str>>();
+    WildcardPattern::new(pattern).matches(text)
+}
+
+/// Checks if a pattern containing wildcards matches anywhere in a given string.
+///
+/// `is_wildcard_match` only matches if the pattern matches all of the text, while this
+/// matches if the pattern matches any part of it, including an empty part, so an empty
+/// pattern matches every text. It is the same as putting a `*` at each end of the pattern,
+/// except that a trailing backslash in the pattern stays a literal backslash rather than
+/// escaping the added star.
+///
+/// # Arguments
+///
+/// * `text` - The text to search in.
+/// * `pattern` - The pattern to search for.
+///
+/// # Examples
+/// ```
+/// use pyglob::{is_wildcard_match, is_wildcard_search};
+/// assert_eq!(is_wildcard_search("abcdef", "c?e"), true);
+/// assert_eq!(is_wildcard_match("abcdef", "c?e"), false);
+/// ```
+pub fn is_wildcard_search(text: &str, pattern: &str) -> bool {
+    WildcardPattern::new(pattern).unanchored().matches(text)
+}
+
+/// Finds where in a string a pattern containing wildcards matches, as the byte offsets of
+/// the start and the end of the part it matches.
+///
+/// Where `is_wildcard_search` only says whether the pattern matches some part of the text,
+/// this says which. When several parts match, the one that starts leftmost is found, and
+/// of those that start there, the longest. The part can be empty, so an empty pattern is
+/// found at the start of any text. See `WildcardPattern::find`.
+///
+/// # Arguments
+///
+/// * `text` - The text to search in.
+/// * `pattern` - The pattern to search for.
+///
+/// # Examples
+/// ```
+/// use pyglob::find;
+/// assert_eq!(find("a main.rs file", "m*.rs"), Some((2, 9)));
+/// assert_eq!(find("a main.rs file", "*.py"), None);
+/// ```
+pub fn find(text: &str, pattern: &str) -> Option<(usize, usize)> {
+    WildcardPattern::new(pattern).find(text)
+}
+
+/// Checks if a pattern containing wildcards matches a given string, with the given options.
+///
+/// With the default options, this is the same as `is_wildcard_match`.
+///
+/// # Arguments
+///
+/// * `text` - The text to check the pattern on.
+/// * `pattern` - The pattern to check for.
+/// * `options` - How the pattern should be matched.
+///
+/// # Examples
+/// ```
+/// use pyglob::{is_wildcard_match_with, MatchOptions, Normalization};
+/// let options = MatchOptions::new().normalization(Normalization::Nfc).case_insensitive(true);
+/// assert_eq!(is_wildcard_match_with("CAFE\u{301}", "caf\u{e9}", &options), true);
+/// ```
+pub fn is_wildcard_match_with(text: &str, pattern: &str, options: &MatchOptions) -> bool {
+    WildcardPattern::with_options(pattern, options).matches(text)
+}
+
+/// Checks if a pattern containing wildcards matches a given string, with the given options,
+/// unless that would take more work than the budget in the options allows.
+///
+/// The work is counted as the number of tokens in the pattern times the number of
+/// graphemes in the text, where a run of stars counts as one token. It is checked before
+/// matching, so a pattern and text which are too big together are rejected straight away
+/// instead of tying up the caller. The budget is `MatchOptions::DEFAULT_BUDGET` unless it's
+/// changed with `MatchOptions::budget`, and `budget(None)` turns it off.
+///
+/// # Arguments
+///
+/// * `text` - The text to check the pattern on.
+/// * `pattern` - The pattern to check for.
+/// * `options` - How the pattern should be matched, including the budget.
+///
+/// # Examples
+/// ```
+/// use pyglob::{try_match, MatchOptions, PatternError};
+/// let options = MatchOptions::new().budget(Some(100));
+/// assert_eq!(try_match("main.rs", "*.rs", &options), Ok(true));
+/// let text = "a".repeat(100);
+/// assert_eq!(
+///     try_match(&text, "*a", &options),
+///     Err(PatternError::OverBudget { cost: 200, budget: 100 })
+/// );
+/// assert_eq!(try_match(&text, "*a", &options.budget(None)), Ok(true));
+/// ```
+pub fn try_match(text: &str, pattern: &str, options: &MatchOptions) -> Result<bool, PatternError> {
+    WildcardPattern::with_options(pattern, options).try_matches(text)
+}
+
+/// Checks if a pattern containing wildcards matches a given string, without treating
+/// backslashes as escapes.
+///
+/// This is how `is_wildcard_match` behaved before escaping was supported, and is useful
+/// for patterns where a backslash is a path separator.
+///
+/// # Examples
+/// ```
+/// use pyglob::is_wildcard_match_without_escapes;
+/// assert_eq!(is_wildcard_match_without_escapes("dir\\file", "dir\\*"), true);
+/// ```
+pub fn is_wildcard_match_without_escapes(text: &str, pattern: &str) -> bool {
+    WildcardPattern::without_escapes(pattern).matches(text)
+}
+
+/// Checks if a pattern containing wildcards matches a given string, ignoring case.
+///
+/// The wildcards are the same as for `is_wildcard_match`, and behave the same way. Both
+/// the pattern and the text are lowercased using the unicode lowercase mapping before
+/// matching. This is simple lowercasing rather than full case folding, so for example
+/// `STRASSE` does not match `straße`, while with `is_wildcard_match_casefold` it does.
+///
+/// # Examples
+/// ```
+/// use pyglob::is_wildcard_match_case_insensitive;
+/// assert_eq!(is_wildcard_match_case_insensitive("Hello.TXT", "hello.txt"), true);
+/// ```
+pub fn is_wildcard_match_case_insensitive(text: &str, pattern: &str) -> bool {
+    WildcardPattern::case_insensitive(pattern).matches(text)
+}
+
+/// Checks if a pattern containing wildcards matches a given string, ignoring case with
+/// full unicode case folding.
+///
+/// Both the pattern and the text are folded the same way as Python's `str.casefold`, which
+/// is lowercasing except that some characters fold to several, like `ß` to `ss`, and the
+/// final sigma `ς` folds to `σ`. A character that folds to several is also several
+/// characters for `?` and classes, so `stra?e` doesn't match `STRASSE`, and neither does
+/// `stra[ß]e`. The folding is the same for every language, so a Turkish dotted `İ` is not
+/// folded to a plain `i`. See `MatchOptions::case_folding`.
+///
+/// # Examples
+/// ```
+/// use pyglob::is_wildcard_match_casefold;
+/// assert_eq!(is_wildcard_match_casefold("STRASSE", "straße"), true);
+/// assert_eq!(is_wildcard_match_casefold("Straße.TXT", "strasse.*"), true);
+/// ```
+pub fn is_wildcard_match_casefold(text: &str, pattern: &str) -> bool {
+    let options = MatchOptions::new().case_folding(true);
+    WildcardPattern::with_options(pattern, &options).matches(text)
+}
+
+/// Checks if a pattern containing wildcards matches a given string with at most
+/// `max_edits` graphemes of the text substituted, left out or added.
+///
+/// Stars match what they can without any edits, so only what the rest of the pattern needs
+/// is edited. See `WildcardPattern::matches_within_distance`.
+///
+/// # Examples
+/// ```
+/// use pyglob::is_wildcard_match_approx;
+/// assert_eq!(is_wildcard_match_approx("color", "colour", 1), true);
+/// assert_eq!(is_wildcard_match_approx("colr", "colour", 1), false);
+/// assert_eq!(is_wildcard_match_approx("recieve.txt", "receive.*", 2), true);
+/// ```
+pub fn is_wildcard_match_approx(text: &str, pattern: &str, max_edits: usize) -> bool {
+    WildcardPattern::new(pattern).matches_within_distance(text, max_edits)
+}
+
+/// Checks if a pattern containing wildcards matches a given string, treating the different
+/// ways of writing the same character as equal.
+///
+/// Both the pattern and the text are converted to unicode normalization form C before
+/// matching, so a character written as a single code point matches the same character
+/// written with combining marks. Normalizing uses the `unicode-normalization` crate, and
+/// costs a copy of any text that isn't in form C already.
+///
+/// # Examples
+/// ```
+/// use pyglob::is_wildcard_match_normalized;
+/// assert_eq!(is_wildcard_match_normalized("e\u{301}", "\u{e9}"), true);
+/// ```
+pub fn is_wildcard_match_normalized(text: &str, pattern: &str) -> bool {
+    WildcardPattern::normalized(pattern, Normalization::Nfc).matches(text)
+}
+
+/// Checks if a pattern containing wildcards matches a path, where `*` doesn't cross the
+/// separator but `**` does.
+///
+/// `*`, `?` and classes never match the separator. A `**` which makes up a whole segment
+/// of the pattern matches any number of segments, including none. See
+/// `WildcardPattern::path` for the details.
+///
+/// # Arguments
+///
+/// * `text` - The path to check the pattern on.
+/// * `pattern` - The pattern to check for.
+/// * `separator` - The character that separates the segments of the path, such as `/`.
+///
+/// # Examples
+/// ```
+/// use pyglob::is_wildcard_match_path;
+/// assert_eq!(is_wildcard_match_path("src/a/b.rs", "src/*.rs", '/'), false);
+/// assert_eq!(is_wildcard_match_path("src/a/b.rs", "src/**/*.rs", '/'), true);
+/// ```
+pub fn is_wildcard_match_path(text: &str, pattern: &str, separator: char) -> bool {
+    WildcardPattern::path(pattern, separator).matches(text)
+}
+
+/// Checks if a pattern containing wildcards matches a filesystem path, which doesn't have
+/// to be valid unicode.
+///
+/// This saves converting the path to a string first, which can fail. A path which is
+/// valid unicode is matched the same way as by `is_wildcard_match`. On Unix, a path which
+/// isn't is matched byte by byte when the pattern is plain ASCII, and on other platforms
+/// the invalid parts are replaced with `U+FFFD`. See `WildcardPattern::matches_path` for
+/// the details. The path is matched as a whole, so `*` matches across its separators, the
+/// same as for a string; use a compiled `WildcardPattern::path` to match segment by
+/// segment.
+///
+/// # Arguments
+///
+/// * `path` - The path to check the pattern on.
+/// * `pattern` - The pattern to check for.
+///
+/// # Examples
+/// ```
+/// use std::path::Path;
+/// use pyglob::is_wildcard_match_os_path;
+/// assert_eq!(is_wildcard_match_os_path(Path::new("src/main.rs"), "src/*.rs"), true);
+/// ```
+pub fn is_wildcard_match_os_path(path: &Path, pattern: &str) -> bool {
+    WildcardPattern::new(pattern).matches_path(path)
+}
+
+/// Walks a directory tree, yielding every file and directory whose path relative to the
+/// root matches a path pattern.
+///
+/// The relative paths are matched with `/` as the separator, whatever the platform, the
+/// same as by `is_wildcard_match_path`, so `*` stays within one directory and a `**`
+/// segment matches any number of them. The paths that are yielded start with the root.
+///
+/// The tree is walked depth first, with the entries of each directory in order of their
+/// names, and a directory is yielded before what's inside of it. Symbolic links are yielded
+/// if they match, but not followed. Directories which can't be read are skipped, and names
+/// which aren't valid unicode are matched with the invalid parts replaced by `U+FFFD`.
+///
+/// # Arguments
+///
+/// * `root` - The directory to walk.
+/// * `pattern` - The pattern the relative paths have to match.
+///
+/// # Examples
+/// ```no_run
+/// use std::path::Path;
+/// use pyglob::glob;
+/// for path in glob(Path::new("."), "src/**/*.rs") {
+///     println!("{}", path.display());
+/// }
+/// ```
+pub fn glob(root: &Path, pattern: &str) -> impl Iterator<Item = PathBuf> {
+    walk::Walk::new(root, pattern)
+}
+
+/// Checks if a pattern containing wildcards matches a given string, where `{a,b}` matches
+/// either `a` or `b`.
+///
+/// A group in braces holds alternatives separated by commas, and matches if any one of
+/// them matches at that point, so `image.{jpg,png}` matches both `image.jpg` and
+/// `image.png`. The alternatives can contain wildcards and more groups, like
+/// `{*.rs,src/{a,b}}`, and can be empty, so `file{,.bak}` matches `file` and `file.bak`.
+/// A `{` without a matching `}` is matched literally, as are `}` and `,` outside of a
+/// group, and a backslash escapes any of them.
+///
+/// The groups are matched in place rather than expanded in to one pattern per combination
+/// of alternatives, so a pattern with many groups doesn't take exponentially longer to
+/// compile or match. Each alternative costs as much as the same tokens would outside of
+/// braces.
+///
+/// # Arguments
+///
+/// * `text` - The text to check the pattern on.
+/// * `pattern` - The pattern to check for.
+///
+/// # Examples
+/// ```
+/// use pyglob::is_wildcard_match_braces;
+/// assert_eq!(is_wildcard_match_braces("image.png", "image.{jpg,png,gif}"), true);
+/// assert_eq!(is_wildcard_match_braces("image.bmp", "image.{jpg,png,gif}"), false);
+/// ```
+pub fn is_wildcard_match_braces(text: &str, pattern: &str) -> bool {
+    WildcardPattern::with_options(pattern, &MatchOptions::new().braces(true)).matches(text)
+}
+
+/// Checks if a pattern matches a given string, treating every grapheme of the pattern as
+/// a literal.
+///
+/// `*`, `?`, `[`, `{` and backslashes are all ordinary graphemes, so this is the same as
+/// comparing the two strings grapheme by grapheme. It's useful for a pattern that should
+/// be compared verbatim, without having to `escape` it first. Combine
+/// `MatchOptions::literal` with the other options to, for example, also ignore case.
+///
+/// # Arguments
+///
+/// * `text` - The text to check the pattern on.
+/// * `pattern` - The pattern to check for, which has no wildcards.
+///
+/// # Examples
+/// ```
+/// use pyglob::literal_match;
+/// assert_eq!(literal_match("a*b", "a*b"), true);
+/// assert_eq!(literal_match("axb", "a*b"), false);
+/// ```
+pub fn literal_match(text: &str, pattern: &str) -> bool {
+    WildcardPattern::with_options(pattern, &MatchOptions::new().literal(true)).matches(text)
+}
+
+/// Checks if a pattern matches a given string the same way as Python's
+/// `fnmatch.fnmatchcase`, for code moving over from it.
+///
+/// This parses classes the way `fnmatch` does:
+/// * A backslash is an ordinary character, both in and out of classes.
+/// * Only `!` negates a class, and `[^a]` matches `^` or `a`.
+/// * A `]` right after the `[` or `[!` is a member of the class, so `[]a]` matches `]` or
+///   `a`, and `[]` and `[!]` aren't classes at all but literals.
+/// * There are no named classes, so `[[:digit:]]` is the class of `[`, `:`, `d`, `i`, `g`
+///   and `t`, followed by a literal `]`.
+///
+/// The one difference that remains is that `?` and each class match a grapheme, where
+/// `fnmatch` matches a single code point, so an `e` followed by a combining accent matches
+/// `?` here but `??` in `fnmatch`. Normalizing the text and the pattern to NFC avoids this
+/// for most accented letters. Like `fnmatchcase`, this doesn't ignore case. `fnmatch.fnmatch`
+/// also lowercases on Windows, which `MatchOptions::fnmatch` together with
+/// `MatchOptions::case_insensitive` does.
+///
+/// # Arguments
+///
+/// * `text` - The text to check the pattern on.
+/// * `pattern` - The pattern to check for.
+///
+/// # Examples
+/// ```
+/// use pyglob::is_wildcard_match_fnmatch;
+/// assert_eq!(is_wildcard_match_fnmatch("]", "[]a]"), true);
+/// assert_eq!(is_wildcard_match_fnmatch("a\\b", "a\\b"), true);
+/// assert_eq!(is_wildcard_match_fnmatch("^", "[^a]"), true);
+/// ```
+pub fn is_wildcard_match_fnmatch(text: &str, pattern: &str) -> bool {
+    WildcardPattern::with_options(pattern, &MatchOptions::new().fnmatch(true)).matches(text)
+}
+
+/// Checks if a pattern containing wildcards matches a given string of bytes.
+///
+/// This works on bytes rather than graphemes, so the text doesn't have to be valid UTF-8.
+/// `?` matches exactly one byte, `*` matches any number of bytes, and a class matches
+/// exactly one byte, with ranges comparing byte values. Otherwise the syntax is the same
+/// as for `is_wildcard_match`, including backslash escapes.
+///
+/// Since a character outside of ASCII is several bytes long in UTF-8, `?` does not match
+/// a single `é`, and `[é]` matches either of its two bytes on their own.
+///
+/// # Arguments
+///
+/// * `text` - The bytes to check the pattern on.
+/// * `pattern` - The pattern to check for.
+///
+/// # Examples
+/// ```
+/// use pyglob::is_wildcard_match_bytes;
+/// assert_eq!(is_wildcard_match_bytes(b"file\xff.txt", b"file?.txt"), true);
+/// ```
+pub fn is_wildcard_match_bytes(text: &[u8], pattern: &[u8]) -> bool {
+    let tokens = remove_duplicate_stars(token::parse(pattern, true));
+    let greedy = matcher::is_greedy_matchable(&tokens);
+    match_tokens(&tokens, text, greedy)
+}
+
+/// Returns the text captured by each `*` in the pattern, if the pattern matches the text.
+///
+/// Each star captures the substring it matched, which is empty if it matched nothing. If
+/// there are several ways for the text to match, stars are greedy and take as much of the
+/// text as they can from left to right, so `*-*` captures `a-b` and `c` from `a-b-c`.
+/// A run of consecutive stars counts as a single star.
+///
+/// # Arguments
+///
+/// * `text` - The text to check the pattern on.
+/// * `pattern` - The pattern to check for.
+///
+/// # Examples
+/// ```
+/// use pyglob::captures;
+/// assert_eq!(captures("src/main.rs", "src/*.rs"), Some(vec![String::from("main")]));
+/// assert_eq!(captures("src/main.py", "src/*.rs"), None);
+/// ```
+pub fn captures(text: &str, pattern: &str) -> Option<Vec<String>> {
+    WildcardPattern::new(pattern).captures(text)
+}
+
+/// Returns the names which match the pattern.
+///
+/// The pattern is only compiled once, rather than once per name.
+///
+/// # Arguments
+///
+/// * `names` - The strings to filter.
+/// * `pattern` - The pattern the strings have to match.
+///
+/// # Examples
+/// ```
+/// use pyglob::filter;
+/// assert_eq!(filter(&["a.rs", "b.py", "c.rs"], "*.rs"), vec!["a.rs", "c.rs"]);
+/// ```
+pub fn filter<'a>(names: &[&'a str], pattern: &str) -> Vec<&'a str> {
+    let pattern = WildcardPattern::new(pattern);
+    names
+        .iter()
+        .copied()
+        .filter(|name| pattern.matches(name))
+        .collect()
+}
+
+/// Returns the names which match the pattern, checking them on several threads at once.
+///
+/// This gives the same result as `filter`, in the same order, but splits the names between
+/// the threads of rayon's global thread pool. The pattern is compiled once and shared by
+/// every thread, which is safe since matching never changes a compiled pattern. It's only
+/// worth it for a large number of names, since sharing out the work has a cost of its own.
+///
+/// Needs the `rayon` feature.
+///
+/// # Arguments
+///
+/// * `names` - The strings to filter.
+/// * `pattern` - The pattern the strings have to match.
+///
+/// # Examples
+/// ```
+/// use pyglob::par_filter;
+/// assert_eq!(par_filter(&["a.rs", "b.py", "c.rs"], "*.rs"), vec!["a.rs", "c.rs"]);
+/// ```
+#[cfg(feature = "rayon")]
+pub fn par_filter<'a>(names: &[&'a str], pattern: &str) -> Vec<&'a str> {
+    use rayon::prelude::*;
+
+    let pattern = WildcardPattern::new(pattern);
+    names
+        .par_iter()
+        .copied()
+        .filter(|name| pattern.matches(name))
+        .collect()
+}
+
+/// Splits the names in to those which match the pattern and those which don't.
+///
+/// The pattern is only compiled once, and the names are only gone through once. Both lists
+/// keep the names in the order they were given in.
+///
+/// # Arguments
+///
+/// * `names` - The strings to split.
+/// * `pattern` - The pattern the strings are checked on.
+///
+/// # Examples
+/// ```
+/// use pyglob::partition;
+/// let (matching, rest) = partition(&["a.rs", "b.py", "c.rs"], "*.rs");
+/// assert_eq!(matching, vec!["a.rs", "c.rs"]);
+/// assert_eq!(rest, vec!["b.py"]);
+/// ```
+pub fn partition<'a>(names: &[&'a str], pattern: &str) -> (Vec<&'a str>, Vec<&'a str>) {
+    let pattern = WildcardPattern::new(pattern);
+    names.iter().partition(|name| pattern.matches(name))
+}
+
+/// Returns the positions of the names which match the pattern.
+///
+/// This is the same as `filter`, except that it returns where the matching names are in
+/// the list rather than the names themselves, so that they can be looked up in other lists
+/// that line up with it. The positions are in increasing order.
+///
+/// # Arguments
+///
+/// * `names` - The strings to filter.
+/// * `pattern` - The pattern the strings have to match.
+///
+/// # Examples
+/// ```
+/// use pyglob::filter_indices;
+/// assert_eq!(filter_indices(&["a.rs", "b.py", "c.rs"], "*.rs"), vec![0, 2]);
+/// ```
+pub fn filter_indices(names: &[&str], pattern: &str) -> Vec<usize> {
+    let pattern = WildcardPattern::new(pattern);
+    names
+        .iter()
+        .enumerate()
+        .filter_map(|(i, name)| pattern.matches(name).then_some(i))
+        .collect()
+}
+
+/// Checks each of the texts against the pattern, returning whether each one matched in the
+/// same order as the texts.
+///
+/// Unlike `filter` and `filter_indices`, this keeps a result for every text, so it can be
+/// lined up with anything else kept per text. The pattern is only compiled once.
+///
+/// # Arguments
+///
+/// * `texts` - The strings to check the pattern on.
+/// * `pattern` - The pattern the strings have to match.
+///
+/// # Examples
+/// ```
+/// use pyglob::matches_all_texts;
+/// assert_eq!(matches_all_texts(&["a.rs", "b.py", "c.rs"], "*.rs"), vec![true, false, true]);
+/// ```
+pub fn matches_all_texts(texts: &[&str], pattern: &str) -> Vec<bool> {
+    let pattern = WildcardPattern::new(pattern);
+    texts.iter().map(|text| pattern.matches(text)).collect()
+}
+
+/// Counts how many of the texts match the pattern.
+///
+/// The pattern is only compiled once, and nothing is collected, so this is cheaper than
+/// taking the length of what `filter` returns.
+///
+/// # Arguments
+///
+/// * `texts` - The strings to check the pattern on.
+/// * `pattern` - The pattern the strings have to match.
+///
+/// # Examples
+/// ```
+/// use pyglob::count_matches;
+/// assert_eq!(count_matches(&["a.rs", "b.py", "c.rs"], "*.rs"), 2);
+/// ```
+pub fn count_matches(texts: &[&str], pattern: &str) -> usize {
+    let pattern = WildcardPattern::new(pattern);
+    texts.iter().filter(|text| pattern.matches(text)).count()
+}
+
+/// Checks if the text matches at least one of the patterns.
+///
+/// The patterns are compiled and checked in order, and this stops as soon as one of them
+/// matches, so the patterns after it are never compiled. An empty list of patterns never
+/// matches.
+///
+/// # Arguments
+///
+/// * `text` - The text to check the patterns on.
+/// * `patterns` - The patterns to check for.
+///
+/// # Examples
+/// ```
+/// use pyglob::match_any;
+/// assert_eq!(match_any("main.rs", &["*.py", "*.rs"]), true);
+/// assert_eq!(match_any("main.rs", &[]), false);
+/// ```
+pub fn match_any(text: &str, patterns: &[&str]) -> bool {
+    first_match(text, patterns).is_some()
+}
+
+/// Returns the index of the first pattern that matches the text.
+///
+/// This is useful when the patterns are rules in order of priority. The patterns are
+/// compiled and checked in order, and this stops at the first one that matches. Returns
+/// `None` if no pattern matches, including when there are no patterns.
+///
+/// # Arguments
+///
+/// * `text` - The text to check the patterns on.
+/// * `patterns` - The patterns to check for, in order of priority.
+///
+/// # Examples
+/// ```
+/// use pyglob::first_match;
+/// assert_eq!(first_match("main.rs", &["*.py", "main.*", "*.rs"]), Some(1));
+/// assert_eq!(first_match("main.c", &["*.py", "*.rs"]), None);
+/// ```
+pub fn first_match(text: &str, patterns: &[&str]) -> Option<usize> {
+    patterns
+        .iter()
+        .position(|pattern| WildcardPattern::new(pattern).matches(text))
+}
+
+/// Returns every pattern that matches the text, in the order they were given.
+///
+/// This complements `first_match` for rule engines where every applicable rule counts,
+/// rather than only the one with the highest priority. Every pattern is compiled and
+/// checked, even once one of them has matched.
+///
+/// # Arguments
+///
+/// * `text` - The text to check the patterns on.
+/// * `patterns` - The patterns to check for.
+///
+/// # Examples
+/// ```
+/// use pyglob::matching_patterns;
+/// let patterns = ["*.py", "main.*", "*.rs"];
+/// assert_eq!(matching_patterns("main.rs", &patterns), vec!["main.*", "*.rs"]);
+/// assert_eq!(matching_patterns("lib.c", &patterns), Vec::<&str>::new());
+/// ```
+pub fn matching_patterns<'a>(text: &str, patterns: &[&'a str]) -> Vec<&'a str> {
+    patterns
+        .iter()
+        .copied()
+        .filter(|pattern| WildcardPattern::new(pattern).matches(text))
+        .collect()
+}
+
+/// Checks the text against each of the patterns, returning whether each one matched in
+/// the same order as the patterns.
+///
+/// Unlike `matching_patterns`, this keeps a result for every pattern, so it can be lined
+/// up with anything else kept per pattern.
+///
+/// # Arguments
+///
+/// * `text` - The text to check the patterns on.
+/// * `patterns` - The patterns to check for.
+///
+/// # Examples
+/// ```
+/// use pyglob::match_each;
+/// assert_eq!(match_each("main.rs", &["*.py", "main.*", "*.rs"]), vec![false, true, true]);
+/// ```
+pub fn match_each(text: &str, patterns: &[&str]) -> Vec<bool> {
+    patterns
+        .iter()
+        .map(|pattern| WildcardPattern::new(pattern).matches(text))
+        .collect()
+}
+
+/// Checks if the text matches every one of the patterns.
+///
+/// The patterns are compiled and checked in order, and this stops as soon as one of them
+/// doesn't match. An empty list of patterns always matches, since there is no pattern
+/// that the text fails to match.
+///
+/// # Arguments
+///
+/// * `text` - The text to check the patterns on.
+/// * `patterns` - The patterns to check for.
+///
+/// # Examples
+/// ```
+/// use pyglob::match_all;
+/// assert_eq!(match_all("src/main.rs", &["src/*", "*.rs"]), true);
+/// assert_eq!(match_all("src/main.py", &["src/*", "*.rs"]), false);
+/// assert_eq!(match_all("src/main.py", &[]), true);
+/// ```
+pub fn match_all(text: &str, patterns: &[&str]) -> bool {
+    patterns
+        .iter()
+        .all(|pattern| WildcardPattern::new(pattern).matches(text))
+}
+
+/// Checks if the text matches at least one pattern out of each of the groups.
+///
+/// This puts together `match_any` for each group with `match_all` across them, for rules
+/// like "a source file in either `src` or `lib`". A group without any patterns never
+/// matches, so neither does anything with one, while no groups at all always match.
+///
+/// # Arguments
+///
+/// * `text` - The text to check the patterns on.
+/// * `groups` - The groups of patterns, each of which needs one pattern to match.
+///
+/// # Examples
+/// ```
+/// use pyglob::matches_at_least_one_of_each;
+/// let rule: &[&[&str]] = &[&["*.rs", "*.py"], &["src/*", "lib/*"]];
+/// assert_eq!(matches_at_least_one_of_each("lib/util.py", rule), true);
+/// assert_eq!(matches_at_least_one_of_each("docs/index.rs", rule), false);
+/// ```
+pub fn matches_at_least_one_of_each(text: &str, groups: &[&[&str]]) -> bool {
+    groups.iter().all(|group| match_any(text, group))
+}
+
+/// Checks if the text matches at least one of the include patterns and none of the exclude
+/// patterns, so an exclude always wins over an include.
+///
+/// Without any include patterns everything is included, so that only excludes can be
+/// given, and without any exclude patterns nothing is excluded. Like `match_any`, each
+/// pattern is compiled at most once, and only until the answer is known.
+///
+/// # Arguments
+///
+/// * `text` - The text to check the patterns on.
+/// * `includes` - The patterns of which the text has to match one.
+/// * `excludes` - The patterns which the text can't match any of.
+///
+/// # Examples
+/// ```
+/// use pyglob::matches_filtered;
+/// assert_eq!(matches_filtered("src/main.rs", &["*.rs"], &["*/test_*"]), true);
+/// assert_eq!(matches_filtered("src/test_main.rs", &["*.rs"], &["*/test_*"]), false);
+/// assert_eq!(matches_filtered("README.md", &[], &["*.rs"]), true);
+/// ```
+pub fn matches_filtered(text: &str, includes: &[&str], excludes: &[&str]) -> bool {
+    (includes.is_empty() || match_any(text, includes)) && !match_any(text, excludes)
+}
+
+/// Removes the patterns which compile to the same thing as an earlier one, returning the
+/// canonical strings of the rest, in the order they were first seen.
+///
+/// Patterns are compared by `WildcardPattern::canonical_string`, so runs of stars count as
+/// one star, `*?` is the same as `?*`, and needless escapes are dropped, but patterns that
+/// are only written differently, like `[ab]` and `[ba]`, are both kept.
+///
+/// # Arguments
+///
+/// * `patterns` - The patterns to remove the duplicates from.
+///
+/// # Examples
+/// ```
+/// use pyglob::dedup_patterns;
+/// assert_eq!(dedup_patterns(&["*.rs", "**.rs", "\\x", "x"]), vec!["*.rs", "x"]);
+/// ```
+pub fn dedup_patterns(patterns: &[&str]) -> Vec<String> {
+    let mut seen = HashSet::with_capacity(patterns.len());
+    patterns
+        .iter()
+        .map(|pattern| WildcardPattern::new(pattern).canonical_string())
+        .filter(|canonical| seen.insert(canonical.clone()))
+        .collect()
+}
+
+/// Escapes a string, so that it can be used as a pattern which only matches the string
+/// itself.
+///
+/// Every `*`, `?`, `[`, `|` and `\\` gets a backslash in front of it. The result only
+/// matches literally when escapes are on and braces are off, which they are by default.
+///
+/// # Arguments
+///
+/// * `literal` - The text to escape.
+///
+/// # Examples
+/// ```
+/// use pyglob::{escape, is_wildcard_match};
+/// assert_eq!(escape("what?[1].txt"), "what\\?\\[1].txt");
+/// assert_eq!(is_wildcard_match("whatever[1].txt", &escape("what?[1].txt")), false);
+/// ```
+pub fn escape(literal: &str) -> String {
+    let mut pattern = String::with_capacity(literal.len());
+    for c in literal.chars() {
+        if matches!(c, '*' | '?' | '[' | '|' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern
+}
+
+/// Removes the escapes from a pattern, recovering the text that `escape` was given.
+///
+/// Each backslash is dropped, and the character after it is kept as it is, so
+/// `unescape(&escape(text))` is always `text`. Wildcards which aren't escaped are left
+/// in the result unchanged, rather than being an error, so that a pattern which isn't
+/// entirely literal still gives something readable. A trailing backslash has nothing to
+/// escape, and is kept, the same as when the pattern is matched.
+///
+/// # Arguments
+///
+/// * `pattern` - The pattern to unescape.
+///
+/// # Examples
+/// ```
+/// use pyglob::{escape, unescape};
+/// assert_eq!(unescape("what\\?\\[1].txt"), "what?[1].txt");
+/// assert_eq!(unescape(&escape("a*b")), "a*b");
+/// assert_eq!(unescape("*.rs"), "*.rs");
+/// ```
+pub fn unescape(pattern: &str) -> String {
+    let mut literal = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => literal.push(chars.next().unwrap_or('\\')),
+            c => literal.push(c),
+        }
+    }
+    literal
+}
+
+/// Translates a pattern in to a regular expression for the `regex` crate.
+///
+/// This is an escape hatch for when a regular expression is needed rather than a pattern,
+/// for example to combine it with others in a `RegexSet`. `*` becomes `.*`, `?` becomes
+/// `.`, classes become regular expression classes, and every other character is escaped.
+/// The result is anchored with `^` and `$`, so it only matches the whole of a text.
+///
+/// Note that `?` matches a whole grapheme, while `.` in a regular expression only matches
+/// a single code point, so the two can disagree on text with combining characters.
+///
+/// # Arguments
+///
+/// * `pattern` - The pattern to translate.
+///
+/// # Examples
+/// ```
+/// use pyglob::translate_to_regex;
+/// assert_eq!(translate_to_regex("*.rs"), "^(?s:.*\\.rs)$");
+/// ```
+pub fn translate_to_regex(pattern: &str) -> String {
+    translate::to_rust_regex(WildcardPattern::new(pattern).tokens())
+}
+
+/// Checks that a pattern is well formed.
+///
+/// Matching never fails, since an unterminated `[` and a trailing `\\` are matched as
+/// literals. This instead reports them as errors, with the position they're at, so that
+/// mistakes in a pattern can be pointed out to whoever wrote it.
+///
+/// # Arguments
+///
+/// * `pattern` - The pattern to check.
+///
+/// # Examples
+/// ```
+/// use pyglob::{validate, PatternError};
+/// assert_eq!(validate("[a-z]*"), Ok(()));
+/// assert_eq!(validate("[a-z*"), Err(PatternError::UnterminatedClass { pos: 0 }));
+/// assert_eq!(validate("a*\\"), Err(PatternError::DanglingEscape { pos: 2 }));
+/// ```
+pub fn validate(pattern: &str) -> Result<(), PatternError> {
+    let graphemes = pattern.graphemes(true).collect::<Vec<&str>>();
+    token::validate(&graphemes, true)
+}
+
+/// Splits a pattern in to the tokens that make it up, the same way `WildcardPattern::new`
+/// does, for looking at or changing a pattern a token at a time.
+///
+/// A pattern with an unterminated class or a dangling escape is rejected with the error
+/// `validate` would give. Runs of stars are parsed as a single star, and an escaped
+/// wildcard is a literal. For the tokens of a pattern compiled with other options, like
+/// braces or path mode, see `WildcardPattern::tokens`.
+///
+/// # Arguments
+///
+/// * `pattern` - The pattern to parse.
+///
+/// # Examples
+/// ```
+/// use pyglob::{parse, ClassItem, PatternError, Token};
+/// assert_eq!(
+///     parse("x[!0-9]").unwrap(),
+///     vec![
+///         Token::Literal(String::from("x")),
+///         Token::Class { negated: true, items: vec![ClassItem::Range('0', '9')] },
+///     ]
+/// );
+/// assert_eq!(parse("a[b"), Err(PatternError::UnterminatedClass { pos: 1 }));
+/// ```
+pub fn parse(pattern: &str) -> Result<Vec<Token>, PatternError> {
+    validate(pattern)?;
+    Ok(WildcardPattern::new(pattern).tokens().to_vec())
+}
+
+/// Writes tokens back out as a pattern, which `parse` turns in to the same tokens again,
+/// except that a class which excludes nothing, `[!]`, is written as the `?` it's the same
+/// as.
+///
+/// Literals which would otherwise be wildcards are escaped, so `to_pattern` of what `parse`
+/// gave can differ from the pattern that was parsed, like `\\x` being written as `x`, but
+/// it always matches the same texts. Tokens put together by hand round trip as long as each
+/// literal is a whole grapheme, that a combining accent doesn't join to the one before it,
+/// and a group of alternatives is the whole pattern, since without braces it can only be
+/// written with `|`.
+///
+/// # Arguments
+///
+/// * `tokens` - The tokens to write out.
+///
+/// # Examples
+/// ```
+/// use pyglob::{parse, to_pattern, Token};
+/// let mut tokens = parse("*.rs").unwrap();
+/// tokens.insert(0, Token::Literal(String::from("?")));
+/// assert_eq!(to_pattern(&tokens), "\\?*.rs");
+/// ```
+pub fn to_pattern(tokens: &[Token]) -> String {
+    struct Pattern<'t>(&'t [Token]);
+
+    impl fmt::Display for Pattern<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            token::write_pattern(f, self.0, &MatchOptions::new().syntax())
+        }
+    }
+
+    Pattern(tokens).to_string()
+}
+
+/// Checks if a string is ASCII and contains no `\r\n`, which is the only sequence of ASCII
+/// characters that makes up a single grapheme. Such a string can be matched byte by byte.
+pub(crate) fn is_plain_ascii(text: &str) -> bool {
+    text.is_ascii() && !text.contains("\r\n")
+}
+
+/// Match the tokens of a compiled pattern against a text, using the greedy matcher if the
+/// pattern is simple enough for it.
+fn match_tokens<U: Unit>(pattern: &[Token<U::Owned>], text: &[U], greedy: bool) -> bool {
+    // Strip whatever we can from both ends before the real matching
+    let (pattern, text) = preprocessing(pattern, text);
+
+    if greedy {
+        matcher::greedy_match(pattern, text)
+    } else {
+        matcher::match_with_cache(pattern, text)
+    }
+}
+
+/// This applies preprocessing to the pattern and text to speed up matching.
+///
+/// The returned pattern matches the returned text exactly when the original pattern
+/// matches the original text.
+fn preprocessing<'a, 'b, U: Unit>(
+    pattern: &'a [Token<U::Owned>],
+    text: &'b [U],
+) -> (&'a [Token<U::Owned>], &'b [U]) {
+    remove_matching_start_and_end(pattern, text)
+}
+
+/// Remove any duplicate stars, because they do not impact the matching.
+///
+/// This works on tokens rather than graphemes, so that escaped stars are left alone. Each
+/// star adds a row to the table in `match_with_cache` and another place for the greedy
+/// matcher to backtrack to, so without this a pattern made of many stars would cost time
+/// in proportion to the number of stars times the length of the text. The runs are removed
+/// in a single pass, so that a pattern of nothing but stars doesn't take quadratic time to
+/// compile either.
+fn remove_duplicate_stars<O>(mut pattern: Vec<Token<O>>) -> Vec<Token<O>> {
+    pattern.dedup_by(|star, previous| {
+        matches!(star, Token::AnyRun) && matches!(previous, Token::AnyRun)
+    });
+    for token in &mut pattern {
+        if let Token::Alternatives(alternatives) = token {
+            for alternative in alternatives {
+                *alternative = remove_duplicate_stars(std::mem::take(alternative));
+            }
+        }
+    }
+    pattern
+}
+
+/// Put each run of stars and question marks in a canonical order, with all of the question
+/// marks first and then a single star if there were any stars.
+///
+/// This doesn't change what the pattern matches. A question mark matches any one grapheme
+/// and a star any number of them, so a run of `k` question marks and stars in any order
+/// matches any `k` graphemes if it has no stars, and at least `k` of them if it has at least
+/// one star. The run is only broken by some other token, so `*?*` becomes `?*` but `*a?*`
+/// stays as it is. It does change which star captures what and how many captures there
+/// are, so this is only done to the tokens used for matching, and not to those used for
+/// `WildcardPattern::captures`.
+///
+/// Every star but one in each run is dropped, which saves a row of the table in
+/// `match_with_cache` for each of them, and the question marks before the star can be
+/// stripped off of the start of the text by `remove_matching_start`. Path stars and
+/// question marks in path mode, which are classes, are left alone.
+fn normalize_wildcards<O>(pattern: Vec<Token<O>>) -> Vec<Token<O>> {
+    let mut normalized = Vec::with_capacity(pattern.len());
+    let (mut questions, mut star) = (0, false);
+    for token in pattern {
+        match token {
+            Token::AnyOne => questions += 1,
+            Token::AnyRun => star = true,
+            token => {
+                push_wildcards(&mut normalized, &mut questions, &mut star);
+                normalized.push(match token {
+                    Token::Alternatives(alternatives) => Token::Alternatives(
+                        alternatives.into_iter().map(normalize_wildcards).collect(),
+                    ),
+                    token => token,
+                });
+            }
+        }
+    }
+    push_wildcards(&mut normalized, &mut questions, &mut star);
+    normalized
+}
+
+/// Push the question marks and star of a run in `normalize_wildcards`, and start a new run
+fn push_wildcards<O>(pattern: &mut Vec<Token<O>>, questions: &mut usize, star: &mut bool) {
+    pattern.extend((0..*questions).map(|_| Token::AnyOne));
+    if *star {
+        pattern.push(Token::AnyRun);
+    }
+    (*questions, *star) = (0, false);
+}
+
+/// If the start and end of two strings match, we can pre-emptively strip them.
+///
+/// Only tokens that consume exactly one grapheme are stripped, so this stops at the first
+/// star from either end.
+fn remove_matching_start_and_end<'a, 'b, U: Unit>(
+    pattern: &'a [Token<U::Owned>],
+    text: &'b [U],
+) -> (&'a [Token<U::Owned>], &'b [U]) {
+    let (pattern, text) = remove_matching_start(pattern, text);
+    remove_matching_end(pattern, text)
+}
+
+/// Strip the tokens at the start of the pattern which consume the start of the text
+fn remove_matching_start<'a, 'b, U: Unit>(
+    pattern: &'a [Token<U::Owned>],
+    text: &'b [U],
+) -> (&'a [Token<U::Owned>], &'b [U]) {
+    let mut i: usize = 0;
+    while (
+        i < pattern.len() && i < text.len()
+        // Check that we're not at the end of the string
+    ) && (
+        pattern[i].matches_one(&text[i])
+        // Check if the token consumes the character
+    ) {
+        i += 1;
+    }
+    (&pattern[i..], &text[i..])
+}
+
+/// Strip the tokens at the end of the pattern which consume the end of the text.
+///
+/// `i` and `j` are the lengths of what is left of the pattern and text, so that the
+/// first token and grapheme can be stripped too.
+fn remove_matching_end<'a, 'b, U: Unit>(
+    pattern: &'a [Token<U::Owned>],
+    text: &'b [U],
+) -> (&'a [Token<U::Owned>], &'b [U]) {
+    let mut i: usize = pattern.len();
+    let mut j: usize = text.len();
+    while (
+        i > 0 && j > 0
+        // Check that we're not at the start of the string
+    ) && (
+        pattern[i - 1].matches_one(&text[j - 1])
+        // Check if the token consumes the character
+    ) {
+        i -= 1;
+        j -= 1;
+    }
+    (&pattern[..i], &text[..j])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::{prop_assert_eq, proptest, ProptestConfig, Strategy};
+    use unicode_segmentation::UnicodeSegmentation;
+
+    #[test]
+    fn simple_match() {
+        assert_eq!(true, is_wildcard_match("alyib", "a?y*b"));
+    }
+
+    #[test]
+    fn simple_non_match() {
+        assert_eq!(false, is_wildcard_match("abcd", "a*b?"))
+    }
+
+    #[test]
+    fn test_kanji_characters() {
+        assert_eq!(true, is_wildcard_match("漢字", "??"))
+    }
+
+    #[test]
+    fn zero_width_joiner_sequence_is_one_grapheme() {
+        let family = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+        assert_eq!(true, is_wildcard_match(family, "?"));
+        assert_eq!(false, is_wildcard_match(family, "??"));
+        assert_eq!(true, is_wildcard_match(&format!("a{}b", family), "a?b"));
+        assert_eq!(
+            true,
+            is_wildcard_match(family, "[x\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}]")
+        );
+        // The man alone is not the whole family
+        assert_eq!(false, is_wildcard_match(family, "\u{1f468}*"));
+    }
+
+    #[test]
+    fn flag_is_one_grapheme() {
+        let flag = "\u{1f1fa}\u{1f1f8}";
+        assert_eq!(true, is_wildcard_match(flag, "?"));
+        assert_eq!(false, is_wildcard_match(flag, "??"));
+        // Regional indicators pair up from the start
+        let flags = "\u{1f1fa}\u{1f1f8}\u{1f1e9}\u{1f1ea}";
+        assert_eq!(true, is_wildcard_match(flags, "??"));
+        assert_eq!(true, is_wildcard_match(flags, "?\u{1f1e9}\u{1f1ea}"));
+        assert_eq!(false, is_wildcard_match(flags, "\u{1f1fa}*"));
+    }
+
+    #[test]
+    fn skin_tone_modifier_is_part_of_the_grapheme() {
+        let wave = "\u{1f44b}\u{1f3fd}";
+        assert_eq!(true, is_wildcard_match(wave, "?"));
+        assert_eq!(false, is_wildcard_match(wave, "\u{1f44b}?"));
+        assert_eq!(true, is_wildcard_match(&format!("hi {}!", wave), "hi ?!"));
+        assert_eq!(
+            Some(vec![String::from(wave)]),
+            captures(&format!("{}.png", wave), "*.png")
+        );
+    }
+
+    #[test]
+    fn empty_string_matches_star() {
+        assert_eq!(true, is_wildcard_match("", "*"));
+    }
+
+    #[test]
+    fn empty_string_doesnt_match_questionmark() {
+        assert_eq!(false, is_wildcard_match("", "?"));
+    }
+
+    #[test]
+    fn empty_string_matches_multiple_stars() {
+        assert_eq!(true, is_wildcard_match("", "***"));
+    }
+
+    #[test]
+    fn repeating_sequence_with_stars() {
+        assert_eq!(true, is_wildcard_match("daaadabadmanda", "da*da*da"));
+    }
+
+    #[test]
+    fn different_text_ending_doesnt_match() {
+        assert_eq!(false, is_wildcard_match("testingmore", "testing"));
+    }
+
+    #[test]
+    fn different_text_start_doesnt_match() {
+        assert_eq!(false, is_wildcard_match("more, testing", "testing"));
+    }
+
+    #[test]
+    fn star_and_questionmark_does_match() {
+        assert_eq!(true, is_wildcard_match("xx", "*?"));
+    }
+
+    #[test]
+    fn star_in_text_is_escaped() {
+        assert_eq!(true, is_wildcard_match("a*", "*"));
+    }
+
+    #[test]
+    fn empty_input_string() {
+        assert_eq!(true, is_wildcard_match("", "*"))
+    }
+
+    #[test]
+    fn empty_pattern_string() {
+        assert_eq!(false, is_wildcard_match("test", ""))
+    }
+
+    #[test]
+    fn both_strings_empty() {
+        assert_eq!(true, is_wildcard_match("", ""))
+    }
+
+    #[test]
+    fn class_matches_member() {
+        assert_eq!(true, is_wildcard_match("fileb.txt", "file[abc].txt"));
+    }
+
+    #[test]
+    fn class_doesnt_match_non_member() {
+        assert_eq!(false, is_wildcard_match("filed.txt", "file[abc].txt"));
+    }
+
+    #[test]
+    fn class_matches_only_one_character() {
+        assert_eq!(false, is_wildcard_match("fileab.txt", "file[abc].txt"));
+    }
+
+    #[test]
+    fn unterminated_class_is_literal() {
+        assert_eq!(true, is_wildcard_match("file[a", "file[a"));
+        assert_eq!(false, is_wildcard_match("filea", "file[a"));
+    }
+
+    #[test]
+    fn empty_class_matches_nothing() {
+        assert_eq!(false, is_wildcard_match("a", "[]"));
+        assert_eq!(false, is_wildcard_match("", "[]"));
+    }
+
+    #[test]
+    fn class_with_graphemes() {
+        assert_eq!(true, is_wildcard_match("漢", "[漢字]"));
+    }
+
+    #[test]
+    fn range_matches_character_inside() {
+        assert_eq!(true, is_wildcard_match("m", "[a-z]"));
+    }
+
+    #[test]
+    fn range_doesnt_match_character_outside() {
+        assert_eq!(false, is_wildcard_match("5", "[a-z]"));
+    }
+
+    #[test]
+    fn mixed_ranges() {
+        assert_eq!(true, is_wildcard_match("file5", "file[a-z0-9]"));
+        assert_eq!(true, is_wildcard_match("filex", "file[a-z0-9]"));
+        assert_eq!(false, is_wildcard_match("file_", "file[a-z0-9]"));
+    }
+
+    #[test]
+    fn hex_digit_range() {
+        assert_eq!(true, is_wildcard_match("0xB", "0x[0-9A-F]"));
+        assert_eq!(false, is_wildcard_match("0xb", "0x[0-9A-F]"));
+    }
+
+    #[test]
+    fn dash_at_class_edges_is_literal() {
+        assert_eq!(true, is_wildcard_match("-", "[-a]"));
+        assert_eq!(true, is_wildcard_match("-", "[a-]"));
+        assert_eq!(false, is_wildcard_match("b", "[a-]"));
+    }
+
+    #[test]
+    fn negated_class_matches_non_member() {
+        assert_eq!(true, is_wildcard_match("d", "[!abc]"));
+    }
+
+    #[test]
+    fn negated_class_doesnt_match_member() {
+        assert_eq!(false, is_wildcard_match("b", "[!abc]"));
+    }
+
+    #[test]
+    fn caret_negates_class() {
+        assert_eq!(true, is_wildcard_match("d", "[^abc]"));
+        assert_eq!(false, is_wildcard_match("b", "[^abc]"));
+    }
+
+    #[test]
+    fn negated_range() {
+        assert_eq!(true, is_wildcard_match("5", "[!a-z]"));
+        assert_eq!(false, is_wildcard_match("m", "[!a-z]"));
+    }
+
+    #[test]
+    fn negated_class_still_needs_a_character() {
+        assert_eq!(false, is_wildcard_match("", "[!abc]"));
+    }
+
+    #[test]
+    fn exclamation_mark_inside_class_is_literal() {
+        assert_eq!(true, is_wildcard_match("!", "[a!]"));
+        assert_eq!(false, is_wildcard_match("b", "[a!]"));
+    }
+
+    #[test]
+    fn named_classes_match() {
+        assert_eq!(true, is_wildcard_match("7", "[[:digit:]]"));
+        assert_eq!(false, is_wildcard_match("x", "[[:digit:]]"));
+        assert_eq!(true, is_wildcard_match("a7", "[[:alpha:]][[:digit:]]"));
+        assert_eq!(false, is_wildcard_match("7a", "[[:alpha:]][[:digit:]]"));
+        assert_eq!(true, is_wildcard_match("Q", "[[:upper:]]"));
+        assert_eq!(false, is_wildcard_match("q", "[[:upper:]]"));
+        assert_eq!(true, is_wildcard_match(" ", "[[:space:]]"));
+        assert_eq!(true, is_wildcard_match(",", "[[:punct:]]"));
+        assert_eq!(true, is_wildcard_match("z", "[[:alnum:]]"));
+        assert_eq!(true, is_wildcard_match("z", "[[:lower:]]"));
+    }
+
+    #[test]
+    fn named_class_mixed_with_members() {
+        for text in ["5", "a", "c"] {
+            assert_eq!(true, is_wildcard_match(text, "[[:digit:]abc]"), "{}", text);
+        }
+        assert_eq!(false, is_wildcard_match("d", "[[:digit:]abc]"));
+        assert_eq!(true, is_wildcard_match("d", "[![:digit:]abc]"));
+        assert_eq!(false, is_wildcard_match("5", "[![:digit:]abc]"));
+    }
+
+    #[test]
+    fn named_classes_are_ascii_only() {
+        assert_eq!(false, is_wildcard_match("\u{e9}", "[[:alpha:]]"));
+        assert_eq!(false, is_wildcard_match("e\u{301}", "[[:alpha:]]"));
+        assert_eq!(true, is_wildcard_match("\u{e9}", "[![:alpha:]]"));
+        assert_eq!(false, is_wildcard_match_bytes(b"\xe9", b"[[:alpha:]]"));
+    }
+
+    #[test]
+    fn named_class_in_path_mode() {
+        assert_eq!(false, is_wildcard_match_path("a/b", "a[[:punct:]]b", '/'));
+        assert_eq!(true, is_wildcard_match_path("a.b", "a[[:punct:]]b", '/'));
+    }
+
+    #[test]
+    fn null_bytes_are_ordinary_graphemes() {
+        assert_eq!(true, is_wildcard_match("a\0b", "a?b"));
+        assert_eq!(true, is_wildcard_match("a\0b", "a\0b"));
+        assert_eq!(false, is_wildcard_match("a\0b", "a\0"));
+        assert_eq!(true, is_wildcard_match("\0\0\0", "*\0"));
+        assert_eq!(true, is_wildcard_match("a\0b\u{e9}", "a*\u{e9}"));
+        assert_eq!(true, is_wildcard_match_bytes(b"a\0b", b"a?b"));
+        assert_eq!(false, is_wildcard_match_bytes(b"a\0", b"a\0b"));
+        assert_eq!(Some(vec![String::from("\0b")]), captures("a\0b", "a*"));
+        assert_eq!(Some((1, 2)), find("a\0b", "[\0]"));
+    }
+
+    #[test]
+    fn control_characters_are_ordinary_graphemes() {
+        for control in (0..0x20).chain([0x7f]).map(char::from) {
+            let text = format!("x{}y", control);
+            assert_eq!(true, is_wildcard_match(&text, "x?y"), "{:?}", control);
+            assert_eq!(true, is_wildcard_match(&text, "x*y"), "{:?}", control);
+            assert_eq!(true, is_wildcard_match(&text, "x[\0-\x1f\x7f]y"));
+            assert_eq!(false, is_wildcard_match(&text, "x[!\0-\x1f\x7f]y"));
+            assert_eq!(true, is_wildcard_match(&text, &escape(&text)));
+            // The same text with something outside of ASCII, so it's split in to graphemes
+            let text = format!("{}\u{e9}", text);
+            assert_eq!(true, is_wildcard_match(&text, "x?y?"), "{:?}", control);
+        }
+        // Except for a carriage return and line feed together, which are one grapheme
+        assert_eq!(true, is_wildcard_match("x\r\ny", "x?y"));
+    }
+
+    #[test]
+    fn escaped_star_matches_literal_star() {
+        assert_eq!(true, is_wildcard_match("a*b", "a\\*b"));
+    }
+
+    #[test]
+    fn star_in_text_matches_escaped_star_everywhere() {
+        // Graphemes, bytes, plain ASCII and the table all compare a `*` in the text like
+        // any other literal
+        assert_eq!(true, is_wildcard_match("\u{e9}*b", "\u{e9}\\*b"));
+        assert_eq!(true, is_wildcard_match_bytes(b"a*b", b"a\\*b"));
+        assert_eq!(true, is_wildcard_match("x*y*z", "*\\**\\**"));
+        assert_eq!(false, is_wildcard_match("x*yz", "*\\**\\**"));
+        assert_eq!(
+            Some(vec![String::from("a"), String::from("b")]),
+            captures("a*b", "*\\**")
+        );
+    }
+
+    #[test]
+    fn escaped_star_doesnt_match_other_characters() {
+        assert_eq!(false, is_wildcard_match("axb", "a\\*b"));
+    }
+
+    #[test]
+    fn escaped_questionmark_matches_literal_questionmark() {
+        assert_eq!(true, is_wildcard_match("why?", "why\\?"));
+        assert_eq!(false, is_wildcard_match("whys", "why\\?"));
+    }
+
+    #[test]
+    fn escaped_bracket_matches_literal_bracket() {
+        assert_eq!(true, is_wildcard_match("[a]", "\\[a]"));
+        assert_eq!(false, is_wildcard_match("a", "\\[a]"));
+    }
+
+    #[test]
+    fn escaped_backslash_matches_backslash() {
+        assert_eq!(true, is_wildcard_match("a\\b", "a\\\\b"));
+    }
+
+    #[test]
+    fn escaped_star_followed_by_star() {
+        assert_eq!(true, is_wildcard_match("*abc", "\\**"));
+        assert_eq!(false, is_wildcard_match("abc", "\\**"));
+    }
+
+    #[test]
+    fn backslash_is_literal_without_escapes() {
+        assert_eq!(true, is_wildcard_match_without_escapes("a\\b", "a\\b"));
+        assert_eq!(true, is_wildcard_match_without_escapes("a\\xb", "a\\*b"));
+        assert_eq!(false, is_wildcard_match_without_escapes("a*b", "a\\*b"));
+    }
+
+    #[test]
+    fn matching_is_case_sensitive_by_default() {
+        assert_eq!(false, is_wildcard_match("Hello.TXT", "hello.txt"));
+    }
+
+    #[test]
+    fn case_insensitive_match() {
+        assert_eq!(
+            true,
+            is_wildcard_match_case_insensitive("Hello.TXT", "hello.txt")
+        );
+        assert_eq!(
+            true,
+            is_wildcard_match_case_insensitive("hello.txt", "HELLO.*")
+        );
+        assert_eq!(
+            false,
+            is_wildcard_match_case_insensitive("Hello.TXT", "hello.rs")
+        );
+    }
+
+    #[test]
+    fn case_insensitive_wildcards_are_unchanged() {
+        assert_eq!(true, is_wildcard_match_case_insensitive("ABC", "a?c"));
+        assert_eq!(false, is_wildcard_match_case_insensitive("ABBC", "a?c"));
+        assert_eq!(true, is_wildcard_match_case_insensitive("Q", "[a-z]"));
+        assert_eq!(true, is_wildcard_match_case_insensitive("q", "[A-Z]"));
+    }
+
+    #[test]
+    fn case_insensitive_non_ascii() {
+        assert_eq!(true, is_wildcard_match_case_insensitive("ÄÖÜ", "äöü"));
+        assert_eq!(
+            true,
+            is_wildcard_match_case_insensitive("ΣΊΣΥΦΟΣ", "σίσυφος")
+        );
+    }
+
+    #[test]
+    fn case_insensitive_is_not_case_folding() {
+        assert_eq!(
+            false,
+            is_wildcard_match_case_insensitive("STRASSE", "straße")
+        );
+    }
+
+    #[test]
+    fn casefold_match() {
+        assert_eq!(true, is_wildcard_match_casefold("STRASSE", "straße"));
+        assert_eq!(true, is_wildcard_match_casefold("straße", "STRASSE"));
+        assert_eq!(true, is_wildcard_match_casefold("ΣΊΣΥΦΟΣ", "σίσυφος"));
+        assert_eq!(true, is_wildcard_match_casefold("Hello.TXT", "hello.*"));
+        assert_eq!(false, is_wildcard_match_casefold("Hello.TXT", "hello.rs"));
+    }
+
+    #[test]
+    fn casefold_wildcards_see_the_folded_text() {
+        // `ß` folds to two characters in both the pattern and the text
+        assert_eq!(true, is_wildcard_match_casefold("STRASSE", "stra??e"));
+        assert_eq!(false, is_wildcard_match_casefold("STRASSE", "stra?e"));
+        assert_eq!(false, is_wildcard_match_casefold("straße", "stra?e"));
+        assert_eq!(true, is_wildcard_match_casefold("STRASSE", "stra[s]*"));
+    }
+
+    #[test]
+    fn case_folding_takes_the_place_of_lowercasing() {
+        let options = MatchOptions::new()
+            .case_insensitive(true)
+            .case_folding(true);
+        assert_eq!(true, is_wildcard_match_with("STRASSE", "straße", &options));
+    }
+
+    #[test]
+    fn very_long_text() {
+        let text = "ab".repeat(20_000);
+        assert_eq!(true, is_wildcard_match(&text, "a*b*ab"));
+        assert_eq!(false, is_wildcard_match(&text, "a*b*ba"));
+    }
+
+    #[test]
+    fn triple_star_is_the_same_as_one_star() {
+        assert_eq!(
+            WildcardPattern::new("*").tokens(),
+            WildcardPattern::new("***").tokens()
+        );
+        for text in ["", "a", "abc", "***"] {
+            assert_eq!(is_wildcard_match(text, "*"), is_wildcard_match(text, "***"));
+            assert_eq!(
+                is_wildcard_match(text, "a*c"),
+                is_wildcard_match(text, "a***c")
+            );
+        }
+    }
+
+    #[test]
+    fn adversarial_inputs_dont_panic() {
+        let stars = "*".repeat(10_000);
+        let questions = "?".repeat(1_000);
+        let long = "a".repeat(10_000);
+        let cases = [
+            ("", ""),
+            ("", stars.as_str()),
+            (long.as_str(), stars.as_str()),
+            ("", questions.as_str()),
+            (&long[..999], questions.as_str()),
+            (&long[..1_000], questions.as_str()),
+            (&long[..1_001], questions.as_str()),
+            (long.as_str(), ""),
+            ("", "\\"),
+            ("\\", "\\"),
+            ("[", "["),
+            ("", "[!]"),
+            ("", "{"),
+            ("", "}{,}"),
+            ("", "**/"),
+            ("/", "**/"),
+            ("", "?*?*?"),
+        ];
+        for (text, pattern) in cases {
+            for options in [
+                MatchOptions::new(),
+                MatchOptions::new().escapes(false),
+                MatchOptions::new().separator('/').braces(true),
+                MatchOptions::new().optional_question_marks(true),
+                MatchOptions::new().lazy_captures(true).braces(true),
+                MatchOptions::new().case_insensitive(true).budget(Some(1)),
+            ] {
+                let compiled = WildcardPattern::with_options(pattern, &options);
+                let matched = compiled.matches(text);
+                assert_eq!(matched, compiled.captures(text).is_some());
+                if let Ok(result) = compiled.try_matches(text) {
+                    assert_eq!(matched, result);
+                }
+            }
+            is_wildcard_match_bytes(text.as_bytes(), pattern.as_bytes());
+            is_wildcard_search(text, pattern);
+            let _ = validate(pattern);
+        }
+    }
+
+    #[test]
+    fn random_patterns_dont_panic() {
+        let mut random = Random(0x1f83_d9ab_fb41_bd6b);
+        let alphabet = [
+            "a",
+            "*",
+            "?",
+            "[",
+            "]",
+            "!",
+            "^",
+            "-",
+            "\\",
+            "{",
+            "}",
+            ",",
+            "/",
+            ":",
+            "e\u{301}",
+            "[:alpha:]",
+        ];
+        let options = MatchOptions::new()
+            .braces(true)
+            .separator('/')
+            .optional_question_marks(true);
+        for _ in 0..5_000 {
+            let pattern = random.string(&alphabet, 10);
+            let text = random.string(&alphabet, 8);
+            let compiled = WildcardPattern::with_options(&pattern, &options);
+            assert_eq!(
+                compiled.matches(&text),
+                compiled.captures(&text).is_some(),
+                "{:?} against {:?}",
+                pattern,
+                text
+            );
+            compiled.to_string();
+            compiled.prefix();
+            compiled.suffix();
+            is_wildcard_match(&text, &pattern);
+            let _ = validate(&pattern);
+            unescape(&pattern);
+        }
+    }
+
+    #[test]
+    fn os_paths_match() {
+        assert_eq!(
+            true,
+            is_wildcard_match_os_path(Path::new("src/main.rs"), "src/*.rs")
+        );
+        assert_eq!(
+            true,
+            is_wildcard_match_os_path(Path::new("src/a/main.rs"), "src/*.rs")
+        );
+        assert_eq!(
+            false,
+            is_wildcard_match_os_path(Path::new("src/main.py"), "src/*.rs")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_unicode_os_paths_match() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"\xfe\xff.rs"));
+        assert_eq!(true, is_wildcard_match_os_path(path, "*.rs"));
+        assert_eq!(true, is_wildcard_match_os_path(path, "??.rs"));
+        assert_eq!(false, is_wildcard_match_os_path(path, "?.rs"));
+    }
+
+    #[test]
+    fn many_stars_dont_explode() {
+        use std::time::{Duration, Instant};
+
+        // Without collapsing the stars, these would need billions of steps
+        let start = Instant::now();
+        let stars = "*".repeat(100_000);
+        let text = "a".repeat(100_000);
+        assert_eq!(
+            true,
+            is_wildcard_match(&text, &format!("{}a{}", stars, stars))
+        );
+        assert_eq!(
+            false,
+            is_wildcard_match(&text, &format!("{}b{}", stars, stars))
+        );
+        assert_eq!(
+            false,
+            is_wildcard_match(&text, &format!("{}[b]{}", stars, stars))
+        );
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "took {:?}",
+            start.elapsed()
+        );
+    }
+
+    /// A small xorshift generator, so the randomised tests are reproducible
+    struct Random(u64);
+
+    impl Random {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        fn string(&mut self, alphabet: &[&str], max_len: usize) -> String {
+            let len = self.below(max_len + 1);
+            (0..len)
+                .map(|_| alphabet[self.below(alphabet.len())])
+                .collect()
+        }
+    }
+
+    #[test]
+    fn preprocessing_strips_matching_start_and_end() {
+        let pattern = token::parse(&["a", "?", "c", "*", "d"], true);
+        let (pattern, text) = preprocessing(&pattern, &["a", "b", "c", "x", "d"]);
+        assert_eq!(vec![Token::AnyRun], pattern);
+        assert_eq!(vec!["x"], text);
+    }
+
+    #[test]
+    fn end_stripping_removes_everything_that_matches() {
+        let pattern = token::parse(&["a", "b", "c"], true);
+        let (pattern, text) = remove_matching_end(&pattern, &["a", "b", "c"]);
+        assert_eq!(true, pattern.is_empty());
+        assert_eq!(true, text.is_empty());
+    }
+
+    #[test]
+    fn end_stripping_stops_at_first_mismatch() {
+        let pattern = token::parse(&["y", "a", "b", "c"], true);
+        let (pattern, text) = remove_matching_end(&pattern, &["x", "x", "a", "b", "c"]);
+        assert_eq!(vec![Token::Literal(String::from("y"))], pattern);
+        assert_eq!(vec!["x", "x"], text);
+    }
+
+    #[test]
+    fn end_stripping_respects_questionmarks() {
+        let pattern = token::parse(&["?", "a", "b", "c"], true);
+        let (pattern, text) = remove_matching_end(&pattern, &["x", "a", "b", "c"]);
+        assert_eq!(true, pattern.is_empty());
+        assert_eq!(true, text.is_empty());
+    }
+
+    #[test]
+    fn end_stripping_stops_at_star() {
+        let pattern = token::parse(&["*", "b"], true);
+        let (pattern, text) = remove_matching_end(&pattern, &["a", "b"]);
+        assert_eq!(vec![Token::AnyRun], pattern);
+        assert_eq!(vec!["a"], text);
+    }
+
+    #[test]
+    fn end_stripping_with_shorter_text() {
+        let pattern = token::parse(&["x", "a", "b"], true);
+        let (pattern, text) = remove_matching_end(&pattern, &["a", "b"]);
+        assert_eq!(vec![Token::Literal(String::from("x"))], pattern);
+        assert_eq!(true, text.is_empty());
+    }
+
+    #[test]
+    fn preprocessing_stops_at_mismatch() {
+        let pattern = token::parse(&["a", "b", "*"], true);
+        let (pattern, text) = preprocessing(&pattern, &["a", "c", "b"]);
+        assert_eq!(2, pattern.len());
+        assert_eq!(vec!["c", "b"], text);
+    }
+
+    #[test]
+    fn wildcard_runs_are_normalized() {
+        let parse = |pattern: &str| {
+            let graphemes = pattern.graphemes(true).collect::<Vec<&str>>();
+            token::parse(&graphemes, true)
+        };
+        assert_eq!(parse("?*"), normalize_wildcards(parse("*?*")));
+        assert_eq!(parse("a??*b"), normalize_wildcards(parse("a*?*?b")));
+        assert_eq!(parse("*a?*"), normalize_wildcards(parse("*a*?")));
+        assert_eq!(parse("??"), normalize_wildcards(parse("??")));
+        assert_eq!(parse("\\*?*"), normalize_wildcards(parse("\\**?")));
+        let braces = MatchOptions::new().braces(true);
+        assert_eq!(
+            WildcardPattern::with_options("{?*,a}", &braces).tokens(),
+            normalize_wildcards(
+                WildcardPattern::with_options("{*?,a}", &braces)
+                    .tokens()
+                    .to_vec()
+            )
+        );
+    }
+
+    #[test]
+    fn normalizing_wildcards_doesnt_change_results() {
+        let mut random = Random(0x6a09_e667_f3bc_c909);
+        let pattern_alphabet = ["a", "b", "*", "?", "[ab]"];
+        let text_alphabet = ["a", "b", "c"];
+        for _ in 0..5_000 {
+            let pattern = random.string(&pattern_alphabet, 8);
+            let text = random.string(&text_alphabet, 8);
+
+            let graphemes = pattern.graphemes(true).collect::<Vec<&str>>();
+            let tokens = token::parse(&graphemes, true);
+            let text_graphemes = text.graphemes(true).collect::<Vec<&str>>();
+
+            let expected = matcher::match_with_cache(&tokens, &text_graphemes);
+            assert_eq!(
+                expected,
+                matcher::match_with_cache(&normalize_wildcards(tokens), &text_graphemes),
+                "{:?} against {:?}",
+                pattern,
+                text
+            );
+            assert_eq!(expected, is_wildcard_match(&text, &pattern));
+        }
+    }
+
+    #[test]
+    fn interning_graphemes_doesnt_change_results() {
+        let mut random = Random(0x3c6e_f372_fe94_f82b);
+        let pattern_alphabet = [
+            "\u{e9}",
+            "e\u{301}",
+            "e",
+            "*",
+            "?",
+            "[\u{e0}-\u{ea}]",
+            "[!e]",
+        ];
+        let text_alphabet = ["\u{e9}", "e\u{301}", "e", "\u{fc}"];
+        for _ in 0..5_000 {
+            let pattern = random.string(&pattern_alphabet, 8);
+            let text = random.string(&text_alphabet, 8);
+
+            let compiled = WildcardPattern::new(&pattern);
+            let text_graphemes = text.graphemes(true).collect::<Vec<&str>>();
+            assert_eq!(
+                matcher::match_with_cache(compiled.tokens(), &text_graphemes),
+                compiled.matches(&text),
+                "{:?} against {:?}",
+                pattern,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn normalizing_wildcards_keeps_captures() {
+        assert_eq!(
+            Some(vec![String::from("ab"), String::new()]),
+            captures("abc", "*?*")
+        );
+        assert_eq!(Some(vec![String::from("ab")]), captures("abc", "*?"));
+        assert_eq!(2, WildcardPattern::new("*?*").complexity_hint());
+    }
+
+    #[test]
+    fn preprocessing_doesnt_change_results() {
+        let mut random = Random(0x2545_f491_4f6c_dd1d);
+        let pattern_alphabet = ["a", "b", "*", "?", "[ab]", "[!a]"];
+        let text_alphabet = ["a", "b", "c"];
+        for _ in 0..5_000 {
+            let pattern = random.string(&pattern_alphabet, 6);
+            let text = random.string(&text_alphabet, 6);
+
+            let graphemes = pattern.graphemes(true).collect::<Vec<&str>>();
+            let tokens = token::parse(&graphemes, true);
+            let text_graphemes = text.graphemes(true).collect::<Vec<&str>>();
+
+            let expected = matcher::match_with_cache(&tokens, &text_graphemes);
+            let (stripped_pattern, stripped_text) = preprocessing(&tokens, &text_graphemes);
+            assert_eq!(
+                expected,
+                matcher::match_with_cache(stripped_pattern, stripped_text),
+                "{:?} against {:?}",
+                pattern,
+                text
+            );
+            assert_eq!(expected, is_wildcard_match(&text, &pattern));
+        }
+    }
+
+    #[test]
+    fn ascii_fast_path_is_equivalent() {
+        let mut random = Random(0x9e37_79b9_7f4a_7c15);
+        let pattern_alphabet = ["a", "b", "*", "?", "[", "]", "!", "-", "\\", "\r", "\n"];
+        let text_alphabet = ["a", "b", "-", "]", "\\", "\r", "\n"];
+        for _ in 0..5_000 {
+            let pattern = random.string(&pattern_alphabet, 8);
+            let text = random.string(&text_alphabet, 8);
+
+            let graphemes = pattern.graphemes(true).collect::<Vec<&str>>();
+            let tokens = remove_duplicate_stars(token::parse(&graphemes, true));
+            let text_graphemes = text.graphemes(true).collect::<Vec<&str>>();
+            let expected = match_tokens(&tokens, &text_graphemes, false);
+            assert_eq!(
+                expected,
+                is_wildcard_match(&text, &pattern),
+                "{:?} against {:?}",
+                pattern,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn matching_from_the_end_agrees_with_the_table() {
+        let mut random = Random(0x1f83_d9ab_5be0_cd19);
+        // Plain ASCII is matched by bytes, and the rest by graphemes
+        for letter in ["e", "\u{e9}"] {
+            let pattern_alphabet = ["a", letter, "/", "*", "?"];
+            let text_alphabet = ["a", letter, "/"];
+            for _ in 0..5_000 {
+                let pattern = random.string(&pattern_alphabet, 8);
+                let text = random.string(&text_alphabet, 10);
+
+                let graphemes = pattern.graphemes(true).collect::<Vec<&str>>();
+                let tokens = token::parse(&graphemes, true);
+                let text_graphemes = text.graphemes(true).collect::<Vec<&str>>();
+                assert_eq!(
+                    match_tokens(&tokens, &text_graphemes, false),
+                    is_wildcard_match(&text, &pattern),
+                    "{:?} against {:?}",
+                    pattern,
+                    text
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn carriage_return_line_feed_is_one_grapheme() {
+        assert_eq!(true, is_wildcard_match("a\r\n", "a?"));
+        assert_eq!(false, is_wildcard_match("a\r\n", "a??"));
+        assert_eq!(false, is_wildcard_match("a\r\n", "a\r?"));
+    }
+
+    #[test]
+    fn filter_keeps_matching_names_in_order() {
+        let names = ["b.txt", "a.rs", "c.txt", "txt", ""];
+        assert_eq!(vec!["b.txt", "c.txt"], filter(&names, "*.txt"));
+    }
+
+    #[test]
+    fn filter_with_no_names() {
+        assert_eq!(true, filter(&[], "*").is_empty());
+    }
+
+    #[test]
+    fn partition_puts_every_name_in_one_list() {
+        let names = ["b.txt", "a.rs", "c.txt", "txt", "", "b.txt"];
+        let (matching, rest) = partition(&names, "*.txt");
+        assert_eq!(vec!["b.txt", "c.txt", "b.txt"], matching);
+        assert_eq!(vec!["a.rs", "txt", ""], rest);
+        assert_eq!(names.len(), matching.len() + rest.len());
+        for name in names {
+            let expected = is_wildcard_match(name, "*.txt");
+            assert_eq!(expected, matching.contains(&name), "{:?}", name);
+            assert_eq!(expected, !rest.contains(&name), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn partition_without_names() {
+        let (matching, rest) = partition(&[], "*");
+        assert_eq!(true, matching.is_empty());
+        assert_eq!(true, rest.is_empty());
+    }
+
+    #[test]
+    fn filter_indices_point_at_matches() {
+        let names = ["b.txt", "a.rs", "c.txt", "txt", "", "d.txt"];
+        let indices = filter_indices(&names, "*.txt");
+        assert_eq!(vec![0, 2, 5], indices);
+        for (i, name) in names.iter().enumerate() {
+            assert_eq!(indices.contains(&i), is_wildcard_match(name, "*.txt"));
+        }
+        assert_eq!(true, filter_indices(&[], "*").is_empty());
+    }
+
+    #[test]
+    fn matches_all_texts_keeps_a_result_per_text() {
+        let texts = ["a.txt", "b.rs", "a.txt", "txt", ""];
+        let matches = matches_all_texts(&texts, "*.txt");
+        assert_eq!(texts.len(), matches.len());
+        assert_eq!(vec![true, false, true, false, false], matches);
+        assert_eq!(Vec::<bool>::new(), matches_all_texts(&[], "*"));
+    }
+
+    #[test]
+    fn count_matches_counts_duplicates() {
+        let texts = ["a.txt", "b.rs", "a.txt", "txt", ""];
+        assert_eq!(2, count_matches(&texts, "*.txt"));
+        assert_eq!(5, count_matches(&texts, "*"));
+        assert_eq!(0, count_matches(&[], "*"));
+    }
+
+    #[test]
+    fn captures_single_star() {
+        assert_eq!(
+            Some(vec![String::from("main")]),
+            captures("src/main.rs", "src/*.rs")
+        );
+    }
+
+    #[test]
+    fn captures_of_non_match() {
+        assert_eq!(None, captures("src/main.py", "src/*.rs"));
+    }
+
+    #[test]
+    fn captures_multiple_stars() {
+        assert_eq!(
+            Some(vec![String::from("xx"), String::from("yy")]),
+            captures("axxbyyc", "a*b*c")
+        );
+        assert_eq!(
+            Some(vec![String::from(""), String::from("")]),
+            captures("abc", "a*b*c")
+        );
+    }
+
+    #[test]
+    fn captures_are_greedy() {
+        assert_eq!(
+            Some(vec![String::from("xbx"), String::from("")]),
+            captures("axbxbc", "a*b*c")
+        );
+        assert_eq!(
+            Some(vec![String::from("a-b"), String::from("c")]),
+            captures("a-b-c", "*-*")
+        );
+    }
+
+    #[test]
+    fn lazy_captures_take_the_least() {
+        let greedy = WildcardPattern::new("*-*");
+        let lazy = WildcardPattern::with_options("*-*", &MatchOptions::new().lazy_captures(true));
+        assert_eq!(
+            Some(vec![String::from("a-b"), String::from("c")]),
+            greedy.captures("a-b-c")
+        );
+        assert_eq!(
+            Some(vec![String::from("a"), String::from("b-c")]),
+            lazy.captures("a-b-c")
+        );
+        assert_eq!(None, lazy.captures("abc"));
+        assert_eq!(greedy.matches("a-b-c"), lazy.matches("a-b-c"));
+    }
+
+    #[test]
+    fn lazy_captures_still_match_the_whole_text() {
+        let lazy = WildcardPattern::with_options("a*b*", &MatchOptions::new().lazy_captures(true));
+        assert_eq!(
+            Some(vec![String::from("x"), String::from("ybz")]),
+            lazy.captures("axbybz")
+        );
+    }
+
+    #[test]
+    fn captures_without_stars() {
+        assert_eq!(Some(vec![]), captures("abc", "a?c"));
+    }
+
+    #[test]
+    fn captures_keep_graphemes_whole() {
+        assert_eq!(
+            Some(vec![String::from("漢字e\u{301}")]),
+            captures("漢字e\u{301}.txt", "*.txt")
+        );
+    }
+
+    #[test]
+    fn normalized_match() {
+        assert_eq!(true, is_wildcard_match_normalized("e\u{301}", "\u{e9}"));
+        assert_eq!(true, is_wildcard_match_normalized("\u{e9}", "e\u{301}"));
+        assert_eq!(false, is_wildcard_match("e\u{301}", "\u{e9}"));
+    }
+
+    #[test]
+    fn normalized_wildcards_match_one_character() {
+        assert_eq!(
+            true,
+            is_wildcard_match_normalized("re\u{301}sume\u{301}", "r?sum?")
+        );
+        assert_eq!(
+            true,
+            is_wildcard_match_normalized("r\u{e9}sum\u{e9}", "r[e\u{301}]sum*")
+        );
+    }
+
+    #[test]
+    fn path_star_stays_in_segment() {
+        assert_eq!(true, is_wildcard_match_path("src/main.rs", "src/*.rs", '/'));
+        assert_eq!(false, is_wildcard_match_path("src/a/b.rs", "src/*.rs", '/'));
+        assert_eq!(false, is_wildcard_match_path("src/a/b.rs", "*.rs", '/'));
+        assert_eq!(true, is_wildcard_match_path("src/", "src/*", '/'));
+        assert_eq!(true, is_wildcard_match("src/a/b.rs", "src/*.rs"));
+    }
+
+    #[test]
+    fn path_globstar_crosses_segments() {
+        let pattern = "src/**/*.rs";
+        assert_eq!(true, is_wildcard_match_path("src/a/b.rs", pattern, '/'));
+        assert_eq!(true, is_wildcard_match_path("src/a/b/c/d.rs", pattern, '/'));
+        assert_eq!(true, is_wildcard_match_path("src/main.rs", pattern, '/'));
+        assert_eq!(false, is_wildcard_match_path("srca/b.rs", pattern, '/'));
+        assert_eq!(false, is_wildcard_match_path("src/a/b.py", pattern, '/'));
+        assert_eq!(false, is_wildcard_match_path("lib/src/a.rs", pattern, '/'));
+    }
+
+    #[test]
+    fn path_globstar_at_start_and_end() {
+        assert_eq!(true, is_wildcard_match_path("main.rs", "**/*.rs", '/'));
+        assert_eq!(true, is_wildcard_match_path("a/b/main.rs", "**/*.rs", '/'));
+        assert_eq!(true, is_wildcard_match_path("src/a/b", "src/**", '/'));
+        assert_eq!(true, is_wildcard_match_path("src/", "src/**", '/'));
+        assert_eq!(false, is_wildcard_match_path("src", "src/**", '/'));
+        assert_eq!(true, is_wildcard_match_path("a/b/c", "**", '/'));
+        assert_eq!(true, is_wildcard_match_path("", "**", '/'));
+    }
+
+    #[test]
+    fn path_double_star_inside_segment_is_single_star() {
+        assert_eq!(true, is_wildcard_match_path("axxb", "a**b", '/'));
+        assert_eq!(false, is_wildcard_match_path("ax/xb", "a**b", '/'));
+        assert_eq!(false, is_wildcard_match_path("a/x/b", "a/**b", '/'));
+        assert_eq!(true, is_wildcard_match_path("a/xb", "a/**b", '/'));
+    }
+
+    #[test]
+    fn path_consecutive_globstars() {
+        assert_eq!(true, is_wildcard_match_path("a/b", "a/**/**/b", '/'));
+        assert_eq!(true, is_wildcard_match_path("a/x/y/b", "a/**/**/b", '/'));
+        assert_eq!(
+            true,
+            is_wildcard_match_path("a/x/b/y/b", "a/**/b/**/b", '/')
+        );
+        assert_eq!(false, is_wildcard_match_path("a/x/b", "a/**/b/**/b", '/'));
+    }
+
+    #[test]
+    fn path_single_wildcards_dont_match_separator() {
+        assert_eq!(false, is_wildcard_match_path("a/b", "a?b", '/'));
+        assert_eq!(false, is_wildcard_match_path("a/b", "a[/]b", '/'));
+        assert_eq!(false, is_wildcard_match_path("a/b", "a[!x]b", '/'));
+        assert_eq!(false, is_wildcard_match_path("a/b", "a[.-0]b", '/'));
+        assert_eq!(true, is_wildcard_match_path("a.b", "a[.-0]b", '/'));
+        assert_eq!(true, is_wildcard_match_path("a0b", "a[.-0]b", '/'));
+        assert_eq!(true, is_wildcard_match_path("a/b", "a\\/b", '/'));
+    }
+
+    #[test]
+    fn path_with_backslash_separator() {
+        let text = "src\\a\\b.rs";
+        assert_eq!(true, is_wildcard_match_path(text, "src\\**\\*.rs", '\\'));
+        assert_eq!(false, is_wildcard_match_path(text, "src\\*.rs", '\\'));
+        // Forward slashes are ordinary characters with a backslash separator
+        assert_eq!(true, is_wildcard_match_path("src/a/b.rs", "src*.rs", '\\'));
+    }
+
+    #[test]
+    fn path_mode_with_unicode() {
+        assert_eq!(
+            true,
+            is_wildcard_match_path("d\u{e9}j\u{e0}/vu.txt", "*/??.txt", '/')
+        );
+        assert_eq!(true, is_wildcard_match_path("a/e\u{301}/b", "a/?/b", '/'));
+    }
+
+    /// Texts that the patterns in `FNMATCH_CASES` are checked on
+    const FNMATCH_TEXTS: [&str; 23] = [
+        "]", "[]", "[", "!", "[!]", "a", "^", "b", "c", "-", "/", "x.", "xy", "a*b", "a\\b", "\\",
+        "\\]", ":]", "d]", "e", "*", "?", "a[",
+    ];
+
+    /// Patterns, and which of `FNMATCH_TEXTS` CPython's `fnmatch.fnmatchcase` matches them
+    /// with
+    const FNMATCH_CASES: [(&str, &[&str]); 29] = [
+        ("[]]", &["]"]),
+        ("[]", &["[]"]),
+        ("[!]", &["[!]"]),
+        (
+            "[!]]",
+            &["[", "!", "a", "^", "b", "c", "-", "/", "\\", "e", "*", "?"],
+        ),
+        ("[]a]", &["]", "a"]),
+        ("[^a]", &["a", "^"]),
+        (
+            "[!a]",
+            &["]", "[", "!", "^", "b", "c", "-", "/", "\\", "e", "*", "?"],
+        ),
+        ("[[:digit:]]", &["[]", ":]", "d]"]),
+        ("[a-c]", &["a", "b", "c"]),
+        ("[c-a]", &[]),
+        ("[a-]", &["a", "-"]),
+        ("[-a]", &["a", "-"]),
+        ("[--0]", &["-", "/"]),
+        ("[a--]", &[]),
+        ("[]-a]", &["]", "a", "^"]),
+        ("[b-ac]", &["c"]),
+        (
+            "[!z-a]",
+            &[
+                "]", "[", "!", "a", "^", "b", "c", "-", "/", "\\", "e", "*", "?",
+            ],
+        ),
+        ("a\\*b", &["a\\b"]),
+        ("a\\b", &["a\\b"]),
+        ("[\\]]", &["\\]"]),
+        ("[\\\\]", &["\\"]),
+        (
+            "*[!.]",
+            &[
+                "]", "[]", "[", "!", "[!]", "a", "^", "b", "c", "-", "/", "xy", "a*b", "a\\b",
+                "\\", "\\]", ":]", "d]", "e", "*", "?", "a[",
+            ],
+        ),
+        (
+            "?*?",
+            &[
+                "[]", "[!]", "x.", "xy", "a*b", "a\\b", "\\]", ":]", "d]", "a[",
+            ],
+        ),
+        ("[", &["["]),
+        ("a[", &["a["]),
+        ("[a-c-e]", &["a", "b", "c", "-", "e"]),
+        (
+            "[!-a]",
+            &["]", "[", "!", "^", "b", "c", "/", "\\", "e", "*", "?"],
+        ),
+        ("[*]", &["*"]),
+        ("[?]", &["?"]),
+    ];
+
+    #[test]
+    fn fnmatch_agrees_with_cpython() {
+        for (pattern, expected) in FNMATCH_CASES {
+            for text in FNMATCH_TEXTS {
+                assert_eq!(
+                    expected.contains(&text),
+                    is_wildcard_match_fnmatch(text, pattern),
+                    "{:?} against {:?}",
+                    pattern,
+                    text
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fnmatch_keeps_other_options() {
+        let options = MatchOptions::new().fnmatch(true).case_insensitive(true);
+        assert_eq!(
+            true,
+            is_wildcard_match_with("MAIN.RS", "[!]]*.rs", &options)
+        );
+        assert_eq!(false, is_wildcard_match_fnmatch("MAIN.RS", "[!]]*.rs"));
+    }
+
+    #[test]
+    fn sql_style_wildcards() {
+        let options = MatchOptions::new().wildcards('%', '_').unwrap();
+        let matches = |text, pattern| is_wildcard_match_with(text, pattern, &options);
+        assert_eq!(true, matches("abc", "a%"));
+        assert_eq!(true, matches("abc", "_b_"));
+        assert_eq!(false, matches("abc", "a_"));
+        assert_eq!(true, matches("a*", "a*"));
+        assert_eq!(false, matches("ab", "a*"));
+        assert_eq!(true, matches("a?c", "a?%"));
+        assert_eq!(true, matches("100%", "1%\\%"));
+        assert_eq!(false, matches("1000", "1%\\%"));
+        assert_eq!(true, matches("abc", "[a-c]%"));
+        assert_eq!(
+            Some(vec![String::from("b")]),
+            WildcardPattern::with_options("a%c", &options).captures("abc")
+        );
+    }
+
+    #[test]
+    fn sql_style_wildcards_in_path_mode() {
+        let options = MatchOptions::new()
+            .separator('/')
+            .wildcards('%', '_')
+            .unwrap();
+        let pattern = WildcardPattern::with_options("src/%%/%.rs", &options);
+        assert_eq!(true, pattern.matches("src/a/b/main.rs"));
+        assert_eq!(false, pattern.matches("lib/main.rs"));
+        assert_eq!(
+            false,
+            is_wildcard_match_with("src/a/b.rs", "src/%.rs", &options)
+        );
+    }
+
+    #[test]
+    fn sql_style_wildcards_are_written_back() {
+        let options = MatchOptions::new().wildcards('%', '_').unwrap();
+        let pattern = WildcardPattern::with_options("a%%_*\\%", &options);
+        assert_eq!("a%_*\\%", pattern.to_string());
+    }
+
+    #[test]
+    fn wildcards_have_to_differ() {
+        assert_eq!(
+            Err(PatternError::SameWildcards { wildcard: '%' }),
+            MatchOptions::new().wildcards('%', '%')
+        );
+        assert_eq!(
+            "'%' can't be both of the wildcards",
+            PatternError::SameWildcards { wildcard: '%' }.to_string()
+        );
+    }
+
+    #[test]
+    fn wildcards_cant_already_mean_something() {
+        let reserved = |options: MatchOptions, any_run, any_one, wildcard| {
+            assert_eq!(
+                Err(PatternError::ReservedWildcard { wildcard }),
+                options.wildcards(any_run, any_one)
+            );
+        };
+        reserved(MatchOptions::new(), '\\', '_', '\\');
+        reserved(MatchOptions::new(), '%', '[', '[');
+        reserved(MatchOptions::new(), ']', '_', ']');
+        reserved(MatchOptions::new().braces(true), '{', '_', '{');
+        reserved(MatchOptions::new().braces(true), '%', '}', '}');
+        reserved(MatchOptions::new().braces(true), ',', '_', ',');
+        reserved(MatchOptions::new(), '|', '_', '|');
+        reserved(MatchOptions::new().separator('/'), '%', '/', '/');
+
+        // Only meanings that the options give are reserved
+        let options = MatchOptions::new().escapes(false).wildcards('\\', '_');
+        assert_eq!(
+            true,
+            is_wildcard_match_with("[ab", "\\ab", &options.unwrap())
+        );
+        assert_eq!(true, MatchOptions::new().wildcards('{', ',').is_ok());
+        assert_eq!(
+            true,
+            MatchOptions::new()
+                .alternation(false)
+                .wildcards('|', '_')
+                .is_ok()
+        );
+        assert_eq!(true, MatchOptions::new().wildcards('%', '/').is_ok());
+        assert_eq!(
+            "'[' already means something in the pattern, so it can't be a wildcard",
+            PatternError::ReservedWildcard { wildcard: '[' }.to_string()
+        );
+    }
+
+    #[test]
+    fn windows_paths() {
+        let options = MatchOptions::windows_paths();
+        let matches = |text, pattern| is_wildcard_match_with(text, pattern, &options);
+        assert_eq!(true, matches("c:\\users\\alice", "C:\\Users\\*"));
+        assert_eq!(false, matches("c:\\users\\alice\\x", "C:\\Users\\*"));
+        assert_eq!(true, matches("C:\\Users\\a\\b.TXT", "c:\\users\\**\\*.txt"));
+        assert_eq!(false, matches("c:/users/alice", "C:\\Users\\*"));
+    }
+
+    #[test]
+    fn windows_paths_with_forward_slashes() {
+        let options = MatchOptions::windows_paths().forward_slashes(true);
+        let matches = |text, pattern| is_wildcard_match_with(text, pattern, &options);
+        assert_eq!(true, matches("c:/users/alice", "C:\\Users\\*"));
+        assert_eq!(true, matches("c:\\users/alice", "C:/Users\\*"));
+        assert_eq!(false, matches("c:/users/alice/x", "C:\\Users\\*"));
+        assert_eq!(
+            Some(vec![String::from("a\\"), String::from("b")]),
+            WildcardPattern::with_options("src/**/*.rs", &options).captures("src/a/b.rs")
+        );
+    }
+
+    #[test]
+    fn path_captures() {
+        let pattern = WildcardPattern::path("src/**/*.rs", '/');
+        assert_eq!(
+            Some(vec![String::from("a/b/"), String::from("c")]),
+            pattern.captures("src/a/b/c.rs")
+        );
+        assert_eq!(
+            Some(vec![String::new(), String::from("main")]),
+            pattern.captures("src/main.rs")
+        );
+        assert_eq!(None, pattern.captures("src/a/b/c.py"));
+    }
+
+    /// A slow but simple recursive matcher to check the path tokens against
+    fn reference_match(pattern: &[Token], text: &[&str]) -> bool {
+        let is = |unit: &&str, separator: &char| unit.scalar() == Some(*separator);
+        match pattern.first() {
+            None => text.is_empty(),
+            Some(Token::AnyRun) => {
+                (0..=text.len()).any(|k| reference_match(&pattern[1..], &text[k..]))
+            }
+            Some(Token::AnyRunInSegment(separator)) => {
+                let segment = text.iter().take_while(|unit| !is(unit, separator)).count();
+                (0..=segment).any(|k| reference_match(&pattern[1..], &text[k..]))
+            }
+            Some(Token::AnySegments(separator)) => (0..=text.len())
+                .filter(|k| *k == 0 || is(&text[k - 1], separator))
+                .any(|k| reference_match(&pattern[1..], &text[k..])),
+            Some(token) => match text.first() {
+                Some(unit) => token.matches_one(unit) && reference_match(&pattern[1..], &text[1..]),
+                None => false,
+            },
+        }
+    }
+
+    #[test]
+    fn path_matching_agrees_with_reference() {
+        let mut random = Random(0xd1b5_4a32_d192_ed03);
+        let pattern_alphabet = ["a", "b", "/", "*", "**", "?", "[!a]", "[/b]"];
+        let text_alphabet = ["a", "b", "/"];
+        for _ in 0..5_000 {
+            let pattern = random.string(&pattern_alphabet, 6);
+            let text = random.string(&text_alphabet, 8);
+
+            let compiled = WildcardPattern::path(&pattern, '/');
+            let text_graphemes = text.graphemes(true).collect::<Vec<&str>>();
+            let expected = reference_match(compiled.tokens(), &text_graphemes);
+            assert_eq!(
+                expected,
+                compiled.matches(&text),
+                "{:?} against {:?}",
+                pattern,
+                text
+            );
+            assert_eq!(expected, compiled.captures(&text).is_some());
+        }
+    }
+
+    /// An exponential but obviously correct matcher, which works straight on the graphemes
+    /// of the pattern, to check the parser and the matcher against
+    fn brute_force_match(text: &[&str], pattern: &[&str]) -> bool {
+        match pattern {
+            [] => text.is_empty(),
+            ["*", rest @ ..] => (0..=text.len()).any(|k| brute_force_match(&text[k..], rest)),
+            ["?", rest @ ..] => !text.is_empty() && brute_force_match(&text[1..], rest),
+            ["\\", escaped, rest @ ..] => {
+                text.first() == Some(escaped) && brute_force_match(&text[1..], rest)
+            }
+            [literal, rest @ ..] => {
+                text.first() == Some(literal) && brute_force_match(&text[1..], rest)
+            }
+        }
+    }
+
+    /// The graphemes the brute force comparison builds its patterns and texts from
+    const BRUTE_FORCE_ALPHABET: [&str; 6] = ["a", "b", "e\u{301}", "*", "?", "\\"];
+
+    /// Strings of up to eight graphemes of `BRUTE_FORCE_ALPHABET`, which proptest shrinks
+    /// by dropping graphemes and moving them earlier in the alphabet
+    fn brute_force_strings() -> impl Strategy<Value = String> {
+        proptest::collection::vec(proptest::sample::select(&BRUTE_FORCE_ALPHABET[..]), 0..=8)
+            .prop_map(|graphemes| graphemes.concat())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(10_000))]
+
+        #[test]
+        fn matching_agrees_with_brute_force(
+            pattern in brute_force_strings(),
+            text in brute_force_strings(),
+        ) {
+            let expected = brute_force_match(
+                &text.graphemes(true).collect::<Vec<&str>>(),
+                &pattern.graphemes(true).collect::<Vec<&str>>(),
+            );
+            prop_assert_eq!(expected, is_wildcard_match(&text, &pattern));
+            prop_assert_eq!(expected, WildcardPattern::new(&pattern).matches(&text));
+            prop_assert_eq!(expected, captures(&text, &pattern).is_some());
+        }
+    }
+
+    #[test]
+    fn prefixes_of_matching_texts() {
+        let pattern = WildcardPattern::new("abc*");
+        assert_eq!(true, pattern.matches_prefix_of("ab"));
+        assert_eq!(false, pattern.matches_prefix_of("ax"));
+        assert_eq!(true, pattern.matches_prefix_of(""));
+        assert_eq!(true, pattern.matches_prefix_of("abcxyz"));
+
+        let pattern = WildcardPattern::new("a?[0-9]");
+        assert_eq!(true, pattern.matches_prefix_of("ax"));
+        assert_eq!(true, pattern.matches_prefix_of("ax5"));
+        assert_eq!(false, pattern.matches_prefix_of("axx"));
+        assert_eq!(false, pattern.matches_prefix_of("ax55"));
+        assert_eq!(false, WildcardPattern::new("a*[]").matches_prefix_of("ab"));
+    }
+
+    #[test]
+    fn prefixes_of_path_and_brace_matches() {
+        let pattern = WildcardPattern::path("src/**/*.rs", '/');
+        assert_eq!(true, pattern.matches_prefix_of("src/a"));
+        assert_eq!(true, pattern.matches_prefix_of("src/a/b/main.r"));
+        assert_eq!(false, pattern.matches_prefix_of("lib/a"));
+
+        let options = MatchOptions::new().braces(true);
+        let pattern = WildcardPattern::with_options("x{abc,de}y", &options);
+        assert_eq!(true, pattern.matches_prefix_of("xab"));
+        assert_eq!(true, pattern.matches_prefix_of("xdey"));
+        assert_eq!(false, pattern.matches_prefix_of("xac"));
+        assert_eq!(false, pattern.matches_prefix_of("xdeyz"));
+    }
+
+    #[test]
+    fn matches_within_distance_agrees_with_brute_force() {
+        let mut random = Random(0x510e_527f_ade6_82d1);
+        let alphabet = ["a", "b", "*", "?"];
+        for _ in 0..2_000 {
+            let pattern = random.string(&alphabet, 6);
+            let text = random.string(&["a", "b"], 5);
+            // Every text one substitution, left out or added grapheme away
+            let mut edited = vec![text.clone()];
+            for i in 0..=text.len() {
+                for c in ["a", "b"] {
+                    edited.push(format!("{}{}{}", &text[..i], c, &text[i..]));
+                    if i < text.len() {
+                        edited.push(format!("{}{}{}", &text[..i], c, &text[i + 1..]));
+                    }
+                }
+                if i < text.len() {
+                    edited.push(format!("{}{}", &text[..i], &text[i + 1..]));
+                }
+            }
+            let compiled = WildcardPattern::new(&pattern);
+            assert_eq!(
+                compiled.matches(&text),
+                compiled.matches_within_distance(&text, 0),
+                "{:?} against {:?}",
+                pattern,
+                text
+            );
+            assert_eq!(
+                edited.iter().any(|text| compiled.matches(text)),
+                compiled.matches_within_distance(&text, 1),
+                "{:?} against {:?}",
+                pattern,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn approximate_matches_by_graphemes() {
+        assert_eq!(true, is_wildcard_match_approx("colour", "color", 1));
+        assert_eq!(false, is_wildcard_match_approx("colour", "color", 0));
+        // A grapheme made of several scalars is still one edit
+        assert_eq!(
+            true,
+            is_wildcard_match_approx("cafe\u{301}.txt", "cafe.*", 1)
+        );
+        let scalars = MatchOptions::new().granularity(Granularity::Scalars);
+        let pattern = WildcardPattern::with_options("cafe.*", &scalars);
+        assert_eq!(false, pattern.matches_within_distance("cafe\u{301}.txt", 0));
+        assert_eq!(true, pattern.matches_within_distance("cafe\u{301}.txt", 1));
+        assert_eq!(true, pattern.matches_within_distance("caf.txt", 1));
+        // Each alternative of a group is edited on its own
+        let braces = WildcardPattern::with_options("{cat,dog}s", &MatchOptions::new().braces(true));
+        assert_eq!(true, braces.matches_within_distance("cogs", 1));
+        assert_eq!(false, braces.matches_within_distance("cows", 1));
+    }
+
+    #[test]
+    fn prefixes_agree_with_brute_force() {
+        let mut random = Random(0xbb67_ae85_84ca_a73b);
+        let alphabet = ["a", "b", "*", "?"];
+        let text_alphabet = ["a", "b"];
+        // Every way of going on with the text that could matter, since a pattern of six
+        // graphemes can't need more than six more of them
+        let completions = (0..=6)
+            .flat_map(|len| {
+                (0..1 << len).map(move |bits: u32| {
+                    (0..len)
+                        .map(|i| if bits & (1 << i) == 0 { 'a' } else { 'b' })
+                        .collect::<String>()
+                })
+            })
+            .collect::<Vec<String>>();
+        for _ in 0..2_000 {
+            let pattern = random.string(&alphabet, 6);
+            let text = random.string(&text_alphabet, 5);
+            let expected = completions
+                .iter()
+                .any(|completion| is_wildcard_match(&(text.clone() + completion), &pattern));
+            assert_eq!(
+                expected,
+                WildcardPattern::new(&pattern).matches_prefix_of(&text),
+                "{:?} against {:?}",
+                pattern,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn bytes_match() {
+        assert_eq!(true, is_wildcard_match_bytes(b"alyib", b"a?y*b"));
+        assert_eq!(false, is_wildcard_match_bytes(b"abcd", b"a*b?"));
+    }
 
-    // Try to preprocess the pattern
-    // let (pattern_graphemes, text_graphemes) = preprocessing(pattern_graphemes, text_graphemes);
+    #[test]
+    fn bytes_match_invalid_utf8() {
+        assert_eq!(
+            true,
+            is_wildcard_match_bytes(b"file\xff\xfe.txt", b"file*.txt")
+        );
+        assert_eq!(true, is_wildcard_match_bytes(b"\xc3", b"?"));
+        assert_eq!(true, is_wildcard_match_bytes(b"\xff", b"\xff"));
+        assert_eq!(false, is_wildcard_match_bytes(b"\xfe", b"\xff"));
+    }
 
-    // Otherwise start our dynamic programming matching
-    match_with_cache(&pattern_graphemes, &text_graphemes)
-}
+    #[test]
+    fn bytes_questionmark_matches_one_byte() {
+        // `é` is two bytes long in UTF-8
+        assert_eq!(false, is_wildcard_match_bytes("é".as_bytes(), b"?"));
+        assert_eq!(true, is_wildcard_match_bytes("é".as_bytes(), b"??"));
+    }
 
-#[pymodule]
-fn pyglob(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
-    m.add_function(wrap_pyfunction!(is_wildcard_match, m)?)?;
-    Ok(())
-}
+    #[test]
+    fn bytes_classes() {
+        assert_eq!(true, is_wildcard_match_bytes(b"\x90", b"[\x80-\xff]"));
+        assert_eq!(false, is_wildcard_match_bytes(b"a", b"[\x80-\xff]"));
+        assert_eq!(true, is_wildcard_match_bytes(b"a", b"[!\x80-\xff]"));
+        assert_eq!(true, is_wildcard_match_bytes(b"file5", b"file[0-9]"));
+    }
 
-/// This applies preprocessing to the pattern to speed up matching
-fn preprocessing<'a, 'b>(
-    pattern: Vec<&'a str>,
-    text: Vec<&'b str>,
-) -> (Vec<&'a str>, Vec<&'b str>) {
-    let pattern = remove_duplicate_stars(pattern);
-    remove_matching_start_and_end(pattern, text)
-}
+    #[test]
+    fn bytes_escapes() {
+        assert_eq!(true, is_wildcard_match_bytes(b"a*b", b"a\\*b"));
+        assert_eq!(false, is_wildcard_match_bytes(b"axb", b"a\\*b"));
+    }
+
+    #[test]
+    fn match_any_matches_one_pattern() {
+        assert_eq!(true, match_any("main.rs", &["*.py", "*.rs", "*.txt"]));
+        assert_eq!(false, match_any("main.c", &["*.py", "*.rs", "*.txt"]));
+    }
+
+    #[test]
+    fn match_any_without_patterns() {
+        assert_eq!(false, match_any("main.rs", &[]));
+        assert_eq!(false, match_any("", &[]));
+    }
+
+    #[test]
+    fn validate_reports_first_error() {
+        assert_eq!(Ok(()), validate("*.[ch]"));
+        assert_eq!(
+            Err(PatternError::UnterminatedClass { pos: 1 }),
+            validate("a[b\\")
+        );
+        assert_eq!(
+            Err(PatternError::DanglingEscape { pos: 3 }),
+            validate("[a]\\")
+        );
+    }
+
+    #[test]
+    fn pattern_error_messages() {
+        assert_eq!(
+            "unterminated class starting at position 4",
+            PatternError::UnterminatedClass { pos: 4 }.to_string()
+        );
+        assert_eq!(
+            "dangling escape at position 0",
+            PatternError::DanglingEscape { pos: 0 }.to_string()
+        );
+    }
+
+    #[test]
+    fn first_match_returns_lowest_index() {
+        let patterns = ["*.txt", "main.*", "*.rs", "*"];
+        assert_eq!(Some(1), first_match("main.rs", &patterns));
+        assert_eq!(Some(2), first_match("lib.rs", &patterns));
+        assert_eq!(Some(3), first_match("Cargo.lock", &patterns));
+    }
+
+    #[test]
+    fn first_match_without_a_match() {
+        assert_eq!(None, first_match("main.c", &["*.py", "*.rs"]));
+        assert_eq!(None, first_match("main.rs", &[]));
+    }
+
+    #[test]
+    fn matching_patterns_keeps_every_match_in_order() {
+        let patterns = ["*", "*.txt", "main.*", "*.rs", "m*n.r?", "lib.rs"];
+        assert_eq!(
+            vec!["*", "main.*", "*.rs", "m*n.r?"],
+            matching_patterns("main.rs", &patterns)
+        );
+        assert_eq!(
+            vec!["*", "*.rs", "lib.rs"],
+            matching_patterns("lib.rs", &patterns)
+        );
+        assert_eq!(vec!["*"], matching_patterns("Cargo.lock", &patterns));
+    }
+
+    #[test]
+    fn matching_patterns_without_a_match() {
+        let empty: Vec<&str> = vec![];
+        assert_eq!(empty, matching_patterns("main.c", &["*.py", "*.rs"]));
+        assert_eq!(empty, matching_patterns("main.rs", &[]));
+    }
+
+    #[test]
+    fn match_each_lines_up_with_the_patterns() {
+        let patterns = ["*", "*.txt", "main.*", "*.rs", "lib.rs"];
+        assert_eq!(
+            vec![true, false, true, true, false],
+            match_each("main.rs", &patterns)
+        );
+        assert_eq!(
+            vec![true, false, false, true, true],
+            match_each("lib.rs", &patterns)
+        );
+        assert_eq!(Vec::<bool>::new(), match_each("main.rs", &[]));
+    }
+
+    #[test]
+    fn matches_at_least_one_of_each_group() {
+        let rule: &[&[&str]] = &[&["*.rs", "*.toml"], &["src/*", "lib/*"]];
+        assert_eq!(true, matches_at_least_one_of_each("src/main.rs", rule));
+        assert_eq!(true, matches_at_least_one_of_each("lib/Cargo.toml", rule));
+        // A source file, but outside of both directories
+        assert_eq!(false, matches_at_least_one_of_each("tests/main.rs", rule));
+        assert_eq!(false, matches_at_least_one_of_each("src/main.py", rule));
+        assert_eq!(false, matches_at_least_one_of_each("src/main.rs", &[&[]]));
+        assert_eq!(true, matches_at_least_one_of_each("src/main.rs", &[]));
+    }
+
+    #[test]
+    fn matches_filtered_excludes_win() {
+        let includes = ["*.rs", "*.toml"];
+        let excludes = ["target/*", "*_test.rs"];
+        assert_eq!(true, matches_filtered("src/main.rs", &includes, &excludes));
+        assert_eq!(true, matches_filtered("Cargo.toml", &includes, &excludes));
+        assert_eq!(false, matches_filtered("src/main.py", &includes, &excludes));
+        // Both an include and an exclude match
+        assert_eq!(
+            false,
+            matches_filtered("src/main_test.rs", &includes, &excludes)
+        );
+        assert_eq!(
+            false,
+            matches_filtered("target/build.rs", &includes, &excludes)
+        );
+        // Nothing to include means everything is, and nothing to exclude means nothing is
+        assert_eq!(true, matches_filtered("notes.txt", &[], &excludes));
+        assert_eq!(false, matches_filtered("target/notes.txt", &[], &excludes));
+        assert_eq!(true, matches_filtered("src/main_test.rs", &includes, &[]));
+        assert_eq!(true, matches_filtered("anything", &[], &[]));
+    }
+
+    #[test]
+    fn dedup_patterns_collapses_runs_of_stars() {
+        assert_eq!(vec!["*"], dedup_patterns(&["**", "*", "***"]));
+        assert_eq!(
+            vec!["*.rs", "a?", "[ab]", "[ba]"],
+            dedup_patterns(&["*.rs", "a?", "***.rs", "[ab]", "[ba]", "a?"])
+        );
+        assert_eq!(vec!["x"], dedup_patterns(&["\\x", "x"]));
+        assert_eq!(vec!["?"], dedup_patterns(&["[!]", "?"]));
+        assert_eq!(vec!["a?*", "*"], dedup_patterns(&["a*?", "a?*", "*"]));
+        assert_eq!(Vec::<String>::new(), dedup_patterns(&[]));
+    }
 
-/// Remove any duplicate stars, because they do not impact the matching
-fn remove_duplicate_stars(mut pattern: Vec<&str>) -> Vec<&str> {
-    let mut i: usize = 1;
-    while i < pattern.len() {
-        if pattern[i] == "*" && pattern[i - 1] == "*" {
-            pattern.remove(i);
-        } else {
-            i += 1;
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_filter_agrees_with_filter() {
+        let mut random = Random(0x510e_527f_ade6_82d1);
+        let alphabet = ["a", "b", ".", "/", "e\u{301}"];
+        let names = (0..20_000)
+            .map(|_| random.string(&alphabet, 8))
+            .collect::<Vec<String>>();
+        let names = names.iter().map(String::as_str).collect::<Vec<&str>>();
+        for pattern in ["*", "a*b", "*.?", "[!a]*e\u{301}", "", "*/*/*"] {
+            assert_eq!(
+                filter(&names, pattern),
+                par_filter(&names, pattern),
+                "{}",
+                pattern
+            );
         }
     }
-    pattern
-}
 
-/// If the start and end of two strings match, we can pre-emptively strip them
-fn remove_matching_start_and_end<'a, 'b>(
-    mut pattern: Vec<&'a str>,
-    mut text: Vec<&'b str>,
-) -> (Vec<&'a str>, Vec<&'b str>) {
-    // Remove matching items from the start
-    let mut i: usize = 0;
-    while (
-        i < pattern.len() && i < text.len()
-        // Check that we're not at the end of the string
-    ) && ((
-        pattern[i] == text[i] && text[i] != "*"
-        // Check if the characters match
-    ) || (
-        pattern[i] == "?"
-        // Or check if the pattern is a question mark
-    )) {
-        pattern.remove(i);
-        text.remove(i);
-        i += 1;
+    #[test]
+    fn match_all_needs_every_pattern() {
+        assert_eq!(true, match_all("src/main.rs", &["src/*", "*.rs", "*main*"]));
+        assert_eq!(
+            false,
+            match_all("src/main.py", &["src/*", "*.rs", "*main*"])
+        );
     }
-    if pattern.len() == 0 || text.len() == 0 {
-        return (pattern, text);
-    }
-
-    // Remove matching items from the end
-    let mut i: usize = pattern.len() - 1;
-    let mut j: usize = text.len() - 1;
-    while (i > 0 && j > 0)
-        && ((
-            pattern[i] == text[j] && text[j] != "*"
-            // Check if the characters match
-        ) || (
-            pattern[i] == "?"
-            // Or check if the pattern is a question mark
-        ))
-    {
-        pattern.remove(i);
-        text.remove(j);
-        i -= 1;
-        j -= 1;
+
+    #[test]
+    fn match_all_without_patterns() {
+        assert_eq!(true, match_all("main.rs", &[]));
+        assert_eq!(true, match_all("", &[]));
+    }
+
+    #[test]
+    fn default_options_are_unchanged() {
+        let mut random = Random(0x853c_49e6_748f_ea9b);
+        let pattern_alphabet = ["a", "B", "*", "?", "[a-c]", "[!a]", "\\", "\u{e9}"];
+        let text_alphabet = ["a", "b", "B", "\\", "e\u{301}", "\u{e9}"];
+        for _ in 0..2_000 {
+            let pattern = random.string(&pattern_alphabet, 6);
+            let text = random.string(&text_alphabet, 6);
+            assert_eq!(
+                is_wildcard_match(&text, &pattern),
+                is_wildcard_match_with(&text, &pattern, &MatchOptions::default()),
+                "{:?} against {:?}",
+                pattern,
+                text
+            );
+        }
+        assert_eq!(MatchOptions::new(), MatchOptions::default());
     }
-    (pattern, text)
-}
 
-fn match_with_cache(pattern: &Vec<&str>, text: &Vec<&str>) -> bool {
-    // Create a cache
-    let mut cache: HashMap<(usize, usize), bool> = HashMap::new();
+    #[test]
+    fn options_can_be_combined() {
+        let options = MatchOptions::new()
+            .case_insensitive(true)
+            .normalization(Normalization::Nfc)
+            .separator('/');
+        assert_eq!(
+            true,
+            is_wildcard_match_with("Caf\u{e9}/A.TXT", "cafe\u{301}/*.txt", &options)
+        );
+        assert_eq!(
+            false,
+            is_wildcard_match_with("Caf\u{e9}/x/A.TXT", "cafe\u{301}/*.txt", &options)
+        );
+        assert_eq!(
+            true,
+            is_wildcard_match_with("Caf\u{e9}/x/A.TXT", "cafe\u{301}/**/*.txt", &options)
+        );
+    }
 
-    // Set the starting position where both strings are empty as `true`
-    cache.insert((1, 1), true);
+    #[test]
+    fn options_without_escapes() {
+        let options = MatchOptions::new().escapes(false);
+        assert_eq!(
+            true,
+            is_wildcard_match_with("dir\\file", "dir\\*", &options)
+        );
+        assert_eq!(false, is_wildcard_match_with("*", "\\*", &options));
+        assert_eq!(
+            true,
+            is_wildcard_match_with("*", "\\*", &MatchOptions::new())
+        );
+    }
 
-    set_cache(&mut cache, pattern, text, pattern.len() + 1, text.len() + 1);
-    *cache.get(&(pattern.len() + 1, text.len() + 1)).unwrap()
-}
+    #[test]
+    fn backslash_separator_turns_off_escapes() {
+        let options = MatchOptions::new().separator('\\');
+        assert_eq!(true, is_wildcard_match_with("a\\b", "a\\*", &options));
+        assert_eq!(false, is_wildcard_match_with("a\\b\\c", "a\\*", &options));
+    }
 
-/// A dynamic solution to the pattern matching, with the help of this video:
-/// https://www.youtube.com/watch?v=3ZDZ-N0EPV0
-///
-/// `row` and `column` indexes are indexed by 1, so that we can use 0 as a "border"
-fn set_cache(
-    cache: &mut HashMap<(usize, usize), bool>,
-    pattern: &Vec<&str>,
-    text: &Vec<&str>,
-    row: usize,
-    column: usize,
-) {
-    // If we already have the item in the cache, return
-    if cache.contains_key(&(row, column)) {
-        return;
+    #[test]
+    fn try_match_within_budget() {
+        let options = MatchOptions::new().budget(Some(6));
+        assert_eq!(Ok(true), try_match("abc", "a*", &options));
+        assert_eq!(Ok(false), try_match("abc", "b*", &options));
+        assert_eq!(Ok(true), try_match("", "*", &options));
     }
 
-    if row == 0 {
-        cache.insert((row, column), false);
-        return;
+    #[test]
+    fn try_match_over_budget() {
+        let options = MatchOptions::new().budget(Some(6));
+        assert_eq!(
+            Err(PatternError::OverBudget { cost: 8, budget: 6 }),
+            try_match("abcd", "a*", &options)
+        );
+        // Graphemes are counted, not bytes
+        assert_eq!(Ok(true), try_match("e\u{301}\u{e9}", "?*", &options));
+        assert_eq!(
+            Err(PatternError::OverBudget { cost: 9, budget: 6 }),
+            try_match("\u{e9}\u{e9}\u{e9}", "?*?", &options)
+        );
     }
-    if column == 0 {
-        cache.insert((row, column), false);
-        return;
+
+    #[test]
+    fn try_match_default_and_disabled_budget() {
+        let text = "a".repeat(10_000);
+        let pattern = "a*".repeat(1_000);
+        assert_eq!(
+            Err(PatternError::OverBudget {
+                cost: 20_000_000,
+                budget: MatchOptions::DEFAULT_BUDGET
+            }),
+            try_match(&text, &pattern, &MatchOptions::new())
+        );
+        let unlimited = MatchOptions::new().budget(None);
+        assert_eq!(Ok(true), try_match(&text, &pattern, &unlimited));
+        // Matching which can't fail ignores the budget
+        assert_eq!(
+            true,
+            is_wildcard_match_with(&text, &pattern, &MatchOptions::new())
+        );
     }
 
-    // Get character of the pattern at the current row
-    let pattern_char = if row == 1 { "" } else { pattern[row - 2] };
+    #[test]
+    fn over_budget_message() {
+        assert_eq!(
+            "matching would cost 12 steps, over the budget of 10",
+            PatternError::OverBudget {
+                cost: 12,
+                budget: 10
+            }
+            .to_string()
+        );
+    }
 
-    // Get the character of the text at the current column
-    let text_char = if column == 1 { "" } else { text[column - 2] };
+    #[test]
+    fn braces_match_any_alternative() {
+        let pattern = "image.{jpg,png,gif}";
+        assert_eq!(true, is_wildcard_match_braces("image.jpg", pattern));
+        assert_eq!(true, is_wildcard_match_braces("image.gif", pattern));
+        assert_eq!(false, is_wildcard_match_braces("image.bmp", pattern));
+        assert_eq!(false, is_wildcard_match_braces("image.", pattern));
+        assert_eq!(false, is_wildcard_match("image.jpg", pattern));
+        assert_eq!(true, is_wildcard_match("image.{jpg,png,gif}", pattern));
+    }
 
-    // If the patter character matches the text character, take the value from the top left
-    if (pattern_char == text_char && text_char != "*") || pattern_char == "?" {
-        set_cache(cache, pattern, text, row - 1, column - 1);
-        // Copy the value from the top left
-        cache.insert(
-            (row, column),
-            *cache.get(&(row - 1, column - 1)).unwrap_or(&false),
+    #[test]
+    fn braces_with_wildcards() {
+        assert_eq!(
+            true,
+            is_wildcard_match_braces("src/main.rs", "{*.py,src/*}")
         );
-        return;
+        assert_eq!(true, is_wildcard_match_braces("a.py", "{*.py,src/*}"));
+        assert_eq!(true, is_wildcard_match_braces("ab", "{?,??}"));
+        assert_eq!(false, is_wildcard_match_braces("abc", "{?,??}"));
+        assert_eq!(true, is_wildcard_match_braces("x5", "{x,y}[0-9]"));
     }
 
-    // If the pattern character is a star, then take a value from above or the left
-    if pattern_char == "*" {
-        set_cache(cache, pattern, text, row - 1, column);
-        let left = cache.get(&(row - 1, column)).unwrap_or(&false);
-        if *left == true {
-            cache.insert((row, column), true);
-            return;
+    #[test]
+    fn nested_braces() {
+        let pattern = "{a{b,c},d}e";
+        for text in ["abe", "ace", "de"] {
+            assert_eq!(true, is_wildcard_match_braces(text, pattern), "{}", text);
         }
+        for text in ["ae", "be", "ade", "abce"] {
+            assert_eq!(false, is_wildcard_match_braces(text, pattern), "{}", text);
+        }
+    }
 
-        set_cache(cache, pattern, text, row, column - 1);
-        let right = cache.get(&(row, column - 1)).unwrap_or(&false);
-        if *right == true {
-            cache.insert((row, column), true);
-            return;
+    #[test]
+    fn adjacent_braces() {
+        let pattern = "{a,b}{c,d}";
+        for text in ["ac", "ad", "bc", "bd"] {
+            assert_eq!(true, is_wildcard_match_braces(text, pattern), "{}", text);
         }
+        assert_eq!(false, is_wildcard_match_braces("ab", pattern));
+        assert_eq!(false, is_wildcard_match_braces("a", pattern));
     }
 
-    // If the strings don't match, and no wildcards matched, then this field is not a match.
-    cache.insert((row, column), false);
-}
+    #[test]
+    fn empty_alternatives() {
+        assert_eq!(true, is_wildcard_match_braces("file", "file{,.bak}"));
+        assert_eq!(true, is_wildcard_match_braces("file.bak", "file{,.bak}"));
+        assert_eq!(true, is_wildcard_match_braces("ab", "a{}b"));
+        assert_eq!(true, is_wildcard_match_braces("", "{,}"));
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn unmatched_braces_are_literal() {
+        assert_eq!(true, is_wildcard_match_braces("a{b", "a{b"));
+        assert_eq!(true, is_wildcard_match_braces("a}b,c", "a}b,c"));
+        assert_eq!(true, is_wildcard_match_braces("{ab", "{a{b,c}"));
+        assert_eq!(true, is_wildcard_match_braces("a,b", "{a\\,b}"));
+        assert_eq!(false, is_wildcard_match_braces("a", "{a\\,b}"));
+        assert_eq!(true, is_wildcard_match_braces("{a}", "\\{a}"));
+        // The comma inside the class doesn't split the group
+        assert_eq!(true, is_wildcard_match_braces(",", "{[,],x}"));
+    }
 
     #[test]
-    fn simple_match() {
-        assert_eq!(true, is_wildcard_match("alyib", "a?y*b"));
+    fn braces_with_paths() {
+        let options = MatchOptions::new().braces(true).separator('/');
+        let pattern = "src/{**/*.rs,*.toml}";
+        assert_eq!(
+            true,
+            is_wildcard_match_with("src/a/b.rs", pattern, &options)
+        );
+        assert_eq!(
+            true,
+            is_wildcard_match_with("src/Cargo.toml", pattern, &options)
+        );
+        assert_eq!(
+            false,
+            is_wildcard_match_with("src/a/Cargo.toml", pattern, &options)
+        );
     }
 
     #[test]
-    fn simple_non_match() {
-        assert_eq!(false, is_wildcard_match("abcd", "a*b?"))
+    fn find_spans() {
+        assert_eq!(Some((2, 5)), find("xxabcxx", "abc"));
+        assert_eq!(Some((1, 6)), find("abcdbd!", "b*d"));
+        assert_eq!(Some((0, 3)), find("abc", "*"));
+        assert_eq!(Some((0, 0)), find("", "*"));
+        assert_eq!(Some((0, 0)), find("abc", ""));
+        assert_eq!(Some((0, 1)), find("abc", "?"));
+        assert_eq!(None, find("abc", "x*"));
+        assert_eq!(None, find("", "?"));
     }
 
     #[test]
-    fn test_kanji_characters() {
-        assert_eq!(true, is_wildcard_match("漢字", "??"))
+    fn find_prefers_leftmost_then_longest() {
+        assert_eq!(Some((1, 3)), find("xaaay", "a|aa"));
+        assert_eq!(Some((1, 4)), find("xaaay", "a*a"));
+        assert_eq!(Some((0, 2)), find("abab", "b|ab"));
     }
 
     #[test]
-    fn empty_string_matches_star() {
-        assert_eq!(true, is_wildcard_match("", "*"));
+    fn find_gives_byte_offsets() {
+        // The é is two bytes, and the e with a combining accent three
+        assert_eq!(Some((0, 8)), find("\u{e9}-e\u{301}-x", "?-*"));
+        assert_eq!(Some((3, 6)), find("\u{e9}-e\u{301}-x", "e\u{301}"));
     }
 
     #[test]
-    fn empty_string_doesnt_match_questionmark() {
-        assert_eq!(false, is_wildcard_match("", "?"));
+    fn find_with_paths() {
+        let pattern = WildcardPattern::path("*/**/b", '/');
+        assert_eq!(Some((0, 7)), pattern.find("x/y/z/b/c"));
+        assert_eq!(Some((0, 2)), WildcardPattern::path("*", '/').find("xy/z"));
     }
 
     #[test]
-    fn empty_string_matches_multiple_stars() {
-        assert_eq!(true, is_wildcard_match("", "***"));
+    fn find_agrees_with_brute_force() {
+        let mut random = Random(0x9b05_688c_2b3e_6c1f);
+        let pattern_alphabet = ["a", "b", "*", "?", "[ab]", "|"];
+        let text_alphabet = ["a", "b", "c"];
+        for _ in 0..3_000 {
+            let pattern = random.string(&pattern_alphabet, 6);
+            let text = random.string(&text_alphabet, 8);
+            let expected = (0..=text.len()).find_map(|start| {
+                (start..=text.len())
+                    .rev()
+                    .find(|&end| is_wildcard_match(&text[start..end], &pattern))
+                    .map(|end| (start, end))
+            });
+            assert_eq!(
+                expected,
+                find(&text, &pattern),
+                "{:?} in {:?}",
+                pattern,
+                text
+            );
+        }
     }
 
     #[test]
-    fn repeating_sequence_with_stars() {
-        assert_eq!(true, is_wildcard_match("daaadabadmanda", "da*da*da"));
+    fn top_level_alternatives() {
+        assert_eq!(true, is_wildcard_match("bartender", "foo|bar*"));
+        assert_eq!(true, is_wildcard_match("foo", "foo|bar*"));
+        assert_eq!(false, is_wildcard_match("baz", "foo|bar*"));
+        assert_eq!(false, is_wildcard_match("foobar", "foo|bar*"));
+        // An alternative can be empty
+        assert_eq!(true, is_wildcard_match("", "a|"));
+        assert_eq!(true, is_wildcard_match("", "|"));
+        assert_eq!(false, is_wildcard_match("|", "|"));
     }
 
     #[test]
-    fn different_text_ending_doesnt_match() {
-        assert_eq!(false, is_wildcard_match("testingmore", "testing"));
+    fn literal_vertical_bars() {
+        assert_eq!(true, is_wildcard_match("a|b", "a\\|b"));
+        assert_eq!(false, is_wildcard_match("a", "a\\|b"));
+        assert_eq!(true, is_wildcard_match("|", "[|]"));
+        assert_eq!(true, is_wildcard_match("a|b", &escape("a|b")));
+        assert_eq!(true, is_wildcard_match_fnmatch("a|b", "a|b"));
+        assert_eq!(false, is_wildcard_match_fnmatch("a", "a|b"));
+        let options = MatchOptions::new().alternation(false);
+        assert_eq!(true, is_wildcard_match_with("a|b", "a|b", &options));
+        assert_eq!(false, is_wildcard_match_with("a", "a|b", &options));
+        let options = options.wildcards('|', '_').unwrap();
+        assert_eq!(true, is_wildcard_match_with("abc", "a|", &options));
+        // Turning alternation back on doesn't take `|` away from the wildcard
+        let options = options.alternation(true);
+        assert_eq!(true, is_wildcard_match_with("abc", "a|", &options));
     }
 
     #[test]
-    fn different_text_start_doesnt_match() {
-        assert_eq!(false, is_wildcard_match("more, testing", "testing"));
+    fn alternatives_bind_less_tightly_than_braces() {
+        // A `|` inside of a group is a literal, and one outside splits around the group
+        let options = MatchOptions::new().braces(true);
+        assert_eq!(true, is_wildcard_match_with("a|b", "{a|b,c}", &options));
+        assert_eq!(false, is_wildcard_match_with("a", "{a|b,c}", &options));
+        assert_eq!(true, is_wildcard_match_with("xc", "x{a,c}|y", &options));
+        assert_eq!(true, is_wildcard_match_with("y", "x{a,c}|y", &options));
+        assert_eq!(false, is_wildcard_match_with("xy", "x{a,c}|y", &options));
     }
 
     #[test]
-    fn star_and_questionmark_does_match() {
-        assert_eq!(true, is_wildcard_match("xx", "*?"));
+    fn alternatives_with_paths() {
+        let options = MatchOptions::new().separator('/');
+        let pattern = "**/*.rs|*.toml";
+        assert_eq!(
+            true,
+            is_wildcard_match_with("src/a/b.rs", pattern, &options)
+        );
+        assert_eq!(
+            true,
+            is_wildcard_match_with("Cargo.toml", pattern, &options)
+        );
+        assert_eq!(
+            false,
+            is_wildcard_match_with("a/Cargo.toml", pattern, &options)
+        );
     }
 
     #[test]
-    fn star_in_text_is_escaped() {
-        assert_eq!(true, is_wildcard_match("a*", "*"));
+    fn alternatives_agree_with_matching_each() {
+        let mut random = Random(0x510e_527f_ade6_82d1);
+        let pattern_alphabet = ["a", "b", "*", "?", "|", "|", "ab"];
+        let text_alphabet = ["a", "b", "|"];
+        for _ in 0..3_000 {
+            let pattern = random.string(&pattern_alphabet, 8);
+            let text = random.string(&text_alphabet, 6);
+            let options = MatchOptions::new().alternation(false);
+            let expected = pattern
+                .split('|')
+                .any(|alternative| is_wildcard_match_with(&text, alternative, &options));
+            assert_eq!(
+                expected,
+                is_wildcard_match(&text, &pattern),
+                "{:?} against {:?}",
+                pattern,
+                text
+            );
+            assert_eq!(
+                expected,
+                is_wildcard_match_bytes(text.as_bytes(), pattern.as_bytes())
+            );
+        }
     }
 
     #[test]
-    fn empty_input_string() {
-        assert_eq!(true, is_wildcard_match("", "*"))
+    fn collapse_whitespace() {
+        let options = MatchOptions::new().collapse_whitespace(true);
+        assert_eq!(true, is_wildcard_match_with("a  b", "a b", &options));
+        assert_eq!(false, is_wildcard_match("a  b", "a b"));
+        assert_eq!(true, is_wildcard_match_with("a \t\n b", "a  b", &options));
+        assert_eq!(true, is_wildcard_match_with("a\u{a0}b", "a\tb", &options));
+        assert_eq!(false, is_wildcard_match_with("ab", "a b", &options));
+        assert_eq!(false, is_wildcard_match_with("a b", "ab", &options));
+        // Only whitespace is collapsed
+        assert_eq!(false, is_wildcard_match_with("a--b", "a-b", &options));
+        // A run is a single grapheme for the wildcards
+        assert_eq!(true, is_wildcard_match_with("a   b", "a?b", &options));
+        assert_eq!(true, is_wildcard_match_with("a \t b", "a[ ]b", &options));
+        assert_eq!(true, is_wildcard_match_with("x  y z", "x*z", &options));
     }
 
     #[test]
-    fn empty_pattern_string() {
-        assert_eq!(false, is_wildcard_match("test", ""))
+    fn collapse_whitespace_with_other_options() {
+        let options = MatchOptions::new()
+            .collapse_whitespace(true)
+            .case_insensitive(true)
+            .separator('/');
+        let pattern = WildcardPattern::with_options("My  Documents/*.TXT", &options);
+        assert_eq!(true, pattern.matches("my documents/a  b.txt"));
+        assert_eq!(false, pattern.matches("my documents/a/b.txt"));
+        assert_eq!(
+            Some(vec![String::from("a b")]),
+            pattern.captures("MY\tDOCUMENTS/A  B.txt")
+        );
+        let names = ["my   documents/x.txt", "mydocuments/x.txt"];
+        let matches = pattern.filter_iter(names.into_iter()).collect::<Vec<_>>();
+        assert_eq!(vec!["my   documents/x.txt"], matches);
     }
 
     #[test]
-    fn both_strings_empty() {
-        assert_eq!(true, is_wildcard_match("", ""))
+    fn trim_only_the_ends_of_the_text() {
+        let options = MatchOptions::new().trim(true);
+        assert_eq!(true, is_wildcard_match_with("  foo  ", "foo", &options));
+        assert_eq!(
+            false,
+            is_wildcard_match_with("  foo  ", "foo", &MatchOptions::new())
+        );
+        assert_eq!(
+            true,
+            is_wildcard_match_with("\t\u{3000}foo\r\n", "foo", &options)
+        );
+        assert_eq!(false, is_wildcard_match_with(" f oo ", "foo", &options));
+        // The pattern isn't trimmed
+        assert_eq!(false, is_wildcard_match_with("  foo  ", " foo", &options));
+        assert_eq!(true, is_wildcard_match_with("   ", "", &options));
+
+        let pattern = WildcardPattern::with_options("f*", &options);
+        assert_eq!(Some(vec![String::from("oo")]), pattern.captures(" foo "));
+        assert_eq!(Some((2, 5)), pattern.find("  foo  "));
+        let names = [" foo", "bar ", "foo\n"];
+        let matches = pattern.filter_iter(names.into_iter()).collect::<Vec<_>>();
+        assert_eq!(vec![" foo", "foo\n"], matches);
+        assert_eq!(true, pattern.matches_path(Path::new(" foo ")));
+        // A space at the end of what's been read so far might not be at the end of the text
+        let pattern = WildcardPattern::with_options("foo?bar", &options);
+        assert_eq!(true, pattern.matches_prefix_of(" foo "));
+        let pattern = WildcardPattern::with_options("foo", &options);
+        assert_eq!(true, pattern.matches_prefix_of(" foo "));
+        let pattern = WildcardPattern::with_options("foobar", &options);
+        assert_eq!(false, pattern.matches_prefix_of(" foo "));
+    }
+
+    #[test]
+    fn multiline_stars_stop_at_newlines() {
+        let options = MatchOptions::new().multiline(true);
+        assert_eq!(false, is_wildcard_match_with("a\nb", "a*b", &options));
+        assert_eq!(
+            true,
+            is_wildcard_match_with("a\nb", "a*b", &MatchOptions::new())
+        );
+        assert_eq!(true, is_wildcard_match_with("a\nb", "a*\n*b", &options));
+        assert_eq!(true, is_wildcard_match_with("a\nb", "a**b", &options));
+        assert_eq!(true, is_wildcard_match_with("a\n\nb", "a***b", &options));
+        assert_eq!(false, is_wildcard_match_with("a\nb", "a?b", &options));
+        // A Windows line ending is a single grapheme, which isn't a newline on its own
+        assert_eq!(true, is_wildcard_match_with("a\r\nb", "a*b", &options));
+        let scalars = options.granularity(Granularity::Scalars);
+        assert_eq!(false, is_wildcard_match_with("a\r\nb", "a*b", &scalars));
+        assert_eq!(true, is_wildcard_match_with("a\r\nb", "a*\nb", &scalars));
+        // Classes can still match a newline
+        assert_eq!(true, is_wildcard_match_with("a\nb", "a[!x]b", &options));
+        // And so does a class that excludes nothing, which isn't written back as a `?`
+        let anything = WildcardPattern::with_options("a[!]b", &options);
+        assert_eq!(true, anything.matches("a\nb"));
+        assert_eq!("a[!]b", anything.to_string());
+
+        let pattern = WildcardPattern::with_options("first*\n**", &options);
+        assert_eq!(
+            Some(vec![String::from(" line"), String::from("second\nthird")]),
+            pattern.captures("first line\nsecond\nthird")
+        );
+        assert_eq!(true, pattern.matches("first\n"));
+        assert_eq!(false, pattern.matches("first line"));
+    }
+
+    #[test]
+    fn multiline_with_other_options() {
+        // The separator takes the place of the newline in path mode
+        let path = MatchOptions::new().multiline(true).separator('/');
+        assert_eq!(true, is_wildcard_match_with("a\nb", "a*b", &path));
+        assert_eq!(false, is_wildcard_match_with("a/b", "a*b", &path));
+        let wildcards = MatchOptions::new()
+            .multiline(true)
+            .wildcards('%', '_')
+            .unwrap();
+        assert_eq!(false, is_wildcard_match_with("a\nb", "a%b", &wildcards));
+        assert_eq!(true, is_wildcard_match_with("a\nb", "a%%b", &wildcards));
+        assert_eq!(false, is_wildcard_match_with("a\nb", "a_b", &wildcards));
+        // Written out, the pattern parses to the same tokens again
+        let options = MatchOptions::new().multiline(true);
+        let pattern = WildcardPattern::with_options("?*a***[!\n-\n]", &options);
+        assert_eq!("?*a**?", pattern.to_string());
+        let written = WildcardPattern::with_options(&pattern.to_string(), &options);
+        assert_eq!(true, pattern == written);
+    }
+
+    #[test]
+    fn optional_question_marks() {
+        let options = MatchOptions::new().optional_question_marks(true);
+        assert_eq!(true, is_wildcard_match_with("color", "colou?r", &options));
+        assert_eq!(true, is_wildcard_match_with("colour", "colou?r", &options));
+        assert_eq!(
+            false,
+            is_wildcard_match_with("colouur", "colou?r", &options)
+        );
+        assert_eq!(false, is_wildcard_match_with("colxr", "colou?r", &options));
+        // Without the option, `?` still has to match exactly one grapheme
+        assert_eq!(false, is_wildcard_match("color", "colou?r"));
+        assert_eq!(true, is_wildcard_match("colouxr", "colou?r"));
+    }
+
+    #[test]
+    fn optional_question_marks_with_wildcards() {
+        let options = MatchOptions::new().optional_question_marks(true);
+        for (text, pattern, expected) in [
+            ("", "a?", true),
+            ("a", "a?", true),
+            ("file.txt", "file[0-9]?.txt", true),
+            ("file7.txt", "file[0-9]?.txt", true),
+            ("filex.txt", "file[0-9]?.txt", false),
+            ("ab", "a?b?*", true),
+            ("xa", "*a?", true),
+            ("e\u{301}", "e\u{301}?", true),
+            ("?a", "?a", true),
+            ("a", "?a", false),
+            ("a?", "a\\?", true),
+        ] {
+            assert_eq!(
+                expected,
+                is_wildcard_match_with(text, pattern, &options),
+                "{:?} against {:?}",
+                text,
+                pattern
+            );
+        }
+        let options = options.braces(true);
+        assert_eq!(true, is_wildcard_match_with("x", "x{a,b}?", &options));
+        assert_eq!(true, is_wildcard_match_with("xb", "x{a,b}?", &options));
+        assert_eq!(false, is_wildcard_match_with("xc", "x{a,b}?", &options));
+    }
+
+    #[test]
+    fn optional_question_marks_captures() {
+        let options = MatchOptions::new().optional_question_marks(true);
+        let pattern = WildcardPattern::with_options("a-?*", &options);
+        assert_eq!(Some(vec![String::from("b")]), pattern.captures("a-b"));
+        assert_eq!(Some(vec![String::from("b")]), pattern.captures("ab"));
+        // The optional grapheme is taken when it can be, even when the stars are lazy
+        let pattern = WildcardPattern::with_options("*x?*", &options.lazy_captures(true));
+        assert_eq!(
+            Some(vec![String::from(""), String::from("b")]),
+            pattern.captures("xb")
+        );
+        assert_eq!(
+            Some(vec![String::from(""), String::from("ab")]),
+            pattern.captures("ab")
+        );
+    }
+
+    #[test]
+    fn literal_match_has_no_wildcards() {
+        assert_eq!(true, literal_match("a*b", "a*b"));
+        assert_eq!(false, literal_match("axb", "a*b"));
+        assert_eq!(true, literal_match("what?[1].txt", "what?[1].txt"));
+        assert_eq!(false, literal_match("whatx1.txt", "what?[1].txt"));
+        assert_eq!(true, literal_match("a\\*", "a\\*"));
+        assert_eq!(false, literal_match("a*", "a\\*"));
+        assert_eq!(true, literal_match("", ""));
+        assert_eq!(false, literal_match("a", ""));
+    }
+
+    #[test]
+    fn literal_mode_with_other_options() {
+        let options = MatchOptions::new()
+            .literal(true)
+            .case_insensitive(true)
+            .braces(true)
+            .separator('/');
+        assert_eq!(
+            true,
+            is_wildcard_match_with("SRC/{A,B}/*.RS", "src/{a,b}/*.rs", &options)
+        );
+        assert_eq!(
+            false,
+            is_wildcard_match_with("src/a/main.rs", "src/{a,b}/*.rs", &options)
+        );
+    }
+
+    #[test]
+    fn braces_captures() {
+        let pattern =
+            WildcardPattern::with_options("{*.rs,src/*}", &MatchOptions::new().braces(true));
+        assert_eq!(
+            Some(vec![String::from("src/main")]),
+            pattern.captures("src/main.rs")
+        );
+        assert_eq!(
+            Some(vec![String::from("lib.py")]),
+            pattern.captures("src/lib.py")
+        );
+        assert_eq!(None, pattern.captures("lib.py"));
+    }
+
+    #[test]
+    fn braces_agree_with_expansion() {
+        let mut random = Random(0x3c6e_f372_fe94_f82b);
+        let pattern_alphabet = ["a", "b", "*", "?", "{", "}", ",", "{a,b}", "{,*a}"];
+        let text_alphabet = ["a", "b", "{", "}", ","];
+        for _ in 0..3_000 {
+            let pattern = random.string(&pattern_alphabet, 6);
+            let text = random.string(&text_alphabet, 6);
+            let compiled =
+                WildcardPattern::with_options(&pattern, &MatchOptions::new().braces(true));
+            let expanded = expand(compiled.tokens());
+            let text_graphemes = text.graphemes(true).collect::<Vec<&str>>();
+            let expected = expanded
+                .iter()
+                .any(|tokens| matcher::match_with_cache(tokens, &text_graphemes));
+            assert_eq!(
+                expected,
+                compiled.matches(&text),
+                "{:?} against {:?}",
+                pattern,
+                text
+            );
+            assert_eq!(expected, compiled.captures(&text).is_some());
+        }
+    }
+
+    /// Expand the groups of a pattern in to every combination of their alternatives
+    fn expand(pattern: &[Token]) -> Vec<Vec<Token>> {
+        let mut expanded = vec![vec![]];
+        for token in pattern {
+            let endings = match token {
+                Token::Alternatives(alternatives) => {
+                    alternatives.iter().flat_map(|a| expand(a)).collect()
+                }
+                token => vec![vec![token.clone()]],
+            };
+            expanded = expanded
+                .iter()
+                .flat_map(|start| {
+                    endings
+                        .iter()
+                        .map(move |ending| [start.clone(), ending.clone()].concat())
+                })
+                .collect();
+        }
+        expanded
+    }
+
+    #[test]
+    fn search_matches_anywhere() {
+        assert_eq!(true, is_wildcard_search("abcdef", "cd"));
+        assert_eq!(false, is_wildcard_match("abcdef", "cd"));
+        assert_eq!(true, is_wildcard_search("abcdef", "abcdef"));
+        assert_eq!(true, is_wildcard_search("abcdef", "b?d*f"));
+        assert_eq!(false, is_wildcard_search("abcdef", "dc"));
+    }
+
+    #[test]
+    fn unanchored_end_matches_prefixes() {
+        let options = MatchOptions::new().anchor_end(false);
+        assert_eq!(true, is_wildcard_match_with("abc...", "abc", &options));
+        assert_eq!(true, is_wildcard_match_with("abc", "abc", &options));
+        assert_eq!(true, is_wildcard_match_with("abcdef", "a?c*", &options));
+        assert_eq!(false, is_wildcard_match_with("xabc", "abc", &options));
+        assert_eq!(false, is_wildcard_match_with("ab", "abc", &options));
+        assert_eq!(true, is_wildcard_match_with("ab\\cd", "ab\\", &options));
+    }
+
+    #[test]
+    fn unanchored_start_matches_suffixes() {
+        let options = MatchOptions::new().anchor_start(false);
+        assert_eq!(true, is_wildcard_match_with("main.rs", ".rs", &options));
+        assert_eq!(
+            false,
+            is_wildcard_match_with("main.rs.bak", ".rs", &options)
+        );
+        let both = options.anchor_end(false);
+        for (text, pattern) in [("abcdef", "cd"), ("abcdef", "dc"), ("abc", ""), ("", "a")] {
+            assert_eq!(
+                is_wildcard_search(text, pattern),
+                is_wildcard_match_with(text, pattern, &both),
+                "{:?} {:?}",
+                text,
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn unanchored_ends_dont_double_stars() {
+        let pattern = WildcardPattern::with_options("abc*", &MatchOptions::new().anchor_end(false));
+        assert_eq!(1, pattern.num_stars());
+        assert_eq!(pattern.tokens(), WildcardPattern::new("abc*").tokens());
+    }
+
+    #[test]
+    fn search_with_empty_pattern_or_text() {
+        assert_eq!(true, is_wildcard_search("abc", ""));
+        assert_eq!(true, is_wildcard_search("", ""));
+        assert_eq!(true, is_wildcard_search("", "*"));
+        assert_eq!(false, is_wildcard_search("", "?"));
+    }
+
+    #[test]
+    fn search_keeps_trailing_backslash_literal() {
+        // Wrapping the pattern in stars would turn `\` in to an escaped star
+        assert_eq!(false, is_wildcard_match("xa\\y", "*a\\*"));
+        assert_eq!(true, is_wildcard_search("xa\\y", "a\\"));
+        assert_eq!(false, is_wildcard_search("xa*", "a\\"));
+        assert_eq!(true, is_wildcard_search("x*y", "\\*"));
+        assert_eq!(false, is_wildcard_search("xy", "\\*"));
+    }
+
+    #[test]
+    fn escape_wildcards() {
+        assert_eq!("a\\*b\\?c\\[d]\\\\", escape("a*b?c[d]\\"));
+        assert_eq!("plain.txt", escape("plain.txt"));
+    }
+
+    #[test]
+    fn escaped_string_matches_itself() {
+        let mut random = Random(0x2f9b_3c7e_11d4_a5b6);
+        let alphabet = [
+            "a", "*", "?", "[", "]", "!", "-", "\\", "\u{301}", "\r", "\n", "/",
+        ];
+        for _ in 0..5_000 {
+            let text = random.string(&alphabet, 8);
+            let pattern = escape(&text);
+            assert_eq!(true, is_wildcard_match(&text, &pattern), "{:?}", text);
+            assert_eq!(Ok(()), validate(&pattern));
+        }
+    }
+
+    #[test]
+    fn unescape_removes_escapes() {
+        assert_eq!("a*b?c[d]\\", unescape("a\\*b\\?c\\[d]\\\\"));
+        assert_eq!("plain.txt", unescape("plain.txt"));
+        assert_eq!("ab", unescape("\\a\\b"));
+        assert_eq!("", unescape(""));
+    }
+
+    #[test]
+    fn unescape_leaves_wildcards_and_trailing_backslash() {
+        assert_eq!("*.rs", unescape("*.rs"));
+        assert_eq!("a?[b]*", unescape("a?[b]\\*"));
+        assert_eq!("a\\", unescape("a\\"));
+        assert_eq!("\\", unescape("\\\\"));
+    }
+
+    #[test]
+    fn unescape_undoes_escape() {
+        let mut random = Random(0x9b05_688c_2b3e_6c1f);
+        let alphabet = [
+            "a",
+            "*",
+            "?",
+            "[",
+            "]",
+            "!",
+            "-",
+            "\\",
+            "\u{301}",
+            "\r",
+            "\n",
+            "{",
+            "\u{1f600}",
+        ];
+        for _ in 0..5_000 {
+            let text = random.string(&alphabet, 8);
+            assert_eq!(text, unescape(&escape(&text)), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn escaped_string_only_matches_itself() {
+        assert_eq!(false, is_wildcard_match("ab", &escape("a?")));
+        assert_eq!(false, is_wildcard_match("abc", &escape("a*")));
+        assert_eq!(false, is_wildcard_match("a", &escape("[a]")));
+    }
+
+    #[test]
+    fn displayed_pattern_matches_the_same() {
+        let mut random = Random(0x6a09_e667_f3bc_c908);
+        let pattern_alphabet = ["a", "*", "?", "[", "]", "!", "^", "-", "\\", "\u{301}"];
+        let text_alphabet = ["a", "*", "?", "[", "]", "!", "-", "\\", "a\u{301}"];
+        for _ in 0..5_000 {
+            let pattern = WildcardPattern::new(&random.string(&pattern_alphabet, 8));
+            let parsed = pattern.to_string().parse::<WildcardPattern>().unwrap();
+            let text = random.string(&text_alphabet, 6);
+            assert_eq!(
+                pattern.matches(&text),
+                parsed.matches(&text),
+                "{} against {:?}",
+                pattern,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn parsed_tokens_round_trip() {
+        let patterns = [
+            "",
+            "*.rs",
+            "src/?/main_[0-9].rs",
+            "[!a-z_]*",
+            "[[:digit:][:upper:]x]",
+            "\\*literal\\?",
+            "a|b*",
+            "e\u{301}?",
+        ];
+        for pattern in patterns {
+            let tokens = parse(pattern).unwrap();
+            assert_eq!(pattern, to_pattern(&tokens));
+            assert_eq!(Ok(tokens), parse(&to_pattern(&parse(pattern).unwrap())));
+        }
+        // Runs of stars and needless escapes don't survive parsing
+        assert_eq!("a*\\?b", to_pattern(&parse("a***\\?\\b").unwrap()));
+        assert_eq!(Err(PatternError::DanglingEscape { pos: 1 }), parse("a\\"));
+    }
+
+    #[test]
+    fn tokens_made_by_hand_round_trip() {
+        let mut random = Random(0x428a_2f98_d728_ae22);
+        let alphabet = ["a", "*", "?", "[", "]", "!", "|", "-", "\\"];
+        for _ in 0..5_000 {
+            let Ok(tokens) = parse(&random.string(&alphabet, 8)) else {
+                continue;
+            };
+            // Start from how the tokens are written out, since `[!]` is written as `?`
+            let tokens = parse(&to_pattern(&tokens)).unwrap();
+            // Literals of the characters which are wildcards have to be escaped, and the
+            // alternatives of `|` have to stay the whole pattern
+            let mut changed = tokens.clone();
+            let edited = match changed.as_mut_slice() {
+                [Token::Alternatives(alternatives)] => &mut alternatives[0],
+                _ => &mut changed,
+            };
+            edited.push(Token::Literal(String::from("*")));
+            edited.insert(0, Token::Literal(String::from("[")));
+            for tokens in [tokens, changed] {
+                assert_eq!(
+                    Ok(&tokens),
+                    parse(&to_pattern(&tokens)).as_ref(),
+                    "{:?}",
+                    to_pattern(&tokens)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn text_comes_before_pattern() {
+        assert_eq!(true, is_wildcard_match("abc", "a*"));
+        assert_eq!(false, is_wildcard_match("a*", "abc"));
     }
 
     #[test]
@@ -0,0 +1,148 @@
+use crate::posix;
+
+/// Compiles a pattern which is known when the program is built, checking it while building.
+///
+/// A pattern with a `[` that is never closed, or with a `\` at the end that has nothing to
+/// escape, fails to compile, in the same cases where `validate` would return an error.
+/// The pattern itself is compiled the first time the macro is reached, and every time
+/// after that the same `&'static WildcardPattern` is returned, so a pattern in a loop or a
+/// function called often is only compiled once.
+///
+/// The check looks at one character at a time rather than one grapheme, so it can miss a
+/// `[`, `]` or `\` which a combining mark after it turns in to part of a larger grapheme.
+///
+/// # Examples
+/// ```
+/// use pyglob::wildcard;
+/// assert_eq!(wildcard!("*.rs").matches("main.rs"), true);
+/// assert_eq!(wildcard!("[[:digit:]]?").matches("1a"), true);
+/// ```
+///
+/// ```compile_fail
+/// let pattern = pyglob::wildcard!("[a-z");
+/// ```
+#[macro_export]
+macro_rules! wildcard {
+    ($pattern:literal $(,)?) => {{
+        const _: () = $crate::__check_pattern($pattern);
+        static PATTERN: ::std::sync::OnceLock<$crate::WildcardPattern> =
+            ::std::sync::OnceLock::new();
+        PATTERN.get_or_init(|| $crate::WildcardPattern::new($pattern))
+    }};
+}
+
+/// Panics if the pattern isn't valid, which is a compile error when the pattern is checked
+/// in a constant, as `wildcard!` does. It walks the pattern the same way as `validate`.
+#[doc(hidden)]
+pub const fn __check_pattern(pattern: &str) {
+    let bytes = pattern.as_bytes();
+    let mut i: usize = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if i + 1 == bytes.len() => {
+                panic!("the pattern ends with a `\\` that has nothing to escape")
+            }
+            b'\\' => i += char_length(bytes[i + 1]),
+            b'[' => match class_length(bytes, i + 1) {
+                Some(length) => i += length,
+                None => panic!("the pattern has a `[` that is never closed by a `]`"),
+            },
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+/// The number of bytes in the class starting right after its `[`, up to and including the
+/// closing `]`, if there is one
+const fn class_length(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b']' => return Some(i + 1 - start),
+            b'\\' if i + 1 < bytes.len() => i += char_length(bytes[i + 1]),
+            b'[' => i += named_class_length(bytes, i + 1),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// The number of bytes in a named class like `[:digit:]` after its `[`, or zero if there
+/// isn't one there
+const fn named_class_length(bytes: &[u8], start: usize) -> usize {
+    let mut n = 0;
+    while n < posix::NAMES.len() {
+        let name = posix::NAMES[n].as_bytes();
+        let end = start + name.len() + 3;
+        if end <= bytes.len()
+            && bytes[start] == b':'
+            && equal(bytes, start + 1, name)
+            && bytes[end - 2] == b':'
+            && bytes[end - 1] == b']'
+        {
+            return end - start;
+        }
+        n += 1;
+    }
+    0
+}
+
+/// Checks if `name` comes in the bytes at `start`
+const fn equal(bytes: &[u8], start: usize, name: &[u8]) -> bool {
+    let mut i = 0;
+    while i < name.len() {
+        if bytes[start + i] != name[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// The number of bytes in a UTF-8 encoded character, from its first byte
+const fn char_length(first: u8) -> usize {
+    match first.leading_ones() {
+        0 => 1,
+        ones => ones as usize,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate;
+
+    #[test]
+    fn macro_compiles_the_pattern_once() {
+        let patterns = (0..3).map(|_| wildcard!("*.rs")).collect::<Vec<_>>();
+        assert!(std::ptr::eq(patterns[0], patterns[2]));
+        assert_eq!(true, patterns[0].matches("main.rs"));
+        assert_eq!(false, patterns[0].matches("main.py"));
+    }
+
+    #[test]
+    fn check_agrees_with_validate() {
+        for pattern in [
+            "*.rs",
+            "[a-z]",
+            "[]",
+            "[\\]",
+            "[\\]]",
+            "\\[",
+            "\\",
+            "a\\\\",
+            "a\\\u{e9}",
+            "[[:digit:]",
+            "[[:digit:]]",
+            "[[:foo:]",
+            "[[:digit:",
+            "[\u{e9}]",
+            "]",
+        ] {
+            let checked = std::panic::catch_unwind(|| __check_pattern(pattern)).is_ok();
+            assert_eq!(validate(pattern).is_ok(), checked, "{:?}", pattern);
+        }
+    }
+}
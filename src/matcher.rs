@@ -0,0 +1,859 @@
+use std::cell::RefCell;
+
+use crate::token::{Token, Unit};
+
+/// A dynamic solution to the pattern matching, with the help of this video:
+/// https://www.youtube.com/watch?v=3ZDZ-N0EPV0
+///
+/// Each row of the table holds whether the first `row` tokens of the pattern match the
+/// first `column` graphemes of the text. The rows are filled in bottom-up, so that long
+/// patterns and texts can't overflow the stack the way a recursive solution could.
+///
+/// A row only depends on the row above it, so instead of keeping the whole table we only
+/// keep two rows and swap them, which needs memory proportional to the length of the text.
+/// The rows are taken from `SPARE_ROWS` and put back afterwards, so matching many texts in
+/// a row only allocates when a text is longer than any before it.
+pub(crate) fn match_with_cache<U: Unit>(pattern: &[Token<U::Owned>], text: &[U]) -> bool {
+    let mut first = take_row(text.len() + 1);
+    // An empty pattern only matches an empty text
+    first[0] = true;
+    let last = last_row(pattern, text, first);
+    let matched = last[text.len()];
+    put_back_row(last);
+    matched
+}
+
+/// Checks if the text is the start of some text that the pattern matches, so that a text
+/// which is still being read can be rejected as soon as no ending could make it match.
+///
+/// This fills in the same rows as `match_with_cache`, and after each token checks whether
+/// the tokens so far match the whole text and every token after them can match something.
+/// The text can also end part of the way through a group or a `**` segment, which is
+/// checked on the way.
+pub(crate) fn matches_prefix<U: Unit>(pattern: &[Token<U::Owned>], text: &[U]) -> bool {
+    let mut first = take_row(text.len() + 1);
+    first[0] = true;
+    let (matched, last) = prefix_row(pattern, text, first);
+    put_back_row(last);
+    matched
+}
+
+/// Go through the rows of `matches_prefix` for the tokens of a pattern, starting from the
+/// row above the first of them. Returns whether the text is the start of something the
+/// pattern matches, along with the last row that was filled in.
+fn prefix_row<U: Unit>(
+    pattern: &[Token<U::Owned>],
+    text: &[U],
+    mut above: Vec<bool>,
+) -> (bool, Vec<bool>) {
+    // Whether each token and every one after it can match something
+    let mut possible = vec![true; pattern.len() + 1];
+    for (i, token) in pattern.iter().enumerate().rev() {
+        possible[i] = possible[i + 1] && token.can_match();
+    }
+
+    for (i, token) in pattern.iter().enumerate() {
+        let ends_here = above[text.len()];
+        let ends_inside = match token {
+            // The text ends inside of one of the alternatives
+            Token::Alternatives(alternatives) => alternatives.iter().any(|alternative| {
+                let mut start = take_row(above.len());
+                start.copy_from_slice(&above);
+                let (matched, row) = prefix_row(alternative, text, start);
+                put_back_row(row);
+                matched
+            }),
+            // A `**` segment can match anything at all, as long as it's followed by a
+            // separator that hasn't been read yet
+            Token::AnySegments(_) => above.contains(&true),
+            _ => false,
+        };
+        if (ends_here && possible[i]) || (ends_inside && possible[i + 1]) {
+            return (true, above);
+        }
+        above = last_row(std::slice::from_ref(token), text, above);
+    }
+    (above[text.len()], above)
+}
+
+thread_local! {
+    /// Rows of the table which are no longer in use, kept for the next match on the same
+    /// thread.
+    ///
+    /// Each thread has its own, so a compiled pattern can still be shared between threads
+    /// and matched on all of them at once, without any locking.
+    static SPARE_ROWS: RefCell<Vec<Vec<bool>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The most rows `SPARE_ROWS` holds on to. Two are enough for a pattern without braces,
+/// and each level of nested braces needs two more.
+const MAX_SPARE_ROWS: usize = 8;
+
+/// Take a spare row, or allocate one if there are none, and fill it with `false`
+fn take_row(width: usize) -> Vec<bool> {
+    match SPARE_ROWS.with(|spare| spare.borrow_mut().pop()) {
+        Some(mut row) => {
+            row.clear();
+            row.resize(width, false);
+            row
+        }
+        None => vec![false; width],
+    }
+}
+
+/// Keep a row which is no longer in use for the next match
+fn put_back_row(row: Vec<bool>) {
+    SPARE_ROWS.with(|spare| {
+        let mut spare = spare.borrow_mut();
+        if spare.len() < MAX_SPARE_ROWS {
+            spare.push(row);
+        }
+    });
+}
+
+/// Fill in the rows for the tokens of a pattern, starting from the row above the first of
+/// them, and return the row for the last one.
+///
+/// Each alternative of a `{...}` group is filled in from the same row above the group, and
+/// the row for the group is whichever columns any of them matched. Only nested groups
+/// recurse, so the depth is limited by the nesting of the pattern rather than its length.
+fn last_row<U: Unit>(pattern: &[Token<U::Owned>], text: &[U], mut above: Vec<bool>) -> Vec<bool> {
+    let width = text.len() + 1;
+    let mut current = take_row(width);
+
+    for token in pattern {
+        if let Token::Alternatives(alternatives) = token {
+            current.fill(false);
+            for alternative in alternatives {
+                let mut start = take_row(width);
+                start.copy_from_slice(&above);
+                let row = last_row(alternative, text, start);
+                for (cell, matched) in current.iter_mut().zip(&row) {
+                    *cell |= matched;
+                }
+                put_back_row(row);
+            }
+            std::mem::swap(&mut above, &mut current);
+            continue;
+        }
+
+        // Only stars and optional tokens can match an empty text
+        current[0] = token.matches_empty() && above[0];
+        // Whether any column of the row above, up to the one before this, matched
+        let mut any_above = above[0];
+
+        for column in 1..width {
+            let unit = &text[column - 1];
+            current[column] = match token {
+                // A star either matches nothing, so we take the value from above, or it
+                // matches one more grapheme, so we take the value from the left
+                Token::AnyRun => above[column] || current[column - 1],
+                // The same, except that it can't match the separator
+                Token::AnyRunInSegment(separator) => {
+                    above[column] || (current[column - 1] && unit.scalar() != Some(*separator))
+                }
+                // Either it matches nothing, or anything at all as long as it ends with a
+                // separator
+                Token::AnySegments(separator) => {
+                    above[column] || (any_above && unit.scalar() == Some(*separator))
+                }
+                // An optional token either matches nothing, so we take the value from above,
+                // or it matches the text grapheme, so we take the value from the top left
+                Token::Optional(token) => {
+                    above[column] || (token.matches_one(unit) && above[column - 1])
+                }
+                // If the token matches the text grapheme, take the value from the top left
+                token => token.matches_one(unit) && above[column - 1],
+            };
+            any_above |= above[column];
+        }
+
+        std::mem::swap(&mut above, &mut current);
+    }
+
+    put_back_row(current);
+    // After the last swap, the row for the whole pattern is the one "above"
+    above
+}
+
+/// Finds the leftmost part of the text that the pattern matches, and of the parts starting
+/// there the longest, as a range of graphemes. An empty part counts, so a pattern which
+/// matches an empty text always finds one.
+///
+/// The rows are filled in like in `match_with_cache`, except that every column of the
+/// first one matches, and each cell holds the leftmost start of a part of the text that
+/// the tokens so far match, rather than just whether there is one. Once the start is
+/// known, the rows are filled in again from that start alone to find the longest end.
+pub(crate) fn leftmost_longest<U: Unit>(
+    pattern: &[Token<U::Owned>],
+    text: &[U],
+) -> Option<std::ops::Range<usize>> {
+    let starts = earliest_row(pattern, text, (0..=text.len()).map(Some).collect());
+    let start = starts.into_iter().flatten().min()?;
+
+    let mut first = take_row(text.len() + 1);
+    first[start] = true;
+    let last = last_row(pattern, text, first);
+    let end = last.iter().rposition(|&matched| matched);
+    put_back_row(last);
+    end.map(|end| start..end)
+}
+
+/// Fill in the rows for `leftmost_longest`, the same way as `last_row` does, where `None`
+/// is a cell which doesn't match and taking either of two cells keeps the earlier start.
+fn earliest_row<U: Unit>(
+    pattern: &[Token<U::Owned>],
+    text: &[U],
+    mut above: Vec<Option<usize>>,
+) -> Vec<Option<usize>> {
+    let earliest = |a: Option<usize>, b: Option<usize>| match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        _ => a.or(b),
+    };
+    let mut current = vec![None; text.len() + 1];
+
+    for token in pattern {
+        if let Token::Alternatives(alternatives) = token {
+            current.fill(None);
+            for alternative in alternatives {
+                let row = earliest_row(alternative, text, above.clone());
+                for (cell, start) in current.iter_mut().zip(row) {
+                    *cell = earliest(*cell, start);
+                }
+            }
+            std::mem::swap(&mut above, &mut current);
+            continue;
+        }
+
+        current[0] = above[0].filter(|_| token.matches_empty());
+        // The earliest start of any column of the row above, up to the one before this
+        let mut any_above = above[0];
+
+        for column in 1..current.len() {
+            let unit = &text[column - 1];
+            let diagonal = above[column - 1];
+            current[column] = match token {
+                Token::AnyRun => earliest(above[column], current[column - 1]),
+                Token::AnyRunInSegment(separator) => earliest(
+                    above[column],
+                    current[column - 1].filter(|_| unit.scalar() != Some(*separator)),
+                ),
+                Token::AnySegments(separator) => earliest(
+                    above[column],
+                    any_above.filter(|_| unit.scalar() == Some(*separator)),
+                ),
+                Token::Optional(token) => {
+                    earliest(above[column], diagonal.filter(|_| token.matches_one(unit)))
+                }
+                token => diagonal.filter(|_| token.matches_one(unit)),
+            };
+            any_above = earliest(any_above, above[column]);
+        }
+
+        std::mem::swap(&mut above, &mut current);
+    }
+    above
+}
+
+/// The fewest edits to the text that make the pattern match it, or `None` if it would take
+/// more than `limit`.
+///
+/// An edit is a grapheme of the text substituted, left out or added, the same as for the
+/// Levenshtein distance. A star matches what it can for free, so only what the other
+/// tokens need is ever edited.
+pub(crate) fn fewest_edits<U: Unit>(
+    pattern: &[Token<U::Owned>],
+    text: &[U],
+    limit: usize,
+) -> Option<usize> {
+    // Before any tokens, every grapheme so far has to be left out
+    let first = (0..=text.len()).collect();
+    let last = fewest_edits_row(pattern, text, first, limit)?;
+    Some(last[text.len()]).filter(|&edits| edits <= limit)
+}
+
+/// Fill in the rows for `fewest_edits`, the same way as `last_row` does, where each cell
+/// is the fewest edits for the tokens so far to match the text up to that column. Returns
+/// `None` as soon as every cell of a row is over the limit, since no cell below it can
+/// take fewer edits than the cells it's filled in from.
+fn fewest_edits_row<U: Unit>(
+    pattern: &[Token<U::Owned>],
+    text: &[U],
+    mut above: Vec<usize>,
+    limit: usize,
+) -> Option<Vec<usize>> {
+    // One more edit, where `usize::MAX` is a cell which can't be matched at all
+    let edit = |edits: usize| edits.saturating_add(1);
+    let mut current = vec![usize::MAX; text.len() + 1];
+
+    for token in pattern {
+        if let Token::Alternatives(alternatives) = token {
+            current.fill(usize::MAX);
+            for alternative in alternatives {
+                let row = fewest_edits_row(alternative, text, above.clone(), limit);
+                for (cell, edits) in current.iter_mut().zip(row.iter().flatten()) {
+                    *cell = (*cell).min(*edits);
+                }
+            }
+        } else {
+            // A token that needs a grapheme can be given one that was added to the text,
+            // unless it's a class that no grapheme is in
+            let added = |edits: usize| {
+                if token.can_match() {
+                    edit(edits)
+                } else {
+                    usize::MAX
+                }
+            };
+            current[0] = if token.matches_empty() {
+                above[0]
+            } else {
+                added(above[0])
+            };
+            // The fewest edits of any column of the row above, up to the one before this
+            let mut any_above = above[0];
+
+            for column in 1..current.len() {
+                let unit = &text[column - 1];
+                let diagonal = above[column - 1];
+                let edits = match token {
+                    Token::AnyRun => above[column].min(current[column - 1]),
+                    Token::AnyRunInSegment(separator) if unit.scalar() != Some(*separator) => {
+                        above[column].min(current[column - 1])
+                    }
+                    Token::AnySegments(separator) if unit.scalar() == Some(*separator) => {
+                        above[column].min(any_above)
+                    }
+                    Token::AnyRunInSegment(_) | Token::AnySegments(_) => above[column],
+                    // Substituting the grapheme for an optional token is no better than
+                    // leaving the grapheme out
+                    Token::Optional(token) if token.matches_one(unit) => {
+                        above[column].min(diagonal)
+                    }
+                    Token::Optional(_) => above[column],
+                    // The token takes the grapheme as it is or substituted, or is given
+                    // one that was added
+                    token if token.matches_one(unit) => diagonal.min(added(above[column])),
+                    _ => added(diagonal).min(added(above[column])),
+                };
+                // Or the grapheme is left out, after what the tokens so far matched
+                current[column] = edits.min(edit(current[column - 1]));
+                any_above = any_above.min(above[column]);
+            }
+        }
+
+        std::mem::swap(&mut above, &mut current);
+        if above.iter().all(|&edits| edits > limit) {
+            return None;
+        }
+    }
+    Some(above)
+}
+
+/// The number of rows `match_with_cache` fills in for a pattern, counting the rows for the
+/// alternatives of a group rather than the group itself
+pub(crate) fn rows<O>(pattern: &[Token<O>]) -> usize {
+    pattern
+        .iter()
+        .map(|token| match token {
+            Token::Alternatives(alternatives) => alternatives.iter().map(|a| rows(a)).sum(),
+            _ => 1,
+        })
+        .sum()
+}
+
+/// The number of stars in a pattern, counting the ones inside of groups
+pub(crate) fn stars<O>(pattern: &[Token<O>]) -> usize {
+    pattern
+        .iter()
+        .map(|token| match token {
+            Token::Alternatives(alternatives) => alternatives.iter().map(|a| stars(a)).sum(),
+            token => usize::from(token.is_star()),
+        })
+        .sum()
+}
+
+/// The number of wildcards in a pattern, see `WildcardPattern::num_wildcards`
+pub(crate) fn wildcards<O>(pattern: &[Token<O>]) -> usize {
+    pattern
+        .iter()
+        .map(|token| match token {
+            Token::Literal(_) => 0,
+            Token::Alternatives(alternatives) => alternatives.iter().map(|a| wildcards(a)).sum(),
+            // The `?` that makes it optional, and the class if it is one
+            Token::Optional(token) => 1 + wildcards(std::slice::from_ref(&**token)),
+            _ => 1,
+        })
+        .sum()
+}
+
+/// How specific a pattern is, see `WildcardPattern::specificity`. This can be negative
+/// here, for a pattern with more stars than anything else.
+pub(crate) fn specificity<O>(pattern: &[Token<O>]) -> isize {
+    pattern
+        .iter()
+        .map(|token| match token {
+            Token::Literal(_) => 3,
+            Token::Class { .. } => 2,
+            Token::AnyOne => 1,
+            Token::Alternatives(alternatives) => alternatives
+                .iter()
+                .map(|alternative| specificity(alternative))
+                .min()
+                .unwrap_or(0),
+            Token::Optional(_) => 0,
+            _ => -1,
+        })
+        .sum()
+}
+
+/// A greedy matcher for patterns that only contain literals, `?` and `*`.
+///
+/// This walks the pattern and text side by side, and only remembers the most recent star.
+/// When a grapheme doesn't match, the star is made to consume one more grapheme and the
+/// pattern after it is retried. A later star always supersedes an earlier one, since
+/// anything the earlier star could consume instead can be consumed by the later one.
+///
+/// It runs in constant memory, and in time linear in the length of the text for typical
+/// patterns, though a pattern like `*aaab` can still make it retry at every position.
+pub(crate) fn greedy_match<U: Unit>(pattern: &[Token<U::Owned>], text: &[U]) -> bool {
+    greedy_match_iter(pattern, text.iter().copied())
+}
+
+/// The same as `greedy_match`, but for a text which is split up as it's walked, so that the
+/// whole text never has to be split up in to a list first.
+pub(crate) fn greedy_match_iter<U, I>(pattern: &[Token<U::Owned>], text: I) -> bool
+where
+    U: Unit,
+    I: Iterator<Item = U> + Clone,
+{
+    greedy_walk(pattern.iter(), text)
+}
+
+/// The same as `greedy_match_iter`, but walking both the pattern and the text from their
+/// ends back to their starts.
+///
+/// Reversing both doesn't change whether they match, since each of the tokens matches the
+/// same graphemes either way. A text that doesn't end the way the pattern does is rejected
+/// as soon as it's found, rather than after walking the whole text looking for a way to
+/// match the end, which is much quicker for a pattern with a long literal suffix.
+pub(crate) fn greedy_match_from_end<U, I>(pattern: &[Token<U::Owned>], text: I) -> bool
+where
+    U: Unit,
+    I: DoubleEndedIterator<Item = U> + Clone,
+{
+    greedy_walk(pattern.iter().rev(), text.rev())
+}
+
+/// Walk the pattern and the text for `greedy_match_iter`, in whichever direction the
+/// iterators go.
+///
+/// The matcher only ever goes back to the grapheme just after where the last star started,
+/// so it keeps a copy of both iterators from there rather than indices.
+fn greedy_walk<'p, U, P, I>(mut pattern: P, mut text: I) -> bool
+where
+    U: Unit + 'p,
+    P: Iterator<Item = &'p Token<U::Owned>> + Clone,
+    I: Iterator<Item = U> + Clone,
+{
+    // The pattern just after the last star, and the text after what it has consumed
+    let mut star: Option<(P, I)> = None;
+
+    loop {
+        let mut rest = text.clone();
+        let Some(unit) = rest.next() else {
+            break;
+        };
+        let mut after = pattern.clone();
+        match after.next() {
+            Some(Token::AnyRun) => {
+                // Start off by having the star match nothing
+                pattern = after;
+                star = Some((pattern.clone(), text.clone()));
+            }
+            Some(token) if token.matches_one(&unit) => {
+                pattern = after;
+                text = rest;
+            }
+            _ => match &mut star {
+                // Let the last star consume one more grapheme, and try again after it
+                Some((star_pattern, star_text)) => {
+                    star_text.next();
+                    pattern = star_pattern.clone();
+                    text = star_text.clone();
+                }
+                None => return false,
+            },
+        }
+    }
+
+    // The text is used up, so whatever is left of the pattern has to match nothing
+    pattern.all(|token| matches!(token, Token::AnyRun))
+}
+
+/// Find what each star in the pattern consumed, if the pattern matches the text.
+///
+/// Returns the range of graphemes of the text that each star matched, in order. When there
+/// are several ways for the pattern to match, every star takes as much as it can, going
+/// from left to right, like `.*` in a regular expression. If `lazy` is set, every star
+/// takes as little as it can instead, like `.*?`.
+///
+/// This needs the whole table rather than just two rows, because the choice for each star
+/// depends on whether the rest of the pattern can still match the rest of the text.
+///
+/// A pattern with braces first has each group replaced by one of its alternatives, see
+/// `choose_alternatives`, so only the stars of the chosen alternatives capture anything.
+pub(crate) fn capture_spans<U: Unit>(
+    pattern: &[Token<U::Owned>],
+    text: &[U],
+    lazy: bool,
+) -> Option<Vec<std::ops::Range<usize>>> {
+    if pattern
+        .iter()
+        .any(|token| matches!(token, Token::Alternatives(_)))
+    {
+        return capture_spans(&choose_alternatives(pattern, text)?, text, lazy);
+    }
+
+    // `cache[row * width + column]` holds whether the pattern from token `row` onwards
+    // matches the text from grapheme `column` onwards
+    let width = text.len() + 1;
+    let mut cache = vec![false; (pattern.len() + 1) * width];
+
+    // An empty pattern only matches an empty text
+    cache[pattern.len() * width + text.len()] = true;
+
+    for row in (0..pattern.len()).rev() {
+        let token = &pattern[row];
+        let below = (row + 1) * width;
+        let current = row * width;
+
+        // Only stars and optional tokens can match an empty text
+        cache[current + text.len()] = token.matches_empty() && cache[below + text.len()];
+        // Whether the rest of the pattern matches just after any separator from here on
+        let mut any_below = false;
+
+        for column in (0..text.len()).rev() {
+            let unit = &text[column];
+            cache[current + column] = match token {
+                Token::AnyRun => cache[below + column] || cache[current + column + 1],
+                Token::AnyRunInSegment(separator) => {
+                    cache[below + column]
+                        || (unit.scalar() != Some(*separator) && cache[current + column + 1])
+                }
+                Token::AnySegments(separator) => {
+                    any_below |= unit.scalar() == Some(*separator) && cache[below + column + 1];
+                    cache[below + column] || any_below
+                }
+                Token::Optional(token) => {
+                    cache[below + column] || (token.matches_one(unit) && cache[below + column + 1])
+                }
+                token => token.matches_one(unit) && cache[below + column + 1],
+            };
+        }
+    }
+
+    if !cache[0] {
+        return None;
+    }
+
+    // Walk through the table, letting every star take as much of the text as it can, or as
+    // little, while the rest of the pattern still matches
+    let mut spans = Vec::new();
+    let mut column: usize = 0;
+    for (row, token) in pattern.iter().enumerate() {
+        let below = (row + 1) * width;
+        if token.is_star() {
+            let fits = |end: &usize| cache[below + end] && star_matches(token, &text[column..*end]);
+            let end = if lazy {
+                (column..=text.len()).find(fits)?
+            } else {
+                (column..=text.len()).rev().find(fits)?
+            };
+            spans.push(column..end);
+            column = end;
+        } else if let Token::Optional(optional) = token {
+            // An optional token always takes the grapheme if the rest still matches
+            let takes = column < text.len()
+                && optional.matches_one(&text[column])
+                && cache[below + column + 1];
+            column += usize::from(takes);
+        } else {
+            column += 1;
+        }
+    }
+    Some(spans)
+}
+
+/// Replace each group in a pattern by the first of its alternatives that still lets the
+/// pattern match the text, going from left to right, like alternation in a regular
+/// expression.
+///
+/// Returns `None` if the pattern doesn't match. Each choice is checked with the full
+/// pattern, so this takes one match for every alternative rather than one for every way of
+/// combining them.
+fn choose_alternatives<U: Unit>(
+    pattern: &[Token<U::Owned>],
+    text: &[U],
+) -> Option<Vec<Token<U::Owned>>> {
+    if !match_with_cache(pattern, text) {
+        return None;
+    }
+    let mut pattern = pattern.to_vec();
+    while let Some((i, Token::Alternatives(alternatives))) = pattern
+        .iter()
+        .enumerate()
+        .find(|(_, token)| matches!(token, Token::Alternatives(_)))
+    {
+        // Since the pattern matches, one of the alternatives has to
+        pattern = alternatives
+            .iter()
+            .map(|alternative| {
+                let mut chosen = pattern[..i].to_vec();
+                chosen.extend(alternative.iter().cloned());
+                chosen.extend(pattern[i + 1..].iter().cloned());
+                chosen
+            })
+            .find(|chosen| match_with_cache(chosen, text))?;
+    }
+    Some(pattern)
+}
+
+/// Checks if a star can match all of the given graphemes
+fn star_matches<U: Unit>(star: &Token<U::Owned>, text: &[U]) -> bool {
+    match star {
+        Token::AnyRunInSegment(separator) => {
+            text.iter().all(|unit| unit.scalar() != Some(*separator))
+        }
+        Token::AnySegments(separator) => match text.last() {
+            Some(unit) => unit.scalar() == Some(*separator),
+            None => true,
+        },
+        _ => true,
+    }
+}
+
+/// The number of graphemes in every text the pattern matches, if the pattern has no stars,
+/// optional tokens or groups, so that each of its tokens matches exactly one grapheme.
+///
+/// A text of any other length can be rejected without matching it at all.
+pub(crate) fn fixed_length<O>(pattern: &[Token<O>]) -> Option<usize> {
+    pattern
+        .iter()
+        .all(|token| {
+            matches!(
+                token,
+                Token::Literal(_) | Token::AnyOne | Token::Class { .. }
+            )
+        })
+        .then_some(pattern.len())
+}
+
+/// The fewest graphemes a text the pattern matches can have
+pub(crate) fn min_length<O>(pattern: &[Token<O>]) -> usize {
+    pattern
+        .iter()
+        .map(|token| match token {
+            Token::Alternatives(alternatives) => alternatives
+                .iter()
+                .map(|alternative| min_length(alternative))
+                .min()
+                .unwrap_or(0),
+            token if token.matches_empty() => 0,
+            _ => 1,
+        })
+        .sum()
+}
+
+/// The most graphemes a text the pattern matches can have, or `None` if there's a star
+/// which lets it be as long as it likes
+pub(crate) fn max_length<O>(pattern: &[Token<O>]) -> Option<usize> {
+    pattern
+        .iter()
+        .map(|token| match token {
+            Token::Alternatives(alternatives) => alternatives
+                .iter()
+                .map(|alternative| max_length(alternative))
+                .try_fold(0, |longest, length| Some(longest.max(length?))),
+            token if token.is_star() => None,
+            _ => Some(1),
+        })
+        .sum()
+}
+
+/// Checks if a pattern can be matched with `greedy_match`, rather than the full dynamic
+/// solution in `match_with_cache`
+pub(crate) fn is_greedy_matchable<O>(pattern: &[Token<O>]) -> bool {
+    pattern
+        .iter()
+        .all(|token| matches!(token, Token::Literal(_) | Token::AnyOne | Token::AnyRun))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    #[test]
+    fn long_inputs_dont_overflow_the_stack() {
+        let pattern = (0..500)
+            .flat_map(|_| [Token::AnyOne, Token::AnyRun])
+            .collect::<Vec<Token>>();
+        let text = ["a"; 2_000];
+        assert_eq!(true, match_with_cache(&pattern, &text));
+        assert_eq!(false, match_with_cache(&pattern, &text[..499]));
+    }
+
+    #[test]
+    fn long_pattern_and_long_text() {
+        // The full table would have 30 million cells, but we only ever keep two rows
+        let pattern = (0..150)
+            .flat_map(|_| [Token::AnyRun, Token::Literal(String::from("a"))])
+            .collect::<Vec<Token>>();
+        let text = vec!["a"; 200_000];
+        assert_eq!(true, match_with_cache(&pattern, &text));
+    }
+
+    #[test]
+    fn empty_pattern_only_matches_empty_text() {
+        let empty: [&str; 0] = [];
+        assert_eq!(true, match_with_cache(&[], &empty));
+        assert_eq!(false, match_with_cache(&[], &["a"]));
+        assert_eq!(true, greedy_match(&[], &empty));
+        assert_eq!(false, greedy_match(&[], &["a"]));
+    }
+
+    fn spare_rows() -> usize {
+        SPARE_ROWS.with(|spare| spare.borrow().len())
+    }
+
+    #[test]
+    fn rows_are_reused_between_matches() {
+        let pattern = tokens("*a[bc]");
+        assert_eq!(false, match_with_cache(&pattern, &["x"; 1_000]));
+        let spare = spare_rows();
+        assert!(spare >= 2);
+
+        // A row left over from a longer text doesn't leak in to a shorter one
+        assert_eq!(true, match_with_cache(&pattern, &["x", "a", "b"]));
+        assert_eq!(false, match_with_cache(&pattern, &["a", "x"]));
+        assert_eq!(true, match_with_cache(&pattern, &["a", "c"]));
+        assert_eq!(false, match_with_cache(&pattern, &["a"]));
+        assert_eq!(spare, spare_rows());
+    }
+
+    #[test]
+    fn rows_are_reused_with_braces() {
+        let pattern = token::parse_path(
+            &["{", "a", ",", "{", "b", ",", "c", "}", "*", "}", "d"],
+            &token::Syntax {
+                escapes: true,
+                braces: true,
+                ..token::Syntax::default()
+            },
+        );
+        for _ in 0..3 {
+            assert_eq!(true, match_with_cache(&pattern, &["a", "d"]));
+            assert_eq!(true, match_with_cache(&pattern, &["c", "x", "x", "d"]));
+            assert_eq!(false, match_with_cache(&pattern, &["a", "x", "d"]));
+            assert!(spare_rows() <= MAX_SPARE_ROWS);
+        }
+    }
+
+    fn tokens(pattern: &str) -> Vec<Token> {
+        let graphemes = pattern.graphemes(true).collect::<Vec<&str>>();
+        token::parse(&graphemes, true)
+    }
+
+    #[test]
+    fn greedy_match_agrees_with_cache() {
+        let cases = [
+            ("alyib", "a?y*b"),
+            ("abcd", "a*b?"),
+            ("", "*"),
+            ("", "?"),
+            ("daaadabadmanda", "da*da*da"),
+            ("testingmore", "testing"),
+            ("more, testing", "testing"),
+            ("xx", "*?"),
+            ("a*", "*"),
+            ("mississippi", "m*iss*ppi"),
+            ("mississippi", "m*iss*ppx"),
+            ("aaaaaaaaab", "*a*a*b"),
+            ("aaaaaaaaaa", "*a*a*b"),
+            ("abc", "abc*"),
+            ("abc", "abc?"),
+        ];
+        for (text, pattern) in cases {
+            let pattern = tokens(pattern);
+            let text = text.graphemes(true).collect::<Vec<&str>>();
+            assert!(is_greedy_matchable(&pattern));
+            let matched = match_with_cache(&pattern, &text);
+            assert_eq!(matched, greedy_match(&pattern, &text));
+            assert_eq!(
+                matched,
+                greedy_match_from_end(&pattern, text.iter().copied())
+            );
+        }
+    }
+
+    #[test]
+    fn capture_spans_of_non_match() {
+        assert_eq!(None, capture_spans(&tokens("a*b"), &["a", "c"], false));
+    }
+
+    #[test]
+    fn capture_spans_are_greedy() {
+        assert_eq!(
+            Some(vec![0..3, 4..5]),
+            capture_spans(&tokens("*-*"), &["a", "-", "b", "-", "c"], false)
+        );
+    }
+
+    #[test]
+    fn lazy_capture_spans() {
+        assert_eq!(
+            Some(vec![0..1, 2..5]),
+            capture_spans(&tokens("*-*"), &["a", "-", "b", "-", "c"], true)
+        );
+        assert_eq!(
+            Some(vec![1..1, 1..1]),
+            capture_spans(&tokens("a**"), &["a"], true)
+        );
+    }
+
+    #[test]
+    fn capture_spans_can_be_empty() {
+        assert_eq!(
+            Some(vec![1..1, 1..1]),
+            capture_spans(&tokens("a**"), &["a"], false)
+        );
+    }
+
+    #[test]
+    fn fewest_edits_counts_substitutions_and_gaps() {
+        let edits = |pattern: &str, text: &str| {
+            let graphemes = text.graphemes(true).collect::<Vec<&str>>();
+            fewest_edits(&tokens(pattern), &graphemes, 3)
+        };
+        assert_eq!(Some(0), edits("color", "color"));
+        assert_eq!(Some(1), edits("colour", "color"));
+        assert_eq!(Some(1), edits("color", "colour"));
+        assert_eq!(Some(1), edits("color", "calor"));
+        assert_eq!(Some(2), edits("receive", "recieve"));
+        // Stars don't need any edits, but `?` and classes do
+        assert_eq!(Some(0), edits("c*r", "colour"));
+        assert_eq!(Some(1), edits("a?", "a"));
+        assert_eq!(Some(1), edits("[0-9]x", "ax"));
+        // Nothing can be added for a class without anything in it
+        assert_eq!(None, edits("a[]", "a"));
+        assert_eq!(None, edits("abcd", "wxyz"));
+    }
+
+    #[test]
+    fn classes_are_not_greedy_matchable() {
+        assert_eq!(true, is_greedy_matchable(&tokens("a*b?")));
+        assert_eq!(false, is_greedy_matchable(&tokens("a*[bc]")));
+    }
+}
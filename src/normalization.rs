@@ -0,0 +1,60 @@
+use std::borrow::Cow;
+use unicode_normalization::{is_nfc_quick, is_nfd_quick, IsNormalized, UnicodeNormalization};
+
+/// A unicode normalization form, which patterns and texts can be converted to before
+/// matching.
+///
+/// The same character can often be written in several ways, for example `é` is both the
+/// single code point U+00E9 and an `e` followed by the combining accent U+0301. Converting
+/// both the pattern and the text to the same form makes these match each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Normalization {
+    /// Canonical composition, which combines characters where possible
+    Nfc,
+    /// Canonical decomposition, which splits characters in to their parts
+    Nfd,
+}
+
+impl Normalization {
+    /// Convert a string to this form, only allocating if it isn't in the form already.
+    pub(crate) fn apply<'t>(self, text: Cow<'t, str>) -> Cow<'t, str> {
+        let quick = match self {
+            Normalization::Nfc => is_nfc_quick(text.chars()),
+            Normalization::Nfd => is_nfd_quick(text.chars()),
+        };
+        if quick == IsNormalized::Yes {
+            return text;
+        }
+        match self {
+            Normalization::Nfc => Cow::Owned(text.nfc().collect()),
+            Normalization::Nfd => Cow::Owned(text.nfd().collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forms_are_converted() {
+        let composed = "caf\u{e9}";
+        let decomposed = "cafe\u{301}";
+        assert_eq!(
+            composed,
+            Normalization::Nfc.apply(Cow::Borrowed(decomposed))
+        );
+        assert_eq!(
+            decomposed,
+            Normalization::Nfd.apply(Cow::Borrowed(composed))
+        );
+    }
+
+    #[test]
+    fn normalized_text_is_borrowed() {
+        let text = Normalization::Nfc.apply(Cow::Borrowed("caf\u{e9}"));
+        assert_eq!(true, matches!(text, Cow::Borrowed(_)));
+        let text = Normalization::Nfd.apply(Cow::Borrowed("cafe\u{301}"));
+        assert_eq!(true, matches!(text, Cow::Borrowed(_)));
+    }
+}
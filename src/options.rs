@@ -0,0 +1,351 @@
+use crate::error::PatternError;
+use crate::granularity::Granularity;
+use crate::normalization::Normalization;
+use crate::token::Syntax;
+
+/// The ways in which a pattern can be matched, bundled together.
+///
+/// The defaults match the same way as `is_wildcard_match`, with backslash escapes, case
+/// sensitive, without normalization, outside of path mode, without braces, with `|` between
+/// alternatives and anchored at both ends of the text. Each method changes one
+/// option and returns the options, so they can be chained.
+///
+/// # Examples
+/// ```
+/// use pyglob::{is_wildcard_match_with, MatchOptions};
+/// let options = MatchOptions::new().case_insensitive(true).separator('/');
+/// assert_eq!(is_wildcard_match_with("SRC/main.rs", "src/*.RS", &options), true);
+/// assert_eq!(is_wildcard_match_with("SRC/a/main.rs", "src/*.RS", &options), false);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MatchOptions {
+    pub(crate) escapes: bool,
+    pub(crate) case_insensitive: bool,
+    pub(crate) case_folding: bool,
+    pub(crate) normalization: Option<Normalization>,
+    pub(crate) collapse_whitespace: bool,
+    pub(crate) trim: bool,
+    pub(crate) granularity: Granularity,
+    pub(crate) separator: Option<char>,
+    pub(crate) forward_slashes: bool,
+    pub(crate) multiline: bool,
+    pub(crate) braces: bool,
+    pub(crate) alternation: bool,
+    pub(crate) lazy_captures: bool,
+    pub(crate) optional_question_marks: bool,
+    pub(crate) literal: bool,
+    pub(crate) fnmatch: bool,
+    pub(crate) any_run: char,
+    pub(crate) any_one: char,
+    pub(crate) anchor_start: bool,
+    pub(crate) anchor_end: bool,
+    pub(crate) budget: Option<usize>,
+}
+
+impl MatchOptions {
+    /// The budget that `try_match` uses unless it's changed, which is ten million steps.
+    ///
+    /// This takes in the order of tens of milliseconds to match, and allows for example a
+    /// pattern of a thousand tokens against a text of ten thousand graphemes.
+    pub const DEFAULT_BUDGET: usize = 10_000_000;
+
+    /// The default options.
+    pub fn new() -> Self {
+        MatchOptions {
+            escapes: true,
+            case_insensitive: false,
+            case_folding: false,
+            normalization: None,
+            collapse_whitespace: false,
+            trim: false,
+            granularity: Granularity::Graphemes,
+            separator: None,
+            forward_slashes: false,
+            multiline: false,
+            braces: false,
+            alternation: true,
+            lazy_captures: false,
+            optional_question_marks: false,
+            literal: false,
+            fnmatch: false,
+            any_run: '*',
+            any_one: '?',
+            anchor_start: true,
+            anchor_end: true,
+            budget: Some(Self::DEFAULT_BUDGET),
+        }
+    }
+
+    /// Options for matching Windows paths, with a backslash as the separator and ignoring
+    /// case, like the file system does. Backslashes are separators rather than escapes,
+    /// and a `**` segment matches any number of segments, as in every path mode. Forward
+    /// slashes are ordinary characters unless `forward_slashes` is turned on.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::{is_wildcard_match_with, MatchOptions};
+    /// let options = MatchOptions::windows_paths();
+    /// assert_eq!(is_wildcard_match_with("c:\\users\\alice", "C:\\Users\\*", &options), true);
+    /// assert_eq!(is_wildcard_match_with("c:\\users\\alice\\x", "C:\\Users\\*", &options), false);
+    /// ```
+    pub fn windows_paths() -> Self {
+        Self::new().separator('\\').case_insensitive(true)
+    }
+
+    /// Whether a backslash escapes the character after it. On by default.
+    pub fn escapes(mut self, escapes: bool) -> Self {
+        self.escapes = escapes;
+        self
+    }
+
+    /// Whether to ignore case, by lowercasing both the pattern and the text. Off by default.
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Whether to ignore case with full unicode case folding, like Python's `str.casefold`,
+    /// rather than by lowercasing. Off by default. Folding makes `ß` match `ss`, which
+    /// lowercasing doesn't, and takes the place of lowercasing if `case_insensitive` is on
+    /// as well. See `is_wildcard_match_casefold`.
+    pub fn case_folding(mut self, case_folding: bool) -> Self {
+        self.case_folding = case_folding;
+        self
+    }
+
+    /// Convert both the pattern and the text to a normalization form before matching. By
+    /// default nothing is normalized.
+    pub fn normalization(mut self, form: Normalization) -> Self {
+        self.normalization = Some(form);
+        self
+    }
+
+    /// Whether a run of whitespace in the pattern matches any run of whitespace in the text,
+    /// however long and whichever whitespace characters it's made of. Off by default. Each
+    /// run in both the pattern and the text is replaced with a single space before
+    /// matching, so `a b` matches `a  b`, or `a` and `b` with a tab between them, but not
+    /// `ab`, and a `?` or a class matches a whole run. Whitespace is what
+    /// `char::is_whitespace` says it is.
+    pub fn collapse_whitespace(mut self, collapse_whitespace: bool) -> Self {
+        self.collapse_whitespace = collapse_whitespace;
+        self
+    }
+
+    /// Whether whitespace at the start and the end of the text is ignored, for matching what
+    /// someone typed in. Off by default. Only the text is trimmed, not the pattern, so `foo`
+    /// matches `  foo  `, while ` foo` doesn't match anything with this on. Whitespace in the
+    /// middle of the text is matched as usual, see `collapse_whitespace` for that.
+    /// Whitespace is what `char::is_whitespace` says it is.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::{is_wildcard_match_with, MatchOptions};
+    /// let options = MatchOptions::new().trim(true);
+    /// assert_eq!(is_wildcard_match_with("  foo\n", "foo", &options), true);
+    /// assert_eq!(is_wildcard_match_with("  foo\n", "foo", &MatchOptions::new()), false);
+    /// assert_eq!(is_wildcard_match_with(" f o o ", "foo", &options), false);
+    /// ```
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// Whether a `?` or a class matches one grapheme, one unicode scalar value or one byte
+    /// of the text. Graphemes by default. See `Granularity` for how they differ.
+    pub fn granularity(mut self, granularity: Granularity) -> Self {
+        self.granularity = granularity;
+        self
+    }
+
+    /// Match in path mode, with the given separator between the segments of a path. See
+    /// `WildcardPattern::path` for how the wildcards change. If the separator is a
+    /// backslash, backslashes are not treated as escapes.
+    pub fn separator(mut self, separator: char) -> Self {
+        self.separator = Some(separator);
+        self
+    }
+
+    /// Whether a `/` is also a separator in path mode, as well as the separator itself.
+    /// Off by default. Every `/` in both the pattern and the text is replaced with the
+    /// separator before matching, so the captures of a text have the separator in place of
+    /// its forward slashes. Outside of path mode this does nothing.
+    pub fn forward_slashes(mut self, forward_slashes: bool) -> Self {
+        self.forward_slashes = forward_slashes;
+        self
+    }
+
+    /// Whether the text is matched a line at a time, like a regular expression without the
+    /// `s` flag, so that neither `*` nor `?` match a `\n`. Off by default. A run of two or
+    /// more stars, `**`, matches any number of graphemes including newlines, so `a*b`
+    /// doesn't match `a\nb` but `a**b` does. Classes aren't affected, so `[!x]` still
+    /// matches a newline, like `[^x]` does in a regular expression. A Windows line ending
+    /// `\r\n` is a single grapheme, which isn't a newline on its own, so text with those is
+    /// best matched by `Granularity::Scalars`.
+    ///
+    /// This is much like path mode with a newline as the separator, except that `**` matches
+    /// across newlines wherever it is, rather than only as a whole segment. In path mode the
+    /// separator is what `*` and `?` don't match, so this does nothing there. Neither does it
+    /// with `collapse_whitespace`, which turns each newline in to a space before matching.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::{is_wildcard_match_with, MatchOptions};
+    /// let options = MatchOptions::new().multiline(true);
+    /// assert_eq!(is_wildcard_match_with("a\nb", "a*b", &options), false);
+    /// assert_eq!(is_wildcard_match_with("a\nb", "a?b", &options), false);
+    /// assert_eq!(is_wildcard_match_with("a\nb", "a**b", &options), true);
+    /// assert_eq!(is_wildcard_match_with("a\nb", "a*b", &MatchOptions::new()), true);
+    /// ```
+    pub fn multiline(mut self, multiline: bool) -> Self {
+        self.multiline = multiline;
+        self
+    }
+
+    /// Whether `{a,b}` matches either `a` or `b`. Off by default, so that braces and commas
+    /// are ordinary characters. See `is_wildcard_match_braces` for the details.
+    pub fn braces(mut self, braces: bool) -> Self {
+        self.braces = braces;
+        self
+    }
+
+    /// Whether a `|` outside of classes and braces splits the pattern in to alternatives,
+    /// so that `foo|bar*` matches `foo` and `bartender`. On by default. A `|` splits the
+    /// whole pattern, so it binds less tightly than anything else, and an alternative can
+    /// be empty. Inside of a class or a group of braces it's an ordinary grapheme, and so
+    /// is an escaped `\\|`. It's also ordinary in `fnmatch` mode, and when it's one of the
+    /// `wildcards`.
+    pub fn alternation(mut self, alternation: bool) -> Self {
+        self.alternation = alternation;
+        self
+    }
+
+    /// Whether each star captures as little of the text as it can, rather than as much.
+    /// Off by default, so `*-*` captures `a-b` and `c` from `a-b-c`, and with this on it
+    /// captures `a` and `b-c`. Only `WildcardPattern::captures` is affected, not whether a
+    /// pattern matches.
+    pub fn lazy_captures(mut self, lazy_captures: bool) -> Self {
+        self.lazy_captures = lazy_captures;
+        self
+    }
+
+    /// Whether `?` makes the grapheme or class before it optional, like in a regular
+    /// expression, rather than matching any one grapheme. Off by default. With this on,
+    /// `colou?r` matches both `color` and `colour`, and `{a,b}?` matches `a`, `b` or
+    /// nothing. A `?` at the start of the pattern is a literal.
+    pub fn optional_question_marks(mut self, optional_question_marks: bool) -> Self {
+        self.optional_question_marks = optional_question_marks;
+        self
+    }
+
+    /// Whether every grapheme of the pattern is matched literally, so that `*`, `?`,
+    /// classes, braces and backslashes are all ordinary graphemes. Off by default. The
+    /// other options still apply, so a literal pattern can ignore case or be normalized.
+    /// See `literal_match`.
+    pub fn literal(mut self, literal: bool) -> Self {
+        self.literal = literal;
+        self
+    }
+
+    /// Whether to parse the pattern the same way as Python's `fnmatch.fnmatchcase`. Off by
+    /// default. See `is_wildcard_match_fnmatch` for the differences this makes.
+    pub fn fnmatch(mut self, fnmatch: bool) -> Self {
+        self.fnmatch = fnmatch;
+        self
+    }
+
+    /// Which graphemes are the wildcards, in place of `*` for any number of graphemes and
+    /// `?` for any one grapheme. With `%` and `_`, patterns are written like the ones of
+    /// SQL's `LIKE`, so `a%` matches `abc`. Everything else about the pattern stays the same,
+    /// so `*` and `?` become ordinary graphemes, while classes and escapes still work, and
+    /// in path mode `%%` is the globstar.
+    ///
+    /// Returns an error if both wildcards are the same grapheme, or if either of them
+    /// already means something with the options set so far: `[` and `]`, a backslash if
+    /// it's an escape, `{`, `}` and `,` with `braces`, `|` with `alternation`, and the
+    /// separator in path mode. Options which add meanings are best set before this.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::{is_wildcard_match_with, MatchOptions};
+    /// let options = MatchOptions::new().wildcards('%', '_').unwrap();
+    /// assert_eq!(is_wildcard_match_with("abc", "a%", &options), true);
+    /// assert_eq!(is_wildcard_match_with("abc", "a_", &options), false);
+    /// assert_eq!(is_wildcard_match_with("abc", "a*", &options), false);
+    /// ```
+    pub fn wildcards(mut self, any_run: char, any_one: char) -> Result<Self, PatternError> {
+        if any_run == any_one {
+            return Err(PatternError::SameWildcards { wildcard: any_run });
+        }
+        let syntax = self.syntax();
+        let reserved = |c: char| {
+            matches!(c, '[' | ']')
+                || (c == '\\' && syntax.escapes)
+                || (matches!(c, '{' | '}' | ',') && syntax.braces)
+                || (c == '|' && syntax.alternation)
+                || Some(c) == syntax.separator
+        };
+        if let Some(wildcard) = [any_run, any_one].into_iter().find(|&c| reserved(c)) {
+            return Err(PatternError::ReservedWildcard { wildcard });
+        }
+        self.any_run = any_run;
+        self.any_one = any_one;
+        Ok(self)
+    }
+
+    /// Whether the pattern has to match from the start of the text. On by default. With
+    /// this off, the pattern matches if it matches any suffix of the text, as if it started
+    /// with a `*`, so a pattern which already starts with a `*` is unaffected.
+    pub fn anchor_start(mut self, anchor_start: bool) -> Self {
+        self.anchor_start = anchor_start;
+        self
+    }
+
+    /// Whether the pattern has to match up to the end of the text. On by default. With this
+    /// off, the pattern matches if it matches any prefix of the text, as if it ended with a
+    /// `*`, so `abc` matches `abcdef`, and a pattern which already ends with a `*` is
+    /// unaffected. Turning off both this and `anchor_start` matches like
+    /// `is_wildcard_search`.
+    pub fn anchor_end(mut self, anchor_end: bool) -> Self {
+        self.anchor_end = anchor_end;
+        self
+    }
+
+    /// The most work `try_match` and `WildcardPattern::try_matches` will do, counted as the
+    /// number of tokens in the pattern times the number of graphemes in the text. Going over
+    /// it is an error rather than a slow match. `None` turns the limit off. By default this
+    /// is `DEFAULT_BUDGET`.
+    ///
+    /// Matching which can't fail, like `is_wildcard_match_with` and
+    /// `WildcardPattern::matches`, ignores the budget.
+    pub fn budget(mut self, budget: Option<usize>) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// The parts of the pattern syntax that these options turn on
+    pub(crate) fn syntax(&self) -> Syntax {
+        Syntax {
+            // A backslash can't be both the separator and an escape, and `fnmatch` has no
+            // escapes at all
+            escapes: self.escapes && !self.fnmatch && self.separator != Some('\\'),
+            separator: self.separator,
+            multiline: self.multiline && self.separator.is_none(),
+            braces: self.braces,
+            alternation: self.alternation
+                && !self.fnmatch
+                && self.any_run != '|'
+                && self.any_one != '|',
+            optional_question_marks: self.optional_question_marks,
+            literal: self.literal,
+            fnmatch: self.fnmatch,
+            any_run: self.any_run,
+            any_one: self.any_one,
+        }
+    }
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,2084 @@
+use pyo3::basic::CompareOp;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyString;
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::OsStr;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::casefold::casefold;
+use crate::granularity::Granularity;
+use crate::intern::Interned;
+use crate::matcher::{
+    capture_spans, fewest_edits, fixed_length, greedy_match_from_end, greedy_match_iter,
+    is_greedy_matchable, leftmost_longest, matches_prefix, max_length, min_length, rows,
+    specificity, stars, wildcards,
+};
+use crate::normalization::Normalization;
+use crate::options::MatchOptions;
+use crate::python::Text;
+use crate::token::{self, Token, Unit};
+use crate::{
+    is_plain_ascii, match_tokens, normalize_wildcards, preprocessing, remove_duplicate_stars,
+    validate, PatternError,
+};
+
+/// How a pattern is pickled in Python: the pattern as it was written, and each of its
+/// options in the order they're declared in `MatchOptions`, with the normalization form as
+/// `"NFC"` or `"NFD"`. `case_folding`, `collapse_whitespace`, `trim`, `granularity`,
+/// `forward_slashes`, `multiline`, `alternation`, `fnmatch` and the wildcards can't be set
+/// from Python, so they're left out.
+type PickleState = (
+    String,
+    bool,
+    bool,
+    Option<String>,
+    Option<char>,
+    bool,
+    bool,
+    bool,
+    bool,
+    bool,
+    bool,
+    Option<usize>,
+);
+
+/// A wildcard pattern that has been compiled ahead of time.
+///
+/// Compiling a pattern splits it in to graphemes and preprocesses it once, so that
+/// matching the same pattern against many strings only has to segment the text.
+///
+/// # Examples
+/// ```
+/// use pyglob::WildcardPattern;
+/// let pattern = WildcardPattern::new("*.rs");
+/// assert_eq!(pattern.matches("main.rs"), true);
+/// assert_eq!(pattern.matches("main.py"), false);
+/// ```
+#[pyclass(module = "pyglob")]
+#[derive(Clone)]
+pub struct WildcardPattern {
+    // The pattern as it was written, so that it can be compiled again after pickling
+    source: String,
+    tokens: Vec<Token>,
+    // The tokens which are matched, see `normalize_wildcards`
+    match_tokens: Vec<Token>,
+    // The same tokens with their graphemes numbered, see `Interned`
+    interned: Interned,
+    // The same tokens matched byte by byte, if the pattern is plain ASCII or is matched by
+    // bytes, and the ones of those which are matched
+    byte_tokens: Option<Vec<Token<u8>>>,
+    byte_match_tokens: Option<Vec<Token<u8>>>,
+    options: MatchOptions,
+    // Whether the pattern is simple enough for the greedy matcher
+    greedy: bool,
+    // Whether the greedy matcher starts from the end, see `starts_from_end`
+    from_end: bool,
+    // The number of graphemes a text needs to match, if the pattern has no stars
+    length: Option<usize>,
+    // The number of stars, including those inside of braces
+    stars: usize,
+    // The number of rows of the matching table, see `matcher::rows`
+    rows: usize,
+}
+
+#[pymethods]
+impl WildcardPattern {
+    #[new]
+    fn py_new(pattern: Text<'_>) -> Self {
+        Self::new(&pattern)
+    }
+
+    /// Checks if the compiled pattern matches the given text.
+    ///
+    /// The GIL is released while matching.
+    #[pyo3(name = "matches")]
+    fn py_matches(&self, py: Python<'_>, text: Text<'_>) -> bool {
+        py.allow_threads(|| self.matches(&text))
+    }
+
+    /// Returns what each `*` in the pattern matched, or `None` if the pattern doesn't
+    /// match the text.
+    ///
+    /// The GIL is released while matching.
+    #[pyo3(name = "captures")]
+    fn py_captures(&self, py: Python<'_>, text: Text<'_>) -> Option<Vec<String>> {
+        py.allow_threads(|| self.captures(&text))
+    }
+
+    /// The longest run of literal graphemes at the start of the pattern, up to the first
+    /// wildcard.
+    ///
+    /// Every text the pattern matches starts with the prefix, so for a sorted list of texts
+    /// only those in the range starting with it need to be checked. Escaped wildcards are
+    /// literals, so `a\\*b*` has the prefix `a*b`. If the pattern ignores case or normalizes,
+    /// the prefix is lowercased or normalized as well.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::WildcardPattern;
+    /// assert_eq!(WildcardPattern::new("src/foo*").prefix(), "src/foo");
+    /// assert_eq!(WildcardPattern::new("*x").prefix(), "");
+    /// ```
+    pub fn prefix(&self) -> String {
+        self.tokens
+            .iter()
+            .map_while(|token| match token {
+                Token::Literal(literal) => Some(literal.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The longest run of literal graphemes at the end of the pattern, after the last
+    /// wildcard.
+    ///
+    /// Every text the pattern matches ends with the suffix, so a text which doesn't can be
+    /// rejected with `str::ends_with` before matching. Like the prefix, escaped wildcards
+    /// are literals, so `*\\*` has the suffix `*`. A pattern without any wildcards is its
+    /// own prefix and suffix.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::WildcardPattern;
+    /// assert_eq!(WildcardPattern::new("*.rs").suffix(), ".rs");
+    /// assert_eq!(WildcardPattern::new("x?").suffix(), "");
+    /// ```
+    pub fn suffix(&self) -> String {
+        let literals = self
+            .tokens
+            .iter()
+            .rev()
+            .map_while(|token| match token {
+                Token::Literal(literal) => Some(literal.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<&str>>();
+        literals.into_iter().rev().collect()
+    }
+
+    /// The runs of literal graphemes between the wildcards, in the order they come in the
+    /// pattern.
+    ///
+    /// Every text the pattern matches contains each of the segments, in this order and
+    /// without overlapping, so a text which doesn't can be rejected before matching it.
+    /// The first segment is the `prefix` and the last the `suffix`, if the pattern starts or
+    /// ends with a literal. Escaped wildcards are literals, as in `prefix`, and a group of
+    /// braces or an optional grapheme ends a segment the same way as a wildcard does.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::WildcardPattern;
+    /// let pattern = WildcardPattern::new("src/*/main_?.rs");
+    /// assert_eq!(pattern.literal_segments(), vec!["src/", "/main_", ".rs"]);
+    /// ```
+    pub fn literal_segments(&self) -> Vec<String> {
+        let mut segments = Vec::new();
+        let mut segment = String::new();
+        for token in &self.tokens {
+            match token {
+                Token::Literal(literal) => segment.push_str(literal),
+                _ if !segment.is_empty() => segments.push(std::mem::take(&mut segment)),
+                _ => {}
+            }
+        }
+        if !segment.is_empty() {
+            segments.push(segment);
+        }
+        segments
+    }
+
+    /// Checks if the pattern is made up of only literal graphemes, without any wildcards.
+    ///
+    /// Escaped wildcards are literals, so `\\*` is literal. A literal pattern only matches
+    /// its own `prefix`, so it can be compared with plain string equality instead.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::WildcardPattern;
+    /// assert_eq!(WildcardPattern::new("main.rs").is_literal(), true);
+    /// assert_eq!(WildcardPattern::new("*.rs").is_literal(), false);
+    /// ```
+    pub fn is_literal(&self) -> bool {
+        self.tokens
+            .iter()
+            .all(|token| matches!(token, Token::Literal(_)))
+    }
+
+    /// Checks if the pattern has any wildcards, the opposite of `is_literal`.
+    pub fn has_wildcards(&self) -> bool {
+        !self.is_literal()
+    }
+
+    /// The number of stars in the pattern, including those inside of braces.
+    ///
+    /// A run of stars counts as one, and so does a `**` in path mode.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::WildcardPattern;
+    /// assert_eq!(WildcardPattern::new("*.rs").num_stars(), 1);
+    /// assert_eq!(WildcardPattern::new("a**b*\\*").num_stars(), 2);
+    /// ```
+    pub fn num_stars(&self) -> usize {
+        self.stars
+    }
+
+    /// The number of wildcards in the pattern, including those inside of braces.
+    ///
+    /// Each star, `?` and class counts as one, and a run of stars counts as a single star,
+    /// the same as in `num_stars`. A `?` that makes the grapheme before it optional counts
+    /// too. Escaped wildcards are literals, and don't count.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::WildcardPattern;
+    /// assert_eq!(WildcardPattern::new("*_[0-9]?.rs").num_wildcards(), 3);
+    /// assert_eq!(WildcardPattern::new("a**b\\?").num_wildcards(), 1);
+    /// ```
+    pub fn num_wildcards(&self) -> usize {
+        wildcards(&self.tokens)
+    }
+
+    /// The fewest graphemes a text can have for the pattern to match it.
+    ///
+    /// Each literal, `?` and class needs one grapheme, and stars and optional graphemes
+    /// none. A group of braces needs as many as its shortest alternative. A text that is
+    /// shorter can be rejected without matching it.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::WildcardPattern;
+    /// assert_eq!(WildcardPattern::new("a?c").min_len(), 3);
+    /// assert_eq!(WildcardPattern::new("a*c").min_len(), 2);
+    /// ```
+    pub fn min_len(&self) -> usize {
+        match &self.byte_tokens {
+            Some(tokens) if self.options.granularity == Granularity::Bytes => min_length(tokens),
+            _ => min_length(&self.tokens),
+        }
+    }
+
+    /// The most graphemes a text can have for the pattern to match it, or `None` if there's
+    /// a star, so that it can match a text of any length.
+    ///
+    /// A group of braces can match as many as its longest alternative, and an optional
+    /// grapheme one. A pattern without stars or groups matches exactly `max_len` graphemes,
+    /// the same as `min_len`.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::WildcardPattern;
+    /// assert_eq!(WildcardPattern::new("a?c").max_len(), Some(3));
+    /// assert_eq!(WildcardPattern::new("a*c").max_len(), None);
+    /// ```
+    pub fn max_len(&self) -> Option<usize> {
+        match &self.byte_tokens {
+            Some(tokens) if self.options.granularity == Granularity::Bytes => max_length(tokens),
+            _ => max_length(&self.tokens),
+        }
+    }
+
+    /// A rough estimate of how much work matching a text costs, for each grapheme of the
+    /// text, in the worst case.
+    ///
+    /// This is the number of tokens in the pattern, including the ones inside of braces,
+    /// which is what `MatchOptions::budget` counts. A run of stars and question marks, like
+    /// `*?*`, counts as its question marks and a single star. Multiplied by the number of
+    /// graphemes in a text, it's the most steps matching that text can take. Many patterns
+    /// are matched in far fewer, but a pattern with a high estimate can be given a budget
+    /// or checked on another thread. Like `num_stars`, this is worked out when the pattern
+    /// is compiled.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::WildcardPattern;
+    /// assert_eq!(WildcardPattern::new("*.rs").complexity_hint(), 4);
+    /// ```
+    pub fn complexity_hint(&self) -> usize {
+        self.rows
+    }
+
+    /// How specific the pattern is, to order rules so that the most specific one is tried
+    /// first, by sorting on it in reverse.
+    ///
+    /// Each literal grapheme counts 3, a class 2 and a `?` 1, and each star takes 1 away,
+    /// so a pattern with more literals is more specific, and of two with the same literals
+    /// the one with fewer stars is. Of a group of braces, only the least specific
+    /// alternative counts, and an optional grapheme doesn't count at all, since either can
+    /// match as little as that. A pattern of only stars has a specificity of 0. The
+    /// specificity is a heuristic, a more specific pattern doesn't have to match fewer
+    /// texts.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::WildcardPattern;
+    /// assert_eq!(WildcardPattern::new("main.rs").specificity(), 21);
+    /// assert_eq!(WildcardPattern::new("*.rs").specificity(), 8);
+    /// ```
+    pub fn specificity(&self) -> usize {
+        specificity(&self.tokens).max(0) as usize
+    }
+
+    /// Describes how the pattern was parsed, with a line for each literal, wildcard and
+    /// class, and the alternatives of a group indented below it.
+    ///
+    /// This is for finding out why a pattern does or doesn't match a text, for example
+    /// where a backslash escaped something, or which graphemes a literal is made of. The
+    /// description stays the same for as long as the pattern is parsed the same way, so it
+    /// can be compared in tests. A pattern that isn't anchored at an end has a star there.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::WildcardPattern;
+    /// assert_eq!(
+    ///     WildcardPattern::new("\\*[!a-c]?").explain(),
+    ///     "literal \"*\"\none grapheme not in \"a\" to \"c\"\nany one grapheme\n"
+    /// );
+    /// ```
+    pub fn explain(&self) -> String {
+        token::explain(&self.tokens, self.options.granularity)
+    }
+
+    /// The pattern as it was compiled, written back out the way `Display` writes it, for
+    /// logging and as a key for telling patterns apart.
+    ///
+    /// Runs of stars are a single star, escapes which aren't needed are left out, and if the
+    /// pattern ignores case or normalizes, it's lowercased or normalized. Unlike `Display`,
+    /// the question marks in a run of wildcards are written before its star, so `*?` and
+    /// `?*` have the same canonical string, since they match the same texts. Two patterns
+    /// compiled with the same options are equal exactly when their canonical strings are.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::WildcardPattern;
+    /// assert_eq!(WildcardPattern::new("a***b").canonical_string(), "a*b");
+    /// assert_eq!(WildcardPattern::new("\\x[!a]").canonical_string(), "x[!a]");
+    /// assert_eq!(WildcardPattern::new("a*?").canonical_string(), "a?*");
+    /// ```
+    pub fn canonical_string(&self) -> String {
+        struct Canonical<'p>(&'p WildcardPattern);
+        impl fmt::Display for Canonical<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                token::write_pattern(f, &self.0.match_tokens, &self.0.options.syntax())
+            }
+        }
+        Canonical(self).to_string()
+    }
+
+    /// The pattern in its canonical form, see the `Display` implementation.
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+
+    /// Like `repr` of a string, `WildcardPattern('*.rs')`, with the pattern in its canonical
+    /// form. The options the pattern was compiled with aren't shown.
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        let pattern = PyString::new(py, &self.to_string());
+        Ok(format!("WildcardPattern({})", pattern.repr()?))
+    }
+
+    /// Checks if the pattern matches the text, so that `"main.rs" in WildcardPattern("*.rs")`
+    /// works. The GIL is released while matching.
+    fn __contains__(&self, py: Python<'_>, text: Text<'_>) -> bool {
+        py.allow_threads(|| self.matches(&text))
+    }
+
+    /// Only `==` and `!=` are supported, which compare the patterns the same way as
+    /// `PartialEq`.
+    fn __richcmp__(&self, py: Python<'_>, other: &Self, op: CompareOp) -> PyObject {
+        match op {
+            CompareOp::Eq => (self == other).into_py(py),
+            CompareOp::Ne => (self != other).into_py(py),
+            _ => py.NotImplemented(),
+        }
+    }
+
+    /// The argument `WildcardPattern` is created with when unpickling, before
+    /// `__setstate__` compiles it again with the options it had.
+    fn __getnewargs__(&self) -> (String,) {
+        (self.source.clone(),)
+    }
+
+    /// The pattern as it was written together with its options, for pickling.
+    fn __getstate__(&self) -> PickleState {
+        let options = &self.options;
+        (
+            self.source.clone(),
+            options.escapes,
+            options.case_insensitive,
+            options.normalization.map(|form| match form {
+                Normalization::Nfc => String::from("NFC"),
+                Normalization::Nfd => String::from("NFD"),
+            }),
+            options.separator,
+            options.braces,
+            options.lazy_captures,
+            options.optional_question_marks,
+            options.literal,
+            options.anchor_start,
+            options.anchor_end,
+            options.budget,
+        )
+    }
+
+    /// Compiles the pattern again from what `__getstate__` gave when it was pickled.
+    fn __setstate__(&mut self, state: PickleState) -> PyResult<()> {
+        let (
+            source,
+            escapes,
+            case_insensitive,
+            normalization,
+            separator,
+            braces,
+            lazy_captures,
+            optional_question_marks,
+            literal,
+            anchor_start,
+            anchor_end,
+            budget,
+        ) = state;
+        let mut options = MatchOptions {
+            escapes,
+            case_insensitive,
+            case_folding: false,
+            normalization: None,
+            collapse_whitespace: false,
+            trim: false,
+            granularity: Granularity::Graphemes,
+            separator,
+            forward_slashes: false,
+            multiline: false,
+            braces,
+            alternation: true,
+            lazy_captures,
+            optional_question_marks,
+            literal,
+            fnmatch: false,
+            any_run: '*',
+            any_one: '?',
+            anchor_start,
+            anchor_end,
+            budget,
+        };
+        options.normalization = match normalization.as_deref() {
+            Some("NFC") => Some(Normalization::Nfc),
+            Some("NFD") => Some(Normalization::Nfd),
+            Some(form) => {
+                return Err(PyValueError::new_err(format!(
+                    "unsupported normalization form {:?} in pickled pattern",
+                    form
+                )))
+            }
+            None => None,
+        };
+        *self = Self::with_options(&source, &options);
+        Ok(())
+    }
+
+    /// A hash which agrees with `==`, so that patterns can be kept in sets and dicts.
+    fn __hash__(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+impl WildcardPattern {
+    /// Compiles a pattern containing the wildcards `*`, `?` and `[...]`.
+    ///
+    /// Backslashes escape the character after them, as in `is_wildcard_match`.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The pattern to compile.
+    pub fn new(pattern: &str) -> Self {
+        Self::with_options(pattern, &MatchOptions::new())
+    }
+
+    /// Checks if the compiled pattern matches the given text.
+    ///
+    /// The memory for matching is kept by each thread and reused for its next match, rather
+    /// than kept in the pattern, so a pattern can be matched from several threads at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text to check the pattern on.
+    pub fn matches(&self, text: &str) -> bool {
+        // Without a budget, matching can't fail
+        self.matches_prepared(&self.prepare(text), None) == Ok(true)
+    }
+
+    /// Checks if the compiled pattern matches the given text, unless that would take more
+    /// work than the budget in the options the pattern was compiled with.
+    ///
+    /// The work is counted as the number of tokens in the pattern, including the ones inside
+    /// of braces, times the number of graphemes in the text, and is checked before any
+    /// matching is done. This is meant for when both the pattern and the text come from
+    /// someone who can't be trusted. See `MatchOptions::budget`.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text to check the pattern on.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::{MatchOptions, PatternError, WildcardPattern};
+    /// let pattern = WildcardPattern::with_options("*a*", &MatchOptions::new().budget(Some(10)));
+    /// assert_eq!(pattern.try_matches("abc"), Ok(true));
+    /// assert_eq!(
+    ///     pattern.try_matches("abcd"),
+    ///     Err(PatternError::OverBudget { cost: 12, budget: 10 })
+    /// );
+    /// ```
+    pub fn try_matches(&self, text: &str) -> Result<bool, PatternError> {
+        self.matches_prepared(&self.prepare(text), self.options.budget)
+    }
+
+    /// Match a text which has already been lowercased and normalized as needed, failing if
+    /// that would cost more than the budget.
+    fn matches_prepared(&self, text: &str, budget: Option<usize>) -> Result<bool, PatternError> {
+        if let Some(byte_tokens) = self
+            .byte_match_tokens
+            .as_ref()
+            .filter(|_| self.by_bytes(text))
+        {
+            check_budget(self.rows, text.len(), budget)?;
+            return Ok(self.match_units(byte_tokens, text.as_bytes()));
+        }
+        // The greedy matcher never needs to look back further than the last star, so the
+        // text is split in to graphemes as it goes, rather than in to a list up front, which
+        // for a huge text would take far more memory than the text itself
+        let units = self.options.granularity.split(text);
+        if self.greedy && self.length.is_none() {
+            if budget.is_some() {
+                check_budget(self.rows, units.clone().count(), budget)?;
+            }
+            if self.from_end {
+                return Ok(greedy_match_from_end(&self.match_tokens, units));
+            }
+            return Ok(greedy_match_iter(&self.match_tokens, units));
+        }
+        let text_units = units.collect::<Vec<&str>>();
+        check_budget(self.rows, text_units.len(), budget)?;
+        let text_ids = self.interned.ids(&text_units);
+        Ok(self.match_units(&self.interned.tokens, &text_ids))
+    }
+
+    /// Whether the text is matched byte by byte, which it always is when the pattern is
+    /// matched by bytes. Otherwise every byte of plain ASCII is its own grapheme, so there's
+    /// no need to segment it.
+    fn by_bytes(&self, text: &str) -> bool {
+        self.options.granularity == Granularity::Bytes || is_plain_ascii(text)
+    }
+
+    /// Match the text, split in to graphemes or bytes, against the tokens for them.
+    ///
+    /// A pattern without stars can only match a text with exactly one grapheme for each
+    /// token, so a text of any other length is rejected before the real matching.
+    fn match_units<U: Unit>(&self, tokens: &[Token<U::Owned>], text: &[U]) -> bool {
+        if matches!(self.length, Some(length) if length != text.len()) {
+            return false;
+        }
+        if self.from_end {
+            let (tokens, text) = preprocessing(tokens, text);
+            return greedy_match_from_end(tokens, text.iter().copied());
+        }
+        match_tokens(tokens, text, self.greedy)
+    }
+
+    /// Checks if the text could still match the pattern once more of it is added to the
+    /// end, that is if the text is the start of at least one text the pattern matches.
+    ///
+    /// This is for text which arrives a piece at a time, so that it can be rejected as soon
+    /// as nothing that comes after could make it match. A text which matches the pattern is
+    /// also a start of one. The text is split in to graphemes as it is, so a piece which ends
+    /// before a combining accent is checked as if the grapheme ended there.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The start of the text to check the pattern on.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::WildcardPattern;
+    /// let pattern = WildcardPattern::new("abc*");
+    /// assert_eq!(pattern.matches_prefix_of("ab"), true);
+    /// assert_eq!(pattern.matches_prefix_of("ax"), false);
+    /// ```
+    pub fn matches_prefix_of(&self, text: &str) -> bool {
+        let text = if self.options.trim {
+            // Whitespace at the end could be where the text ends, or be followed by more of it
+            let text = text.trim_start();
+            if self.matches(text) {
+                return true;
+            }
+            prepare(text, &self.options)
+        } else {
+            self.prepare(text)
+        };
+        if let Some(byte_tokens) = self
+            .byte_match_tokens
+            .as_ref()
+            .filter(|_| self.by_bytes(&text))
+        {
+            return matches_prefix(byte_tokens, text.as_bytes());
+        }
+        let text_units = self.options.granularity.split(&text).collect::<Vec<&str>>();
+        matches_prefix(&self.match_tokens, &text_units)
+    }
+
+    /// Returns what each `*` in the pattern matched, if the pattern matches the text.
+    ///
+    /// When there are several ways for the text to match, each star takes as much as it
+    /// can, from left to right, unless the pattern was compiled with
+    /// `MatchOptions::lazy_captures`, in which case each takes as little as it can. Whether
+    /// the pattern matches doesn't depend on this. A star that matched nothing captures an
+    /// empty string.
+    /// Consecutive stars count as a single star. If the pattern ignores case or normalizes,
+    /// the captures are taken from the lowercased or normalized text.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text to check the pattern on.
+    pub fn captures(&self, text: &str) -> Option<Vec<String>> {
+        let text = self.prepare(text);
+        let lazy = self.options.lazy_captures;
+        if let Some(byte_tokens) = self.byte_tokens.as_ref().filter(|_| self.by_bytes(&text)) {
+            let bytes = text.as_bytes();
+            // A capture can end part of the way through a character matched by bytes
+            let spans = capture_spans(byte_tokens, bytes, lazy)?;
+            return Some(
+                spans
+                    .into_iter()
+                    .map(|span| String::from_utf8_lossy(&bytes[span]).into_owned())
+                    .collect(),
+            );
+        }
+        let text_units = self.options.granularity.split(&text).collect::<Vec<&str>>();
+
+        let spans = capture_spans(&self.tokens, &text_units, lazy)?;
+        Some(
+            spans
+                .into_iter()
+                .map(|span| text_units[span].concat())
+                .collect(),
+        )
+    }
+
+    /// Finds where in the text the pattern matches, as the byte offsets of the start and
+    /// the end of the part it matches, or `None` if it doesn't match any part of it.
+    ///
+    /// The leftmost part wins, and of the parts that start there, the longest. The part can
+    /// be empty, so `*` finds all of any text, and a pattern like `x*` finds nothing only
+    /// when there's no `x`. The pattern is matched as it was compiled, so one that isn't
+    /// anchored at its start always finds a part starting at `0`. If the pattern ignores
+    /// case or normalizes, the offsets are into the lowercased or normalized text, while a
+    /// trimmed text still has its offsets counted from before it was trimmed.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text to search in.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::WildcardPattern;
+    /// let pattern = WildcardPattern::new("b*d");
+    /// assert_eq!(pattern.find("abcdbd!"), Some((1, 6)));
+    /// assert_eq!(pattern.find("dcb"), None);
+    /// ```
+    pub fn find(&self, text: &str) -> Option<(usize, usize)> {
+        // The offsets are into the text before it was trimmed
+        let trimmed = if self.options.trim {
+            text.len() - text.trim_start().len()
+        } else {
+            0
+        };
+        let text = self.prepare(text);
+        if let Some(byte_tokens) = self
+            .byte_match_tokens
+            .as_ref()
+            .filter(|_| self.by_bytes(&text))
+        {
+            let span = leftmost_longest(byte_tokens, text.as_bytes())?;
+            return Some((trimmed + span.start, trimmed + span.end));
+        }
+        let text_units = self.options.granularity.split(&text).collect::<Vec<&str>>();
+        let span = leftmost_longest(&self.match_tokens, &text_units)?;
+
+        let offset = |units: &[&str]| units.iter().map(|unit| unit.len()).sum::<usize>();
+        let start = trimmed + offset(&text_units[..span.start]);
+        Some((start, start + offset(&text_units[span])))
+    }
+
+    /// Checks if the pattern would match the text after at most `max_edits` edits to it,
+    /// for catching spelling mistakes like `colour` for `color`.
+    ///
+    /// Each edit is one grapheme of the text substituted, left out or added, the same as for
+    /// the Levenshtein distance, and the pattern matches within the distance if it would
+    /// match the edited text. A star matches what it can without any edits, so only what the
+    /// literals, classes and `?` of the pattern need is edited, and a `?` which is missing
+    /// its grapheme costs an edit like a literal would. With no edits this is the same as
+    /// `matches`.
+    ///
+    /// This fills in a table of the pattern against the text like `matches` does when it
+    /// can't match greedily, and gives up as soon as every way of matching so far needs
+    /// more edits than allowed.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text to check the pattern on.
+    /// * `max_edits` - The most graphemes which can be substituted, left out or added.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::WildcardPattern;
+    /// let pattern = WildcardPattern::new("colour.*");
+    /// assert_eq!(pattern.matches_within_distance("color.txt", 1), true);
+    /// assert_eq!(pattern.matches_within_distance("colr.txt", 1), false);
+    /// assert_eq!(pattern.matches_within_distance("colr.txt", 2), true);
+    /// ```
+    pub fn matches_within_distance(&self, text: &str, max_edits: usize) -> bool {
+        let text = self.prepare(text);
+        if let Some(byte_tokens) = self
+            .byte_match_tokens
+            .as_ref()
+            .filter(|_| self.by_bytes(&text))
+        {
+            return fewest_edits(byte_tokens, text.as_bytes(), max_edits).is_some();
+        }
+        let text_units = self.options.granularity.split(&text).collect::<Vec<&str>>();
+        fewest_edits(&self.match_tokens, &text_units, max_edits).is_some()
+    }
+
+    /// Compiles a pattern, unless it has more wildcards than the limit.
+    ///
+    /// This is for patterns which come from someone who can't be trusted, together with
+    /// `MatchOptions::budget`. The wildcards are counted the same way as by
+    /// `num_wildcards`, so a run of stars counts once.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The pattern to compile.
+    /// * `max_wildcards` - The most wildcards the pattern can have.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::{PatternError, WildcardPattern};
+    /// assert!(WildcardPattern::new_limited("*.rs", 3).is_ok());
+    /// assert_eq!(
+    ///     WildcardPattern::new_limited("*/*/*/*.rs", 3).err(),
+    ///     Some(PatternError::TooManyWildcards { count: 4, limit: 3 })
+    /// );
+    /// ```
+    pub fn new_limited(pattern: &str, max_wildcards: usize) -> Result<Self, PatternError> {
+        let compiled = Self::new(pattern);
+        match compiled.num_wildcards() {
+            count if count > max_wildcards => Err(PatternError::TooManyWildcards {
+                count,
+                limit: max_wildcards,
+            }),
+            _ => Ok(compiled),
+        }
+    }
+
+    /// Compiles a pattern, unless it's empty.
+    ///
+    /// An empty pattern is well formed, and only matches an empty text, but a pattern built
+    /// up from pieces is more often empty because of a mistake than on purpose. This rejects
+    /// it rather than quietly matching almost nothing. A pattern that only matches an empty
+    /// text in some other way, like `|`, is still allowed.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The pattern to compile.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::{PatternError, WildcardPattern};
+    /// assert!(WildcardPattern::new_strict("*.rs").is_ok());
+    /// assert_eq!(
+    ///     WildcardPattern::new_strict("").err(),
+    ///     Some(PatternError::EmptyPattern)
+    /// );
+    /// ```
+    pub fn new_strict(pattern: &str) -> Result<Self, PatternError> {
+        if pattern.is_empty() {
+            return Err(PatternError::EmptyPattern);
+        }
+        Ok(Self::new(pattern))
+    }
+
+    /// Compiles a pattern, treating backslashes as ordinary characters rather than escapes.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The pattern to compile.
+    pub fn without_escapes(pattern: &str) -> Self {
+        Self::with_options(pattern, &MatchOptions::new().escapes(false))
+    }
+
+    /// Compiles a pattern which ignores case when matching.
+    ///
+    /// Both the pattern and the text are lowercased with `str::to_lowercase`, which uses the
+    /// unicode lowercase mapping. This is not full case folding, so for example `ß` does not
+    /// match `ss`, although `ẞ` does match `ß`.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The pattern to compile.
+    pub fn case_insensitive(pattern: &str) -> Self {
+        Self::with_options(pattern, &MatchOptions::new().case_insensitive(true))
+    }
+
+    /// Compiles a pattern which converts itself and the text to a unicode normalization form
+    /// before matching, so that for example `é` matches `e\u{301}`.
+    ///
+    /// Normalizing a text that isn't in the form already means copying it, which makes
+    /// matching slower. Texts that are already normalized, which includes all ASCII text,
+    /// are only checked and not copied.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The pattern to compile.
+    /// * `form` - The normalization form to convert to.
+    pub fn normalized(pattern: &str, form: Normalization) -> Self {
+        Self::with_options(pattern, &MatchOptions::new().normalization(form))
+    }
+
+    /// Compiles a pattern for matching paths, where the separator splits the text in to
+    /// segments.
+    ///
+    /// `*` matches within a single segment, and doesn't match the separator, and neither
+    /// do `?` or classes. A `**` which makes up a whole segment, like in `src/**/*.rs`,
+    /// matches any number of segments including none, so that pattern matches both
+    /// `src/main.rs` and `src/a/b/main.rs`. A `**` at the end of the pattern matches
+    /// everything, and anywhere else `**` is the same as `*`.
+    ///
+    /// If the separator is a backslash, backslashes are not treated as escapes.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The pattern to compile.
+    /// * `separator` - The character that separates the segments of a path.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::WildcardPattern;
+    /// let pattern = WildcardPattern::path("src/**/*.rs", '/');
+    /// assert_eq!(pattern.matches("src/a/b.rs"), true);
+    /// assert_eq!(WildcardPattern::path("src/*.rs", '/').matches("src/a/b.rs"), false);
+    /// ```
+    pub fn path(pattern: &str, separator: char) -> Self {
+        Self::with_options(pattern, &MatchOptions::new().separator(separator))
+    }
+
+    /// Checks if the compiled pattern matches a path, which doesn't have to be valid
+    /// unicode.
+    ///
+    /// A path that is valid unicode is matched the same way as by `matches`. Otherwise:
+    ///
+    /// * On Unix, a path is any sequence of bytes. If the pattern is plain ASCII, the path is
+    ///   matched byte by byte like `is_wildcard_match_bytes`, so `?` matches a single byte
+    ///   that isn't valid UTF-8. When ignoring case, only ASCII letters in the path are
+    ///   lowercased. A pattern which isn't plain ASCII is matched like on other platforms.
+    /// * On Windows, and any other platform, the parts of the path which aren't valid
+    ///   unicode, such as unpaired surrogates on Windows, are replaced with `U+FFFD`. Each
+    ///   replacement is a single grapheme for the wildcards, and only matches a literal
+    ///   `U+FFFD` in the pattern.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to check the pattern on.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::path::Path;
+    /// use pyglob::WildcardPattern;
+    /// let pattern = WildcardPattern::path("src/**/*.rs", '/');
+    /// assert_eq!(pattern.matches_path(Path::new("src/a/main.rs")), true);
+    /// ```
+    pub fn matches_path(&self, path: &Path) -> bool {
+        match path.to_str() {
+            Some(text) => self.matches(text),
+            None => self.matches_os_str(path.as_os_str()),
+        }
+    }
+
+    /// Match a path which isn't valid unicode, as bytes if the pattern allows it
+    #[cfg(unix)]
+    fn matches_os_str(&self, text: &OsStr) -> bool {
+        use std::os::unix::ffi::OsStrExt;
+
+        // Forward slashes can only be replaced byte for byte with a separator of one byte
+        let separator = self
+            .options
+            .separator
+            .filter(|_| self.options.forward_slashes);
+        match &self.byte_match_tokens {
+            // Whitespace is only collapsed or trimmed in text, as it's made of characters
+            // rather than bytes, and only ASCII is lowercased, which is enough for an ASCII
+            // pattern
+            Some(byte_tokens)
+                if separator.is_none_or(|separator| separator.is_ascii())
+                    && !self.options.collapse_whitespace
+                    && !self.options.trim
+                    && (!self.ignores_case() || self.source.is_ascii()) =>
+            {
+                let mut bytes = Cow::Borrowed(text.as_bytes());
+                if let Some(separator) = separator {
+                    for byte in bytes.to_mut().iter_mut().filter(|byte| **byte == b'/') {
+                        *byte = separator as u8;
+                    }
+                }
+                if self.ignores_case() {
+                    bytes.to_mut().make_ascii_lowercase();
+                }
+                self.match_units(byte_tokens, &bytes)
+            }
+            _ => self.matches(&text.to_string_lossy()),
+        }
+    }
+
+    /// Match a path which isn't valid unicode, with replacement characters in its place
+    #[cfg(not(unix))]
+    fn matches_os_str(&self, text: &OsStr) -> bool {
+        self.matches(&text.to_string_lossy())
+    }
+
+    /// Lazily filters an iterator, yielding only the items that match the pattern.
+    ///
+    /// Unlike calling `matches` on every item, this keeps one buffer for the graphemes of the
+    /// items and reuses it for each of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `iter` - The items to filter.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::WildcardPattern;
+    /// let pattern = WildcardPattern::new("*.rs");
+    /// let names = ["main.rs", "main.py", "lib.rs"];
+    /// let matches = pattern.filter_iter(names.into_iter()).collect::<Vec<_>>();
+    /// assert_eq!(matches, vec!["main.rs", "lib.rs"]);
+    /// ```
+    pub fn filter_iter<'a, 'p, I>(&'p self, iter: I) -> impl Iterator<Item = &'a str> + 'p
+    where
+        I: Iterator<Item = &'a str> + 'p,
+        'a: 'p,
+    {
+        let mut graphemes: Vec<&'a str> = Vec::new();
+        iter.filter(move |text| {
+            let text: &'a str = text;
+            match self.prepare(text) {
+                // A lowercased or normalized copy only lives as long as the call, so only the
+                // original text can go in the buffer
+                Cow::Borrowed(text) if self.byte_match_tokens.is_none() || !self.by_bytes(text) => {
+                    graphemes.clear();
+                    graphemes.extend(self.options.granularity.split(text));
+                    self.match_units(&self.match_tokens, &graphemes)
+                }
+                text => self.matches_prepared(&text, None) == Ok(true),
+            }
+        })
+    }
+
+    /// The tokens the pattern was parsed in to, with the options it was compiled with.
+    ///
+    /// These are the tokens as they were written, before anything is done to them for
+    /// matching, except that runs of stars are a single star. Ignoring case or normalizing
+    /// is done to the literals already.
+    ///
+    /// # Examples
+    /// ```
+    /// use pyglob::{MatchOptions, Token, WildcardPattern};
+    /// let options = MatchOptions::new().braces(true);
+    /// let pattern = WildcardPattern::with_options("{a,b}", &options);
+    /// assert_eq!(
+    ///     pattern.tokens(),
+    ///     [Token::Alternatives(vec![
+    ///         vec![Token::Literal(String::from("a"))],
+    ///         vec![Token::Literal(String::from("b"))],
+    ///     ])]
+    /// );
+    /// ```
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// Let the pattern match anywhere in a text, by surrounding its tokens with stars.
+    ///
+    /// This is done to the tokens rather than the pattern, so that a trailing backslash
+    /// can't escape the star after it.
+    pub(crate) fn unanchored(self) -> Self {
+        Self::from_tokens(
+            self.source,
+            unanchor(self.tokens, true, true),
+            self.byte_tokens.map(|tokens| unanchor(tokens, true, true)),
+            self.options,
+        )
+    }
+
+    /// Whether the pattern ignores case, by lowercasing or by case folding
+    fn ignores_case(&self) -> bool {
+        self.options.case_insensitive || self.options.case_folding
+    }
+
+    /// Trim, lowercase and normalize a text if the pattern was compiled to do so.
+    fn prepare<'t>(&self, text: &'t str) -> Cow<'t, str> {
+        // Only the text is trimmed, so this isn't done to the pattern by `prepare`
+        let text = if self.options.trim { text.trim() } else { text };
+        prepare(text, &self.options)
+    }
+
+    /// Compiles a pattern to be matched with the given options.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The pattern to compile.
+    /// * `options` - How the pattern should be matched.
+    pub fn with_options(pattern: &str, options: &MatchOptions) -> Self {
+        let prepared = prepare(pattern, options);
+        let syntax = options.syntax();
+        let units = options.granularity.split(&prepared).collect::<Vec<&str>>();
+        let tokens = token::parse_path(&units, &syntax);
+
+        // Duplicate stars don't change what a pattern matches, so drop them once up front,
+        // along with any that an unanchored end adds next to a star of the pattern
+        let (start, end) = (!options.anchor_start, !options.anchor_end);
+        let tokens = unanchor(tokens, start, end);
+        let byte_tokens = (options.granularity == Granularity::Bytes || is_plain_ascii(&prepared))
+            .then(|| unanchor(token::parse_path(prepared.as_bytes(), &syntax), start, end));
+        Self::from_tokens(pattern.to_owned(), tokens, byte_tokens, *options)
+    }
+
+    /// Put together a pattern from its tokens, working out everything that depends on them
+    /// once up front.
+    fn from_tokens(
+        source: String,
+        tokens: Vec<Token>,
+        byte_tokens: Option<Vec<Token<u8>>>,
+        options: MatchOptions,
+    ) -> Self {
+        let match_tokens = normalize_wildcards(tokens.clone());
+        let byte_match_tokens = byte_tokens.clone().map(normalize_wildcards);
+        // A pattern matched by bytes can have several tokens for one grapheme
+        let (length, table_rows) = match &byte_match_tokens {
+            Some(bytes) if options.granularity == Granularity::Bytes => {
+                (fixed_length(bytes), rows(bytes))
+            }
+            _ => (fixed_length(&match_tokens), rows(&match_tokens)),
+        };
+        let greedy = is_greedy_matchable(&match_tokens);
+        WildcardPattern {
+            greedy,
+            from_end: greedy && starts_from_end(&match_tokens),
+            length,
+            stars: stars(&tokens),
+            rows: table_rows,
+            interned: Interned::new(&match_tokens),
+            source,
+            tokens,
+            match_tokens,
+            byte_tokens,
+            byte_match_tokens,
+            options,
+        }
+    }
+}
+
+/// Whether a pattern is quicker to match from its end than from its start, which is when
+/// it has more literals at its end than at its start, like `logs/*/error.log` has. Texts
+/// often have the same start, like a list of paths in the same directory, so they're
+/// rejected sooner by the end which tells them apart. Either way gives the same result.
+fn starts_from_end(tokens: &[Token]) -> bool {
+    let is_literal = |token: &&Token| matches!(token, Token::Literal(_));
+    let at_start = tokens.iter().take_while(is_literal).count();
+    let at_end = tokens.iter().rev().take_while(is_literal).count();
+    at_end > at_start
+}
+
+/// Put a star before and or after the tokens, so that they can match only the start or the
+/// end of a text, and drop any duplicate stars.
+fn unanchor<O>(tokens: Vec<Token<O>>, start: bool, end: bool) -> Vec<Token<O>> {
+    let mut unanchored = Vec::with_capacity(tokens.len() + 2);
+    if start {
+        unanchored.push(Token::AnyRun);
+    }
+    unanchored.extend(tokens);
+    if end {
+        unanchored.push(Token::AnyRun);
+    }
+    remove_duplicate_stars(unanchored)
+}
+
+/// Writes the pattern back out in a canonical form.
+///
+/// A run of stars is written as a single `*`, and every literal `*`, `?`, `[`, `|` and `\\` is
+/// escaped with a backslash, whether or not it was escaped in the original pattern. A
+/// negated class that is empty is written as `?`, which matches the same graphemes. A
+/// pattern with other wildcards, from `MatchOptions::wildcards`, is written with those in
+/// place of `*` and `?`.
+///
+/// Only the pattern is written, and not the options it was compiled with. Parsing the
+/// result with `str::parse` gives a pattern that matches the same texts, as long as the
+/// original was compiled with the default options or only without escapes. A pattern which
+/// ignores case or normalizes is written lowercased or normalized, and a path pattern has to
+/// be compiled again with `WildcardPattern::path` and the same separator. A pattern compiled
+/// without anchoring its start or end is written with a star there instead.
+///
+/// # Examples
+/// ```
+/// use pyglob::WildcardPattern;
+/// let pattern = WildcardPattern::new("a***[!]");
+/// assert_eq!(pattern.to_string(), "a*?");
+/// let parsed = pattern.to_string().parse::<WildcardPattern>().unwrap();
+/// assert_eq!(parsed.matches("abc"), true);
+/// ```
+impl fmt::Display for WildcardPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        token::write_pattern(f, &self.tokens, &self.options.syntax())
+    }
+}
+
+/// Two patterns are equal if they were compiled to the same tokens with the same options.
+///
+/// This compares the compiled form rather than the text of the patterns, so `a**` equals
+/// `a*`, `\\x` equals `x`, and `*?` equals `?*`, since each has the same canonical string.
+/// Patterns with different options are never equal, even if they'd match the same texts.
+/// Patterns which match the same texts but are written differently can still be unequal,
+/// for example `[ab]` and `[ba]`.
+///
+/// # Examples
+/// ```
+/// use std::collections::HashSet;
+/// use pyglob::WildcardPattern;
+/// let rules = ["*.rs", "**.rs", "*.py"].into_iter().map(WildcardPattern::new);
+/// assert_eq!(rules.collect::<HashSet<_>>().len(), 2);
+/// ```
+impl PartialEq for WildcardPattern {
+    fn eq(&self, other: &Self) -> bool {
+        // The other fields are worked out from these two, and the runs of wildcards in
+        // these are in the same order whichever order they were written in
+        self.match_tokens == other.match_tokens && self.options == other.options
+    }
+}
+
+impl Eq for WildcardPattern {}
+
+impl Hash for WildcardPattern {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.match_tokens.hash(state);
+        self.options.hash(state);
+    }
+}
+
+/// Compiles a pattern with the default options, like `WildcardPattern::new`.
+///
+/// Unlike `new`, a pattern with an unterminated class or a dangling escape is rejected with
+/// the error `validate` would give, rather than matched literally.
+///
+/// # Examples
+/// ```
+/// use pyglob::{PatternError, WildcardPattern};
+/// assert_eq!("*.rs".parse::<WildcardPattern>().unwrap().matches("main.rs"), true);
+/// assert_eq!(
+///     "[a-z".parse::<WildcardPattern>().err(),
+///     Some(PatternError::UnterminatedClass { pos: 0 })
+/// );
+/// ```
+impl FromStr for WildcardPattern {
+    type Err = PatternError;
+
+    fn from_str(pattern: &str) -> Result<Self, PatternError> {
+        validate(pattern)?;
+        Ok(WildcardPattern::new(pattern))
+    }
+}
+
+/// Matching takes at most one step for every pair of a token and a grapheme
+fn check_budget(
+    pattern_len: usize,
+    text_len: usize,
+    budget: Option<usize>,
+) -> Result<(), PatternError> {
+    let cost = pattern_len.saturating_mul(text_len);
+    match budget {
+        Some(budget) if cost > budget => Err(PatternError::OverBudget { cost, budget }),
+        _ => Ok(()),
+    }
+}
+
+/// Lowercase a pattern or text before normalizing it, since lowercasing doesn't keep a
+/// string normalized. Whitespace is collapsed last.
+fn prepare<'t>(text: &'t str, options: &MatchOptions) -> Cow<'t, str> {
+    let text = match options.separator {
+        Some(separator) if options.forward_slashes && text.contains('/') => {
+            Cow::Owned(text.replace('/', separator.encode_utf8(&mut [0; 4])))
+        }
+        _ => Cow::Borrowed(text),
+    };
+    let text = if options.case_folding {
+        casefold(text)
+    } else if options.case_insensitive {
+        Cow::Owned(text.to_lowercase())
+    } else {
+        text
+    };
+    let text = match options.normalization {
+        Some(form) => form.apply(text),
+        None => text,
+    };
+    if options.collapse_whitespace {
+        collapse_whitespace(text)
+    } else {
+        text
+    }
+}
+
+/// Replace each run of whitespace with a single space, copying the text only if there's a
+/// run which isn't already a single space.
+fn collapse_whitespace(text: Cow<'_, str>) -> Cow<'_, str> {
+    let mut chars = text.chars().peekable();
+    let mut collapsed = true;
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() && (c != ' ' || chars.peek().is_some_and(|c| c.is_whitespace())) {
+            collapsed = false;
+            break;
+        }
+    }
+    if collapsed {
+        return text;
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut in_run = false;
+    for c in text.chars() {
+        if !c.is_whitespace() {
+            result.push(c);
+        } else if !in_run {
+            result.push(' ');
+        }
+        in_run = c.is_whitespace();
+    }
+    Cow::Owned(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compiled_pattern_matches() {
+        let pattern = WildcardPattern::new("a?y*b");
+        assert_eq!(true, pattern.matches("alyib"));
+        assert_eq!(false, pattern.matches("alyic"));
+    }
+
+    #[test]
+    fn compiled_pattern_can_be_reused() {
+        let pattern = WildcardPattern::new("*.txt");
+        let names = ["a.txt", "b.rs", "c.txt", ""];
+        let matches = names
+            .iter()
+            .filter(|name| pattern.matches(name))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![&"a.txt", &"c.txt"], matches);
+    }
+
+    #[test]
+    fn filter_iter_yields_matches_lazily() {
+        let pattern = WildcardPattern::new("*.txt");
+        let names = ["a.txt", "b.rs", "c.txt", ""];
+        let mut matches = pattern.filter_iter(names.into_iter());
+        assert_eq!(Some("a.txt"), matches.next());
+        assert_eq!(Some("c.txt"), matches.next());
+        assert_eq!(None, matches.next());
+    }
+
+    #[test]
+    fn filter_iter_reuses_buffer_for_different_lengths() {
+        let pattern = WildcardPattern::new("?*b");
+        let names = ["aaaaaaab", "ab", "b", "aab", "a\u{301}b"];
+        let matches = pattern.filter_iter(names.into_iter()).collect::<Vec<_>>();
+        assert_eq!(vec!["aaaaaaab", "ab", "aab", "a\u{301}b"], matches);
+    }
+
+    #[test]
+    fn filter_iter_ignores_case() {
+        let pattern = WildcardPattern::case_insensitive("*.TXT");
+        let names = ["a.txt", "B.TXT", "c.rs"];
+        let matches = pattern.filter_iter(names.into_iter()).collect::<Vec<_>>();
+        assert_eq!(vec!["a.txt", "B.TXT"], matches);
+    }
+
+    #[test]
+    fn duplicate_stars_are_collapsed() {
+        let pattern = WildcardPattern::new("a***b");
+        assert_eq!(
+            vec![
+                Token::Literal(String::from("a")),
+                Token::AnyRun,
+                Token::Literal(String::from("b"))
+            ],
+            pattern.tokens
+        );
+        assert_eq!(true, pattern.matches("ab"));
+    }
+
+    #[test]
+    fn case_insensitive_pattern_is_lowercased_once() {
+        let pattern = WildcardPattern::case_insensitive("A*");
+        assert_eq!(
+            vec![Token::Literal(String::from("a")), Token::AnyRun],
+            pattern.tokens
+        );
+        assert_eq!(true, pattern.matches("ABC"));
+        assert_eq!(true, pattern.matches("abc"));
+    }
+
+    #[test]
+    fn patterns_without_stars_have_a_fixed_length() {
+        assert_eq!(Some(3), WildcardPattern::new("a?b").length);
+        assert_eq!(Some(2), WildcardPattern::new("[ab]\\*").length);
+        assert_eq!(Some(0), WildcardPattern::new("").length);
+        assert_eq!(None, WildcardPattern::new("a*b").length);
+        let options = MatchOptions::new().braces(true);
+        assert_eq!(
+            None,
+            WildcardPattern::with_options("{a,bc}", &options).length
+        );
+        let options = MatchOptions::new().optional_question_marks(true);
+        assert_eq!(None, WildcardPattern::with_options("ab?", &options).length);
+    }
+
+    #[test]
+    fn texts_of_the_wrong_length_are_rejected() {
+        assert_eq!(false, crate::is_wildcard_match("abcd", "a?b"));
+        assert_eq!(false, crate::is_wildcard_match("ab", "a?b"));
+        assert_eq!(true, crate::is_wildcard_match("axb", "a?b"));
+        assert_eq!(true, crate::is_wildcard_match("a\u{e9}b", "a?b"));
+        assert_eq!(true, crate::is_wildcard_match("ae\u{301}b", "a?b"));
+
+        // Matching this in full would fill in a billion cells
+        let pattern = WildcardPattern::new(&"[a]".repeat(1_000));
+        let text = "a".repeat(1_000_000);
+        assert_eq!(false, pattern.matches(&text));
+        assert_eq!(
+            false,
+            pattern
+                .filter_iter([text.as_str(), "aa"].into_iter())
+                .any(|_| true)
+        );
+    }
+
+    #[test]
+    fn compiled_patterns_are_shared_between_threads() {
+        let pattern = WildcardPattern::new("*[0-9].rs");
+        let texts = ["a1.rs", "b.rs", "c2.rs", "d3.py"];
+        let matches = std::thread::scope(|scope| {
+            let handles = texts
+                .iter()
+                .map(|text| scope.spawn(|| pattern.matches(text)))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<bool>>()
+        });
+        assert_eq!(vec![true, false, true, false], matches);
+    }
+
+    #[test]
+    fn simple_patterns_use_greedy_matcher() {
+        assert_eq!(true, WildcardPattern::new("a*b?").greedy);
+        assert_eq!(true, WildcardPattern::new("a\\[b").greedy);
+        assert_eq!(false, WildcardPattern::new("a*[bc]").greedy);
+    }
+
+    #[test]
+    fn ascii_patterns_are_also_compiled_to_bytes() {
+        assert_eq!(true, WildcardPattern::new("*.[ch]").byte_tokens.is_some());
+        assert_eq!(
+            true,
+            WildcardPattern::new("*.rs\u{e9}").byte_tokens.is_none()
+        );
+        // A carriage return and line feed together are a single grapheme
+        assert_eq!(true, WildcardPattern::new("a\r\n").byte_tokens.is_none());
+    }
+
+    #[test]
+    fn ascii_pattern_on_unicode_text() {
+        let pattern = WildcardPattern::new("?.txt");
+        assert_eq!(true, pattern.matches("a.txt"));
+        assert_eq!(true, pattern.matches("e\u{301}.txt"));
+        assert_eq!(true, pattern.matches("\r\n.txt"));
+    }
+
+    #[test]
+    fn same_text_under_each_granularity() {
+        // One grapheme, five scalars and eighteen bytes
+        let family = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+        let compile = |pattern: &str, granularity| {
+            let options = MatchOptions::new().granularity(granularity);
+            WildcardPattern::with_options(pattern, &options)
+        };
+        let bytes = "?".repeat(18);
+        for (granularity, matching) in [
+            (Granularity::Graphemes, "?"),
+            (Granularity::Scalars, "?????"),
+            (Granularity::Bytes, bytes.as_str()),
+        ] {
+            for pattern in ["?", "?????", bytes.as_str()] {
+                let compiled = compile(pattern, granularity);
+                assert_eq!(
+                    pattern == matching,
+                    compiled.matches(family),
+                    "{:?}",
+                    granularity
+                );
+                assert_eq!(pattern.len(), compiled.min_len());
+            }
+        }
+
+        // The other two split the grapheme up
+        assert_eq!(
+            false,
+            compile("\u{1f468}*", Granularity::Graphemes).matches(family)
+        );
+        for granularity in [Granularity::Scalars, Granularity::Bytes] {
+            let pattern = compile("\u{1f468}*\u{1f467}", granularity);
+            assert_eq!(true, pattern.matches(family));
+            assert_eq!(
+                Some(vec![String::from("\u{200d}\u{1f469}\u{200d}")]),
+                pattern.captures(family)
+            );
+            assert_eq!(Some((0, 18)), pattern.find(family));
+        }
+        assert_eq!(
+            Some((4, 7)),
+            compile("\u{200d}", Granularity::Scalars).find(family)
+        );
+    }
+
+    #[test]
+    fn granularity_applies_to_classes_and_prefixes() {
+        let scalars = MatchOptions::new().granularity(Granularity::Scalars);
+        let pattern = WildcardPattern::with_options("[e]\u{301}", &scalars);
+        assert_eq!(true, pattern.matches("e\u{301}"));
+        assert_eq!(true, pattern.matches_prefix_of("e"));
+        assert_eq!(
+            false,
+            WildcardPattern::new("[e]\u{301}").matches("e\u{301}")
+        );
+
+        let bytes = MatchOptions::new().granularity(Granularity::Bytes);
+        let pattern = WildcardPattern::with_options("caf??", &bytes);
+        assert_eq!(true, pattern.matches("caf\u{e9}"));
+        assert_eq!(false, pattern.matches("cafe"));
+        assert_eq!("caf??", pattern.to_string());
+        // A capture that splits a character has a replacement character in its place
+        let pattern = WildcardPattern::with_options("caf*?", &bytes);
+        assert_eq!(
+            Some(vec![String::from("\u{fffd}")]),
+            pattern.captures("caf\u{e9}")
+        );
+        assert_eq!(
+            "\u{e9}*",
+            WildcardPattern::with_options("\u{e9}*", &bytes).to_string()
+        );
+    }
+
+    #[test]
+    fn normalized_pattern_matches_other_form() {
+        for form in [Normalization::Nfc, Normalization::Nfd] {
+            let pattern = WildcardPattern::normalized("caf\u{e9}.*", form);
+            assert_eq!(true, pattern.matches("cafe\u{301}.txt"));
+            assert_eq!(true, pattern.matches("caf\u{e9}.txt"));
+            assert_eq!(false, pattern.matches("cafe.txt"));
+        }
+    }
+
+    #[test]
+    fn pattern_is_not_normalized_by_default() {
+        let pattern = WildcardPattern::new("caf\u{e9}");
+        assert_eq!(false, pattern.matches("cafe\u{301}"));
+    }
+
+    #[test]
+    fn normalized_filter_iter() {
+        let pattern = WildcardPattern::normalized("*e\u{301}*", Normalization::Nfc);
+        let names = ["caf\u{e9}", "cafe\u{301}", "cafe", "ascii"];
+        let matches = pattern.filter_iter(names.into_iter()).collect::<Vec<_>>();
+        assert_eq!(vec!["caf\u{e9}", "cafe\u{301}"], matches);
+    }
+
+    #[test]
+    fn escaped_stars_are_not_collapsed() {
+        let pattern = WildcardPattern::new("\\**");
+        assert_eq!(
+            vec![Token::Literal(String::from("*")), Token::AnyRun],
+            pattern.tokens
+        );
+    }
+
+    #[test]
+    fn runs_of_escaped_and_real_stars() {
+        let star = || Token::Literal(String::from("*"));
+        let pattern = WildcardPattern::new("\\*\\***\\*");
+        assert_eq!(vec![star(), star(), Token::AnyRun, star()], pattern.tokens);
+        assert_eq!("\\*\\**\\*", pattern.to_string());
+        assert_eq!(true, WildcardPattern::new("\\*\\**\\*") == pattern);
+        assert_eq!(false, WildcardPattern::new("\\**") == pattern);
+
+        let braces = MatchOptions::new().braces(true);
+        let pattern = WildcardPattern::with_options("{\\***,**}", &braces);
+        assert_eq!(
+            vec![Token::Alternatives(vec![
+                vec![star(), Token::AnyRun],
+                vec![Token::AnyRun]
+            ])],
+            pattern.tokens
+        );
+        assert_eq!(
+            vec!["\\**", "*"],
+            crate::dedup_patterns(&["\\**", "\\***", "**", "\\**"])
+        );
+    }
+
+    #[test]
+    fn display_collapses_stars() {
+        assert_eq!("a*b", WildcardPattern::new("a***b").to_string());
+        assert_eq!("", WildcardPattern::new("").to_string());
+    }
+
+    #[test]
+    fn display_escapes_literals() {
+        assert_eq!("\\**", WildcardPattern::new("\\**").to_string());
+        assert_eq!("\\[a\\\\", WildcardPattern::new("[a\\").to_string());
+        assert_eq!(
+            "dir\\\\*",
+            WildcardPattern::without_escapes("dir\\*").to_string()
+        );
+    }
+
+    #[test]
+    fn display_classes() {
+        assert_eq!("[a-z0-9]", WildcardPattern::new("[a-z0-9]").to_string());
+        assert_eq!("[!\\-a]", WildcardPattern::new("[^-a]").to_string());
+        assert_eq!("[\\]\\!]", WildcardPattern::new("[\\]!]").to_string());
+        assert_eq!("[]", WildcardPattern::new("[]").to_string());
+        assert_eq!("?", WildcardPattern::new("[!]").to_string());
+        assert_eq!(
+            "[![:digit:]a]",
+            WildcardPattern::new("[^[:digit:]a]").to_string()
+        );
+    }
+
+    #[test]
+    fn display_path_pattern() {
+        for (pattern, expected) in [
+            ("src/**/*.rs", "src/**/*.rs"),
+            ("a**/?/**", "a*/?/**"),
+            ("[!a][/b]", "[!a][b]"),
+        ] {
+            let compiled = WildcardPattern::path(pattern, '/');
+            assert_eq!(expected, compiled.to_string());
+            assert_eq!(compiled.tokens, WildcardPattern::path(expected, '/').tokens);
+        }
+    }
+
+    #[test]
+    fn display_braces() {
+        let options = MatchOptions::new().braces(true);
+        for (pattern, expected) in [
+            ("a{b,c**}", "a{b,c*}"),
+            ("{,{x,y}}", "{,{x,y}}"),
+            ("a{b\\,c}", "a{b\\,c}"),
+            ("}{", "\\}\\{"),
+        ] {
+            let compiled = WildcardPattern::with_options(pattern, &options);
+            assert_eq!(expected, compiled.to_string());
+            assert_eq!(
+                compiled.tokens,
+                WildcardPattern::with_options(expected, &options).tokens
+            );
+        }
+        // Without braces, they don't need escaping
+        assert_eq!("a{b,c}", WildcardPattern::new("a{b,c}").to_string());
+    }
+
+    #[test]
+    fn display_alternatives() {
+        for (pattern, expected) in [
+            ("foo|bar**", "foo|bar*"),
+            ("|[|]\\||", "|[|]\\||"),
+            ("a\\|b", "a\\|b"),
+        ] {
+            let compiled = WildcardPattern::new(pattern);
+            assert_eq!(expected, compiled.to_string());
+            assert_eq!(compiled.tokens, WildcardPattern::new(expected).tokens);
+        }
+        // With braces, the same group is written with them instead
+        let options = MatchOptions::new().braces(true);
+        assert_eq!(
+            "{a,b*}",
+            WildcardPattern::with_options("a|b*", &options).to_string()
+        );
+    }
+
+    #[test]
+    fn display_optional_question_marks() {
+        let options = MatchOptions::new().optional_question_marks(true);
+        for (pattern, expected) in [
+            ("colou?r", "colou?r"),
+            ("?a*?", "\\?a*"),
+            ("[!]a?", "[!]a?"),
+        ] {
+            let compiled = WildcardPattern::with_options(pattern, &options);
+            assert_eq!(expected, compiled.to_string());
+            assert_eq!(
+                compiled.tokens,
+                WildcardPattern::with_options(expected, &options).tokens
+            );
+        }
+    }
+
+    #[test]
+    fn prefix_stops_at_the_first_wildcard() {
+        assert_eq!("src/foo", WildcardPattern::new("src/foo*").prefix());
+        assert_eq!("", WildcardPattern::new("*x").prefix());
+        assert_eq!("ab", WildcardPattern::new("ab?c").prefix());
+        assert_eq!("a", WildcardPattern::new("a[bc]").prefix());
+        assert_eq!("abc", WildcardPattern::new("abc").prefix());
+        assert_eq!("", WildcardPattern::new("").prefix());
+    }
+
+    #[test]
+    fn prefix_respects_escapes() {
+        assert_eq!("a*b", WildcardPattern::new("a\\*b*").prefix());
+        assert_eq!("a[", WildcardPattern::new("a[").prefix());
+        assert_eq!("a\\", WildcardPattern::without_escapes("a\\*").prefix());
+    }
+
+    #[test]
+    fn prefix_with_options() {
+        assert_eq!("src/", WildcardPattern::path("src/**/*.rs", '/').prefix());
+        assert_eq!("src/", WildcardPattern::case_insensitive("SRC/*").prefix());
+        let options = MatchOptions::new().braces(true);
+        assert_eq!(
+            "a",
+            WildcardPattern::with_options("a{b,c}", &options).prefix()
+        );
+    }
+
+    #[test]
+    fn suffix_starts_after_the_last_wildcard() {
+        assert_eq!(".rs", WildcardPattern::new("*.rs").suffix());
+        assert_eq!("foo", WildcardPattern::new("foo").suffix());
+        assert_eq!("", WildcardPattern::new("?").suffix());
+        assert_eq!("", WildcardPattern::new("a*").suffix());
+        assert_eq!("c", WildcardPattern::new("a[b]c").suffix());
+        assert_eq!("", WildcardPattern::new("").suffix());
+    }
+
+    #[test]
+    fn suffix_respects_escapes() {
+        assert_eq!("*", WildcardPattern::new("*\\*").suffix());
+        assert_eq!("?.txt", WildcardPattern::new("*\\?.txt").suffix());
+        assert_eq!("\\", WildcardPattern::new("*\\").suffix());
+        // A grapheme with a combining accent stays whole
+        assert_eq!("e\u{301}", WildcardPattern::new("*e\u{301}").suffix());
+    }
+
+    #[test]
+    fn literal_segments_are_between_wildcards() {
+        let segments = |pattern| WildcardPattern::new(pattern).literal_segments();
+        assert_eq!(vec!["src/", "/main_", ".rs"], segments("src/*/main_?.rs"));
+        assert_eq!(vec!["a", "b", "c"], segments("*a*b**c*"));
+        assert_eq!(vec!["ab"], segments("ab"));
+        assert_eq!(vec!["x", "y"], segments("x[ab]y"));
+        assert_eq!(Vec::<String>::new(), segments("*?*"));
+        assert_eq!(Vec::<String>::new(), segments(""));
+    }
+
+    #[test]
+    fn literal_segments_respect_escapes() {
+        let segments = |pattern| WildcardPattern::new(pattern).literal_segments();
+        assert_eq!(vec!["a*b", "?"], segments("a\\*b*\\?"));
+        assert_eq!(vec!["[x]"], segments("*\\[x]*"));
+    }
+
+    #[test]
+    fn canonical_strings_tell_patterns_apart() {
+        let canonical = |pattern| WildcardPattern::new(pattern).canonical_string();
+        assert_eq!("a*b", canonical("a***b"));
+        assert_eq!("?*", canonical("**?"));
+        assert_eq!("?*", canonical("?**"));
+        assert_eq!("\\[x]", canonical("\\[x]"));
+        assert_eq!("?", canonical("[!]"));
+        let patterns = [
+            "a***b", "a*b", "\\a**\\b", "a*?b", "a?*b", "A*b", "[!]", "?", "[!a]", "*?*",
+        ];
+        for first in patterns {
+            for second in patterns {
+                assert_eq!(
+                    canonical(first) == canonical(second),
+                    WildcardPattern::new(first) == WildcardPattern::new(second),
+                    "{:?} and {:?}",
+                    first,
+                    second
+                );
+            }
+        }
+        // The options are part of the pattern, but not of its canonical string
+        let insensitive = WildcardPattern::case_insensitive("A*B");
+        assert_eq!("a*b", insensitive.canonical_string());
+        assert_eq!(false, insensitive == WildcardPattern::new("a*b"));
+    }
+
+    #[test]
+    fn explain_lists_the_tokens() {
+        let explain = |pattern, options: &MatchOptions| {
+            WildcardPattern::with_options(pattern, options).explain()
+        };
+        assert_eq!(
+            "literal \"a\"\nany run of graphemes\nliteral \"e\\u{301}\"\n\
+             one grapheme in \"x\", \"0\" to \"9\", [:alpha:]\n",
+            explain("a*e\u{301}[x0-9[:alpha:]]", &MatchOptions::new())
+        );
+        assert_eq!(
+            "any run of segments, each ending in '/'\nany run of graphemes except '/'\n\
+             one grapheme not in [:punct:], \"/\" to \"/\"\n",
+            explain("**/*[![:punct:]]", &MatchOptions::new().separator('/'))
+        );
+        assert_eq!(
+            "one of 3 alternatives\n  1:\n    literal \"a\"\n  2:\n    nothing\n  3:\n    \
+             optionally literal \"b\"\n",
+            explain("a||b?", &MatchOptions::new().optional_question_marks(true))
+        );
+        assert_eq!(
+            "any one scalar\none scalar in nothing\n",
+            explain(
+                "?[]",
+                &MatchOptions::new().granularity(Granularity::Scalars)
+            )
+        );
+        assert_eq!("nothing\n", explain("", &MatchOptions::new()));
+    }
+
+    #[test]
+    fn specificity_prefers_literals_and_fewer_stars() {
+        let mut rules = ["*", "*.rs", "src/*.rs", "main.rs", "?ain.rs", "*main.rs"]
+            .map(WildcardPattern::new)
+            .to_vec();
+        rules.sort_by_key(|rule| std::cmp::Reverse(rule.specificity()));
+        let rules = rules
+            .iter()
+            .map(|rule| rule.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec!["main.rs", "src/*.rs", "*main.rs", "?ain.rs", "*.rs", "*"],
+            rules
+        );
+    }
+
+    #[test]
+    fn specificity_of_braces_and_classes() {
+        let options = MatchOptions::new().braces(true);
+        let specificity = |pattern| WildcardPattern::with_options(pattern, &options).specificity();
+        assert_eq!(specificity("a{bc,*}"), 2);
+        assert_eq!(specificity("a{bc,d?}"), 7);
+        assert_eq!(specificity("[ab]"), 2);
+        assert_eq!(specificity("**"), 0);
+        assert_eq!(specificity("*?*"), 0);
+        assert_eq!(specificity("\\*"), 3);
+    }
+
+    #[test]
+    fn lengths_of_simple_patterns() {
+        let lengths = |pattern| {
+            let pattern = WildcardPattern::new(pattern);
+            (pattern.min_len(), pattern.max_len())
+        };
+        assert_eq!((3, Some(3)), lengths("a?c"));
+        assert_eq!((2, None), lengths("a*c"));
+        assert_eq!((0, Some(0)), lengths(""));
+        assert_eq!((0, None), lengths("***"));
+        assert_eq!((2, Some(2)), lengths("[ab]\\*"));
+        // Lengths count graphemes
+        assert_eq!((1, Some(1)), lengths("e\u{301}"));
+        assert_eq!((1, Some(3)), lengths("a|bcd|??|x"));
+    }
+
+    #[test]
+    fn lengths_of_groups_and_optional_graphemes() {
+        let options = MatchOptions::new()
+            .braces(true)
+            .optional_question_marks(true);
+        let lengths = |pattern| {
+            let pattern = WildcardPattern::with_options(pattern, &options);
+            (pattern.min_len(), pattern.max_len())
+        };
+        assert_eq!((3, Some(6)), lengths("a{b,cd,}x{yz,w}"));
+        assert_eq!((1, None), lengths("{a,b*}"));
+        assert_eq!((5, Some(6)), lengths("colou?r"));
+        assert_eq!((0, Some(2)), lengths("{ab}?"));
+        let path = WildcardPattern::path("src/**/*.rs", '/');
+        assert_eq!((7, None), (path.min_len(), path.max_len()));
+    }
+
+    #[test]
+    fn lengths_agree_with_matching() {
+        let pattern = WildcardPattern::new("a?[bc]*");
+        for text in ["", "a", "ab", "abc", "axbyyyy"] {
+            let length = text.len();
+            if pattern.matches(text) {
+                assert!(pattern.min_len() <= length);
+                assert!(pattern.max_len().is_none_or(|max| length <= max));
+            }
+        }
+    }
+
+    #[test]
+    fn wildcards_are_counted() {
+        let count = |pattern| WildcardPattern::new(pattern).num_wildcards();
+        assert_eq!(0, count("main.rs"));
+        assert_eq!(0, count("\\*\\?\\[a]"));
+        assert_eq!(3, count("*?[ab]"));
+        assert_eq!(2, count("a***b*"));
+        assert_eq!(4, count("*.rs|?[ab]*"));
+        let options = MatchOptions::new()
+            .braces(true)
+            .optional_question_marks(true);
+        let count = |pattern| WildcardPattern::with_options(pattern, &options).num_wildcards();
+        // After a group, the `?` only adds an empty alternative
+        assert_eq!(2, count("{*.rs,[ab]}?"));
+        assert_eq!(3, count("colou?r[0-9]?"));
+    }
+
+    #[test]
+    fn new_limited_rejects_too_many_wildcards() {
+        assert_eq!(
+            Err(PatternError::TooManyWildcards { count: 5, limit: 3 }),
+            WildcardPattern::new_limited("a*b*c*d*e*", 3).map(|_| ())
+        );
+        let pattern = WildcardPattern::new_limited("a*b*c*", 3).unwrap();
+        assert_eq!(true, pattern.matches("abc"));
+        assert_eq!(true, WildcardPattern::new_limited("abc", 0).is_ok());
+        // Duplicate stars are dropped before counting
+        assert_eq!(true, WildcardPattern::new_limited("a*****", 1).is_ok());
+    }
+
+    #[test]
+    fn empty_patterns_are_only_rejected_when_strict() {
+        // By default an empty pattern matches an empty text, and nothing else
+        let pattern = WildcardPattern::new("");
+        assert_eq!(true, pattern.matches(""));
+        assert_eq!(false, pattern.matches("test"));
+        assert_eq!(true, crate::is_wildcard_match("", ""));
+        assert_eq!(false, crate::is_wildcard_match("test", ""));
+
+        assert_eq!(
+            Err(PatternError::EmptyPattern),
+            WildcardPattern::new_strict("").map(|_| ())
+        );
+        let pattern = WildcardPattern::new_strict("*").unwrap();
+        assert_eq!(true, pattern.matches(""));
+        assert_eq!(true, pattern.matches("test"));
+        // Only a pattern without anything in it is empty
+        assert_eq!(true, WildcardPattern::new_strict("|").unwrap().matches(""));
+        assert_eq!(true, WildcardPattern::new_strict(" ").is_ok());
+    }
+
+    #[test]
+    fn literal_patterns() {
+        for pattern in [
+            "main.rs",
+            "",
+            "a]b",
+            "\\*",
+            "a\\?b\\[c",
+            "[",
+            "a\\",
+            "{a,b}",
+        ] {
+            let compiled = WildcardPattern::new(pattern);
+            assert_eq!(true, compiled.is_literal(), "{}", pattern);
+            assert_eq!(false, compiled.has_wildcards(), "{}", pattern);
+            assert_eq!(compiled.prefix(), crate::unescape(pattern));
+        }
+    }
+
+    #[test]
+    fn patterns_with_wildcards() {
+        for pattern in ["*", "*.rs", "a?", "[abc]", "[!a]", "\\**", "a\\[b]?"] {
+            let compiled = WildcardPattern::new(pattern);
+            assert_eq!(false, compiled.is_literal(), "{}", pattern);
+            assert_eq!(true, compiled.has_wildcards(), "{}", pattern);
+        }
+        let options = MatchOptions::new().braces(true);
+        assert_eq!(
+            true,
+            WildcardPattern::with_options("{a,b}", &options).has_wildcards()
+        );
+        let options = MatchOptions::new().literal(true);
+        assert_eq!(
+            true,
+            WildcardPattern::with_options("*?[a]", &options).is_literal()
+        );
+    }
+
+    #[test]
+    fn stars_are_counted() {
+        assert_eq!(0, WildcardPattern::new("abc").num_stars());
+        assert_eq!(0, WildcardPattern::new("a?[*]\\*").num_stars());
+        assert_eq!(1, WildcardPattern::new("*.rs").num_stars());
+        assert_eq!(2, WildcardPattern::new("*a***b").num_stars());
+        assert_eq!(2, WildcardPattern::path("src/**/*.rs", '/').num_stars());
+        let options = MatchOptions::new().braces(true);
+        assert_eq!(
+            3,
+            WildcardPattern::with_options("*{a*,b*,c}", &options).num_stars()
+        );
+        assert_eq!(2, WildcardPattern::new("a").unanchored().num_stars());
+    }
+
+    #[test]
+    fn complexity_hint_counts_tokens() {
+        assert_eq!(0, WildcardPattern::new("").complexity_hint());
+        assert_eq!(3, WildcardPattern::new("a*b").complexity_hint());
+        assert_eq!(3, WildcardPattern::new("a***b").complexity_hint());
+        let options = MatchOptions::new().braces(true);
+        assert_eq!(
+            5,
+            WildcardPattern::with_options("x{ab,c*}", &options).complexity_hint()
+        );
+        // It's the same count that the budget is checked against
+        let pattern = WildcardPattern::with_options("a*b", &MatchOptions::new().budget(Some(11)));
+        assert_eq!(
+            Err(PatternError::OverBudget {
+                cost: pattern.complexity_hint() * 4,
+                budget: 11
+            }),
+            pattern.try_matches("abcd")
+        );
+    }
+
+    #[test]
+    fn unicode_paths_match_like_strings() {
+        let pattern = WildcardPattern::path("src/**/*.rs", '/');
+        assert_eq!(true, pattern.matches_path(Path::new("src/a/main.rs")));
+        assert_eq!(false, pattern.matches_path(Path::new("src/a/main.py")));
+        let pattern = WildcardPattern::new("caf?.txt");
+        assert_eq!(true, pattern.matches_path(Path::new("cafe\u{301}.txt")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_unicode_paths_match_as_bytes() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"dir/file\xff.TXT"));
+        assert_eq!(
+            true,
+            WildcardPattern::new("dir/file?.TXT").matches_path(path)
+        );
+        assert_eq!(
+            false,
+            WildcardPattern::new("dir/file??.TXT").matches_path(path)
+        );
+        assert_eq!(true, WildcardPattern::new("*[!a].TXT").matches_path(path));
+        assert_eq!(
+            true,
+            WildcardPattern::case_insensitive("DIR/*.txt").matches_path(path)
+        );
+        assert_eq!(
+            false,
+            WildcardPattern::path("*.TXT", '/').matches_path(path)
+        );
+        // A pattern that isn't ASCII sees the invalid byte as a replacement character
+        assert_eq!(
+            true,
+            WildcardPattern::new("dir/file\u{fffd}.TXT").matches_path(path)
+        );
+        assert_eq!(false, WildcardPattern::new("dir/é*").matches_path(path));
+    }
+
+    fn hash_of(pattern: &WildcardPattern) -> u64 {
+        use std::collections::hash_map::DefaultHasher;
+        let mut hasher = DefaultHasher::new();
+        pattern.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn equal_patterns_hash_equally() {
+        for (a, b) in [("**", "*"), ("a***b", "a*b"), ("\\x", "x"), ("[a", "\\[a")] {
+            let (a, b) = (WildcardPattern::new(a), WildcardPattern::new(b));
+            assert_eq!(true, a == b);
+            assert_eq!(hash_of(&a), hash_of(&b));
+        }
+    }
+
+    #[test]
+    fn different_patterns_are_unequal() {
+        assert_eq!(
+            false,
+            WildcardPattern::new("*") == WildcardPattern::new("?")
+        );
+        assert_eq!(
+            false,
+            WildcardPattern::new("\\*") == WildcardPattern::new("*")
+        );
+        // The same tokens with different options
+        assert_eq!(
+            false,
+            WildcardPattern::new("a") == WildcardPattern::case_insensitive("a")
+        );
+    }
+
+    #[test]
+    fn cloned_pattern_is_equal() {
+        let pattern = WildcardPattern::path("src/**/*.rs", '/');
+        let clone = pattern.clone();
+        assert_eq!(true, pattern == clone);
+        assert_eq!(true, clone.matches("src/a/b.rs"));
+    }
+
+    #[test]
+    fn patterns_dedup_in_a_set() {
+        use std::collections::HashSet;
+        let rules = ["*.rs", "**.rs", "*.py", "*.r\\s", "*.py"]
+            .into_iter()
+            .map(WildcardPattern::new)
+            .collect::<HashSet<_>>();
+        assert_eq!(2, rules.len());
+    }
+
+    #[test]
+    fn parse_round_trips() {
+        for pattern in ["a***b", "\\*[!-a-c]?", "[\\]\\\\^]", "x[!a]", "e\u{301}\\"] {
+            let compiled = WildcardPattern::new(pattern);
+            let parsed = compiled.to_string().parse::<WildcardPattern>().unwrap();
+            assert_eq!(compiled.tokens, parsed.tokens, "{:?}", pattern);
+        }
+    }
+
+    #[test]
+    fn parse_reports_errors() {
+        assert_eq!(
+            Some(PatternError::DanglingEscape { pos: 1 }),
+            "a\\".parse::<WildcardPattern>().err()
+        );
+        assert_eq!(
+            Some(PatternError::UnterminatedClass { pos: 1 }),
+            "*[a".parse::<WildcardPattern>().err()
+        );
+    }
+}
@@ -0,0 +1,122 @@
+/// A named class from POSIX, written like `[:digit:]` inside of a class.
+///
+/// These only match ASCII, the same as in the POSIX locale, so `[[:alpha:]]` doesn't match
+/// `é` and `[[:digit:]]` doesn't match `٣`. This keeps them the same when matching bytes,
+/// where a byte outside of ASCII is only part of a character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PosixClass {
+    /// `[:alnum:]`, letters and digits
+    Alnum,
+    /// `[:alpha:]`, letters
+    Alpha,
+    /// `[:blank:]`, a space or a tab
+    Blank,
+    /// `[:cntrl:]`, control characters
+    Cntrl,
+    /// `[:digit:]`, `0` to `9`
+    Digit,
+    /// `[:graph:]`, visible characters, which is everything printable except a space
+    Graph,
+    /// `[:lower:]`, lowercase letters
+    Lower,
+    /// `[:print:]`, printable characters, including a space
+    Print,
+    /// `[:punct:]`, punctuation
+    Punct,
+    /// `[:space:]`, whitespace, including the vertical tab and the form feed
+    Space,
+    /// `[:upper:]`, uppercase letters
+    Upper,
+    /// `[:xdigit:]`, hexadecimal digits
+    Xdigit,
+}
+
+/// The names of all of the classes, which `wildcard!` checks patterns against
+pub(crate) const NAMES: [&str; 12] = [
+    "alnum", "alpha", "blank", "cntrl", "digit", "graph", "lower", "print", "punct", "space",
+    "upper", "xdigit",
+];
+
+impl PosixClass {
+    /// Look up a class by the name between the colons
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        let class = match name {
+            "alnum" => PosixClass::Alnum,
+            "alpha" => PosixClass::Alpha,
+            "blank" => PosixClass::Blank,
+            "cntrl" => PosixClass::Cntrl,
+            "digit" => PosixClass::Digit,
+            "graph" => PosixClass::Graph,
+            "lower" => PosixClass::Lower,
+            "print" => PosixClass::Print,
+            "punct" => PosixClass::Punct,
+            "space" => PosixClass::Space,
+            "upper" => PosixClass::Upper,
+            "xdigit" => PosixClass::Xdigit,
+            _ => return None,
+        };
+        Some(class)
+    }
+
+    /// The name of the class, as it's written between the colons
+    pub fn name(self) -> &'static str {
+        match self {
+            PosixClass::Alnum => "alnum",
+            PosixClass::Alpha => "alpha",
+            PosixClass::Blank => "blank",
+            PosixClass::Cntrl => "cntrl",
+            PosixClass::Digit => "digit",
+            PosixClass::Graph => "graph",
+            PosixClass::Lower => "lower",
+            PosixClass::Print => "print",
+            PosixClass::Punct => "punct",
+            PosixClass::Space => "space",
+            PosixClass::Upper => "upper",
+            PosixClass::Xdigit => "xdigit",
+        }
+    }
+
+    pub(crate) fn contains(self, c: char) -> bool {
+        match self {
+            PosixClass::Alnum => c.is_ascii_alphanumeric(),
+            PosixClass::Alpha => c.is_ascii_alphabetic(),
+            PosixClass::Blank => c == ' ' || c == '\t',
+            PosixClass::Cntrl => c.is_ascii_control(),
+            PosixClass::Digit => c.is_ascii_digit(),
+            PosixClass::Graph => c.is_ascii_graphic(),
+            PosixClass::Lower => c.is_ascii_lowercase(),
+            PosixClass::Print => c.is_ascii_graphic() || c == ' ',
+            PosixClass::Punct => c.is_ascii_punctuation(),
+            // Unlike `char::is_ascii_whitespace`, this includes the vertical tab
+            PosixClass::Space => matches!(c, ' ' | '\t'..='\r'),
+            PosixClass::Upper => c.is_ascii_uppercase(),
+            PosixClass::Xdigit => c.is_ascii_hexdigit(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for name in ["alnum", "alpha", "digit", "punct", "space", "xdigit"] {
+            assert_eq!(
+                Some(name),
+                PosixClass::from_name(name).map(PosixClass::name)
+            );
+        }
+        assert_eq!(None, PosixClass::from_name("letter"));
+        assert_eq!(None, PosixClass::from_name("DIGIT"));
+    }
+
+    #[test]
+    fn classes_are_ascii_only() {
+        assert_eq!(true, PosixClass::Alpha.contains('a'));
+        assert_eq!(false, PosixClass::Alpha.contains('\u{e9}'));
+        assert_eq!(false, PosixClass::Digit.contains('\u{663}'));
+        assert_eq!(true, PosixClass::Space.contains('\u{b}'));
+        assert_eq!(false, PosixClass::Space.contains('\u{a0}'));
+    }
+}
@@ -0,0 +1,720 @@
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{LazyLock, Mutex};
+
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyIterator, PyString};
+
+use crate::translate::to_python_regex;
+use crate::{
+    count_matches, escape, filter_indices, find, first_match, is_wildcard_match_bytes,
+    is_wildcard_match_case_insensitive, is_wildcard_match_fnmatch, is_wildcard_match_normalized,
+    is_wildcard_match_path, is_wildcard_match_without_escapes, is_wildcard_search, match_all,
+    match_any, match_each, matches_all_texts, matches_at_least_one_of_each, matches_filtered,
+    matching_patterns, unescape, validate, MatchOptions, Normalization, PatternError,
+    WildcardPattern,
+};
+
+impl From<PatternError> for PyErr {
+    fn from(err: PatternError) -> PyErr {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+/// Checks if a pattern containing wildcards matches a given string.
+///
+/// `*` matches any number of characters, `?` matches exactly one, `[abc]` and `[a-z]`
+/// match one character out of a set, and `[!abc]` matches one character that is not in
+/// it. Classes can also hold named POSIX classes, like `[[:digit:]]`, which only match
+/// ASCII. A backslash escapes the character after it. Characters are graphemes, so `?`
+/// matches an `é` even when it's written with a combining accent.
+///
+/// The text comes first and the pattern second, as in `is_wildcard_match("main.rs", "*.rs")`.
+///
+/// The keyword arguments change how the pattern is matched, and can be combined:
+/// `escapes=False` treats backslashes as ordinary characters, `case_insensitive=True`
+/// ignores case, `normalization="NFC"` or `"NFD"` converts both to that unicode
+/// normalization form, `separator="/"` matches in path mode, where `*` doesn't match the
+/// separator but a `**` segment does, `braces=True` makes `{a,b}` match either `a` or
+/// `b`, `optional_question_marks=True` makes `?` mean that the character before it is
+/// optional, so `colou?r` matches `color`, and `literal=True` matches every character of
+/// the pattern literally, as if there were no wildcards. The GIL is released while
+/// matching.
+///
+/// A pattern given as a string is compiled once and kept in the same cache as `compile`
+/// uses, so matching with the same pattern again doesn't parse it again. The pattern can
+/// also be a `WildcardPattern` from `compile`, which saves looking it up. It's then matched
+/// with the options it was compiled with, and giving any keyword arguments as well raises a
+/// `ValueError`.
+///
+/// Texts and patterns are matched as unicode, so a string holding a lone surrogate, like
+/// the file names `os.listdir` returns for names which aren't valid UTF-8, raises a
+/// `ValueError` saying where the surrogate is, here and in every other function taking
+/// strings. Those names can be matched with `is_wildcard_match_bytes(os.fsencode(name),
+/// pattern)` instead.
+#[pyfunction(
+    "*",
+    escapes = "true",
+    case_insensitive = "false",
+    normalization = "None",
+    separator = "None",
+    braces = "false",
+    optional_question_marks = "false",
+    literal = "false"
+)]
+#[pyo3(name = "is_wildcard_match")]
+// Every keyword argument of the Python function is a parameter here
+#[allow(clippy::too_many_arguments)]
+fn py_is_wildcard_match(
+    py: Python<'_>,
+    text: Text<'_>,
+    pattern: PatternArgument<'_>,
+    escapes: bool,
+    case_insensitive: bool,
+    normalization: Option<&str>,
+    separator: Option<char>,
+    braces: bool,
+    optional_question_marks: bool,
+    literal: bool,
+) -> PyResult<bool> {
+    let options = match_options(
+        escapes,
+        case_insensitive,
+        normalization,
+        separator,
+        braces,
+        optional_question_marks,
+        literal,
+    )?;
+    match pattern {
+        PatternArgument::Source(pattern) => {
+            let compiled = cached(py, &pattern, &options)?;
+            let compiled: &WildcardPattern = &compiled.borrow(py);
+            Ok(py.allow_threads(|| compiled.matches(&text)))
+        }
+        PatternArgument::Compiled(compiled) if options == MatchOptions::new() => {
+            let compiled: &WildcardPattern = &compiled;
+            Ok(py.allow_threads(|| compiled.matches(&text)))
+        }
+        PatternArgument::Compiled(_) => Err(PyValueError::new_err(
+            "a compiled pattern is matched with the options it was compiled with, \
+             so keyword arguments can't be given with it",
+        )),
+    }
+}
+
+/// A pattern given to Python either as a string or already compiled
+enum PatternArgument<'a> {
+    Source(Text<'a>),
+    Compiled(PyRef<'a, WildcardPattern>),
+}
+
+impl<'a> FromPyObject<'a> for PatternArgument<'a> {
+    fn extract(ob: &'a PyAny) -> PyResult<Self> {
+        if let Ok(compiled) = ob.extract() {
+            return Ok(PatternArgument::Compiled(compiled));
+        }
+        if ob.is_instance_of::<PyString>()? {
+            return Ok(PatternArgument::Source(ob.extract()?));
+        }
+        Err(PyTypeError::new_err(format!(
+            "expected a str or a WildcardPattern, not {}",
+            ob.get_type().name()?
+        )))
+    }
+}
+
+/// A string passed in from Python, which is matched as UTF-8
+///
+/// Python strings can hold lone surrogates, which UTF-8 can't. They come from decoding
+/// file names that aren't valid UTF-8 with `surrogateescape`, as `os.listdir` does, and
+/// are turned in to a `ValueError` saying where the surrogate is and what to do instead.
+pub(crate) struct Text<'a>(&'a str);
+
+impl<'a> FromPyObject<'a> for Text<'a> {
+    fn extract(ob: &'a PyAny) -> PyResult<Self> {
+        let string: &PyString = ob.downcast()?;
+        string.to_str().map(Text).map_err(|err| {
+            let py = ob.py();
+            let position = err
+                .value(py)
+                .getattr("start")
+                .and_then(|start| start.extract::<usize>());
+            let surrogate = match position {
+                Ok(position) => format!("a lone surrogate at position {}", position),
+                Err(_) => "a lone surrogate".to_owned(),
+            };
+            let error = PyValueError::new_err(format!(
+                "the string has {}, so it isn't valid unicode and can't be matched; \
+                 to match a file name, pass os.fsencode(name) to is_wildcard_match_bytes",
+                surrogate
+            ));
+            error.set_cause(py, Some(err));
+            error
+        })
+    }
+}
+
+impl Deref for Text<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0
+    }
+}
+
+/// A list of strings passed in from Python, each of which is a `Text`
+struct Texts<'a>(Vec<&'a str>);
+
+impl<'a> FromPyObject<'a> for Texts<'a> {
+    fn extract(ob: &'a PyAny) -> PyResult<Self> {
+        let texts: Vec<Text<'a>> = ob.extract()?;
+        Ok(Texts(texts.into_iter().map(|text| text.0).collect()))
+    }
+}
+
+impl<'a> Deref for Texts<'a> {
+    type Target = [&'a str];
+
+    fn deref(&self) -> &[&'a str] {
+        &self.0
+    }
+}
+
+/// Gather the keyword arguments of a Python function in to `MatchOptions`
+fn match_options(
+    escapes: bool,
+    case_insensitive: bool,
+    normalization: Option<&str>,
+    separator: Option<char>,
+    braces: bool,
+    optional_question_marks: bool,
+    literal: bool,
+) -> PyResult<MatchOptions> {
+    let mut options = MatchOptions::new()
+        .escapes(escapes)
+        .case_insensitive(case_insensitive)
+        .braces(braces)
+        .optional_question_marks(optional_question_marks)
+        .literal(literal);
+    match normalization {
+        Some("NFC") => options = options.normalization(Normalization::Nfc),
+        Some("NFD") => options = options.normalization(Normalization::Nfd),
+        Some(form) => {
+            return Err(PyValueError::new_err(format!(
+                "unsupported normalization form {:?}, expected \"NFC\" or \"NFD\"",
+                form
+            )))
+        }
+        None => {}
+    }
+    if let Some(separator) = separator {
+        options = options.separator(separator);
+    }
+    Ok(options)
+}
+
+/// Checks if a pattern matches a given string the same way as `fnmatch.fnmatchcase`.
+///
+/// A backslash is an ordinary character, only `!` negates a class, a `]` right after the
+/// `[` or `[!` is part of the class, and there are no named classes. The one difference
+/// left is that `?` and classes match a grapheme rather than a code point. The GIL is
+/// released while matching.
+#[pyfunction]
+#[pyo3(name = "is_wildcard_match_fnmatch")]
+fn py_is_wildcard_match_fnmatch(py: Python<'_>, text: Text<'_>, pattern: Text<'_>) -> bool {
+    py.allow_threads(|| is_wildcard_match_fnmatch(&text, &pattern))
+}
+
+/// Checks if a pattern matches anywhere in a given string, rather than all of it.
+///
+/// The GIL is released while matching.
+#[pyfunction]
+#[pyo3(name = "is_wildcard_search")]
+fn py_is_wildcard_search(py: Python<'_>, text: Text<'_>, pattern: Text<'_>) -> bool {
+    py.allow_threads(|| is_wildcard_search(&text, &pattern))
+}
+
+/// Finds where a pattern matches in a given string, as the start and end of the part it
+/// matches, or `None` if it doesn't match any part of it.
+///
+/// The leftmost part is found, and the longest of those starting there, so that
+/// `text[start:end]` is the part. The GIL is released while matching.
+#[pyfunction]
+#[pyo3(name = "find")]
+fn py_find(py: Python<'_>, text: Text<'_>, pattern: Text<'_>) -> Option<(usize, usize)> {
+    let (start, end) = py.allow_threads(|| find(&text, &pattern))?;
+    // Python indexes strings by code point rather than by byte
+    let start_chars = text[..start].chars().count();
+    Some((start_chars, start_chars + text[start..end].chars().count()))
+}
+
+/// Checks if a pattern matches a given string, without treating backslashes as escapes.
+///
+/// The GIL is released while matching.
+#[pyfunction]
+#[pyo3(name = "is_wildcard_match_without_escapes")]
+fn py_is_wildcard_match_without_escapes(py: Python<'_>, text: Text<'_>, pattern: Text<'_>) -> bool {
+    py.allow_threads(|| is_wildcard_match_without_escapes(&text, &pattern))
+}
+
+/// Checks if a pattern matches a given string, ignoring case.
+///
+/// Both are lowercased before matching, which is not full case folding, so `STRASSE`
+/// does not match `straße`. The GIL is released while matching.
+#[pyfunction]
+#[pyo3(name = "is_wildcard_match_case_insensitive")]
+fn py_is_wildcard_match_case_insensitive(
+    py: Python<'_>,
+    text: Text<'_>,
+    pattern: Text<'_>,
+) -> bool {
+    py.allow_threads(|| is_wildcard_match_case_insensitive(&text, &pattern))
+}
+
+/// Checks if a pattern matches a given string, after converting both to unicode
+/// normalization form C.
+///
+/// This makes a character written as a single code point match the same character
+/// written with combining marks. The GIL is released while matching.
+#[pyfunction]
+#[pyo3(name = "is_wildcard_match_normalized")]
+fn py_is_wildcard_match_normalized(py: Python<'_>, text: Text<'_>, pattern: Text<'_>) -> bool {
+    py.allow_threads(|| is_wildcard_match_normalized(&text, &pattern))
+}
+
+/// Checks if a pattern matches a path, where `*`, `?` and classes don't match the
+/// separator, and a `**` segment matches any number of segments.
+///
+/// The GIL is released while matching.
+#[pyfunction(separator = "'/'")]
+#[pyo3(name = "is_wildcard_match_path")]
+fn py_is_wildcard_match_path(
+    py: Python<'_>,
+    text: Text<'_>,
+    pattern: Text<'_>,
+    separator: char,
+) -> bool {
+    py.allow_threads(|| is_wildcard_match_path(&text, &pattern, separator))
+}
+
+/// Checks if a pattern matches a given `bytes` object, byte by byte.
+///
+/// `?` matches exactly one byte, and ranges in classes compare byte values. The GIL is
+/// released while matching.
+#[pyfunction]
+#[pyo3(name = "is_wildcard_match_bytes")]
+fn py_is_wildcard_match_bytes(py: Python<'_>, text: &[u8], pattern: &[u8]) -> bool {
+    py.allow_threads(|| is_wildcard_match_bytes(text, pattern))
+}
+
+/// Returns what each `*` in the pattern matched as a list of strings, or `None` if the
+/// pattern doesn't match the text.
+///
+/// Stars take as much of the text as they can, from left to right, or as little as they
+/// can with `lazy=True`. The GIL is released while matching.
+#[pyfunction("*", lazy = "false")]
+#[pyo3(name = "captures")]
+fn py_captures(
+    py: Python<'_>,
+    text: Text<'_>,
+    pattern: Text<'_>,
+    lazy: bool,
+) -> Option<Vec<String>> {
+    let options = MatchOptions::new().lazy_captures(lazy);
+    py.allow_threads(|| WildcardPattern::with_options(&pattern, &options).captures(&text))
+}
+
+/// Compiles a pattern in to a `WildcardPattern`, mirroring `re.compile` in Python.
+///
+/// Takes the same keyword arguments as `is_wildcard_match`. Like `re`, the 512 patterns
+/// used most recently are cached, so compiling the same pattern with the same arguments
+/// again returns the same object. `purge` empties the cache.
+///
+/// With `strict=True`, an empty pattern raises a `ValueError` rather than compiling to a
+/// pattern that only matches an empty string, since it's usually a mistake.
+#[pyfunction(
+    "*",
+    escapes = "true",
+    case_insensitive = "false",
+    normalization = "None",
+    separator = "None",
+    braces = "false",
+    optional_question_marks = "false",
+    literal = "false",
+    strict = "false"
+)]
+// Every keyword argument of the Python function is a parameter here
+#[allow(clippy::too_many_arguments)]
+fn compile(
+    py: Python<'_>,
+    pattern: Text<'_>,
+    escapes: bool,
+    case_insensitive: bool,
+    normalization: Option<&str>,
+    separator: Option<char>,
+    braces: bool,
+    optional_question_marks: bool,
+    literal: bool,
+    strict: bool,
+) -> PyResult<Py<WildcardPattern>> {
+    if strict && pattern.is_empty() {
+        return Err(PatternError::EmptyPattern.into());
+    }
+    let options = match_options(
+        escapes,
+        case_insensitive,
+        normalization,
+        separator,
+        braces,
+        optional_question_marks,
+        literal,
+    )?;
+    cached(py, &pattern, &options)
+}
+
+/// Empties the cache of compiled patterns that `compile` and `is_wildcard_match` keep,
+/// like `re.purge`.
+#[pyfunction]
+fn purge() {
+    CACHE
+        .lock()
+        .unwrap_or_else(|error| error.into_inner())
+        .patterns
+        .clear();
+}
+
+/// The most patterns the cache keeps compiled, the same as the `re` module does
+const CACHE_SIZE: usize = 512;
+
+/// Patterns compiled from Python, with when each was last used, so that the one used
+/// least recently can be dropped once the cache is full
+#[derive(Default)]
+struct PatternCache {
+    patterns: HashMap<(String, MatchOptions), (Py<WildcardPattern>, u64)>,
+    uses: u64,
+}
+
+static CACHE: LazyLock<Mutex<PatternCache>> = LazyLock::new(Default::default);
+
+/// Compile a pattern with the options, or take it from the cache if it was compiled before.
+///
+/// The lock isn't held while compiling, since making the Python object can run the garbage
+/// collector, and a finalizer calling back in to this module would then deadlock.
+fn cached(py: Python<'_>, pattern: &str, options: &MatchOptions) -> PyResult<Py<WildcardPattern>> {
+    let key = (pattern.to_owned(), *options);
+    {
+        let mut cache = CACHE.lock().unwrap_or_else(|error| error.into_inner());
+        cache.uses += 1;
+        let now = cache.uses;
+        if let Some((compiled, last_used)) = cache.patterns.get_mut(&key) {
+            *last_used = now;
+            return Ok(compiled.clone_ref(py));
+        }
+    }
+    let compiled = Py::new(py, WildcardPattern::with_options(pattern, options))?;
+    let mut cache = CACHE.lock().unwrap_or_else(|error| error.into_inner());
+    cache.uses += 1;
+    let now = cache.uses;
+    // Another thread may have compiled the same pattern in the meantime, so keep theirs
+    if let Some((existing, last_used)) = cache.patterns.get_mut(&key) {
+        *last_used = now;
+        return Ok(existing.clone_ref(py));
+    }
+    let mut evicted = None;
+    if cache.patterns.len() >= CACHE_SIZE {
+        let oldest = cache
+            .patterns
+            .iter()
+            .min_by_key(|(_, (_, last_used))| *last_used)
+            .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+            evicted = cache.patterns.remove(&oldest);
+        }
+    }
+    cache.patterns.insert(key, (compiled.clone_ref(py), now));
+    // The evicted pattern is only dropped once the lock is released
+    drop(cache);
+    drop(evicted);
+    Ok(compiled)
+}
+
+/// Returns the names which match the pattern, like `fnmatch.filter`.
+///
+/// The pattern is compiled once, and the GIL is released while matching. The returned
+/// list holds the same string objects that were passed in.
+#[pyfunction]
+#[pyo3(name = "filter")]
+fn py_filter<'py>(
+    py: Python<'py>,
+    names: Vec<&'py PyString>,
+    pattern: Text<'_>,
+) -> PyResult<Vec<&'py PyString>> {
+    let texts = names
+        .iter()
+        .map(|name| name.extract().map(|Text(text)| text))
+        .collect::<PyResult<Vec<&str>>>()?;
+    let pattern = WildcardPattern::new(&pattern);
+
+    let matches = py.allow_threads(|| {
+        texts
+            .iter()
+            .map(|text| pattern.matches(text))
+            .collect::<Vec<bool>>()
+    });
+
+    Ok(names
+        .into_iter()
+        .zip(matches)
+        .filter_map(|(name, matches)| matches.then_some(name))
+        .collect())
+}
+
+/// Returns an iterator over the names which match the pattern, going through the names
+/// only as the iterator is advanced.
+///
+/// Unlike `filter`, the names can be any iterable of strings, including a generator, and
+/// neither they nor the matches are ever all held in a list at once. The pattern is
+/// compiled once, up front. The GIL is held while matching, since each name is matched on
+/// its own.
+#[pyfunction]
+#[pyo3(name = "ifilter")]
+fn py_ifilter(names: &PyAny, pattern: Text<'_>) -> PyResult<FilterIterator> {
+    Ok(FilterIterator {
+        names: names.iter()?.into(),
+        pattern: WildcardPattern::new(&pattern),
+    })
+}
+
+/// The iterator that `ifilter` returns
+#[pyclass(module = "pyglob")]
+struct FilterIterator {
+    names: Py<PyIterator>,
+    pattern: WildcardPattern,
+}
+
+#[pymethods]
+impl FilterIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// The next name which matches, skipping over any that don't
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        for name in self.names.as_ref(py) {
+            let name = name?;
+            if self.pattern.matches(&name.extract::<Text<'_>>()?) {
+                return Ok(Some(name.into()));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Splits the names in to a list of those which match the pattern and a list of those
+/// which don't, returned as a tuple.
+///
+/// The pattern is compiled once, and the GIL is released while matching. The returned
+/// lists hold the same string objects that were passed in.
+#[pyfunction]
+#[pyo3(name = "partition")]
+fn py_partition<'py>(
+    py: Python<'py>,
+    names: Vec<&'py PyString>,
+    pattern: Text<'_>,
+) -> PyResult<(Vec<&'py PyString>, Vec<&'py PyString>)> {
+    let texts = names
+        .iter()
+        .map(|name| name.extract().map(|Text(text)| text))
+        .collect::<PyResult<Vec<&str>>>()?;
+    let matches = py.allow_threads(|| filter_indices(&texts, &pattern));
+
+    let mut matching = Vec::with_capacity(matches.len());
+    let mut rest = Vec::with_capacity(names.len() - matches.len());
+    let mut matches = matches.into_iter().peekable();
+    for (i, name) in names.into_iter().enumerate() {
+        if matches.next_if_eq(&i).is_some() {
+            matching.push(name);
+        } else {
+            rest.push(name);
+        }
+    }
+    Ok((matching, rest))
+}
+
+/// Returns the indices of the names which match the pattern, in increasing order.
+///
+/// The pattern is compiled once, and the GIL is released while matching.
+#[pyfunction]
+#[pyo3(name = "filter_indices")]
+fn py_filter_indices(py: Python<'_>, names: Texts<'_>, pattern: Text<'_>) -> Vec<usize> {
+    py.allow_threads(|| filter_indices(&names, &pattern))
+}
+
+/// Returns how many of the texts match the pattern, without building a list of them.
+///
+/// The pattern is compiled once, and the GIL is released while matching.
+#[pyfunction]
+#[pyo3(name = "count_matches")]
+fn py_count_matches(py: Python<'_>, texts: Texts<'_>, pattern: Text<'_>) -> usize {
+    py.allow_threads(|| count_matches(&texts, &pattern))
+}
+
+/// Returns whether each of the texts matches the pattern, as a list of the same length and
+/// in the same order as the texts.
+///
+/// The pattern is compiled once, and the GIL is released while matching.
+#[pyfunction]
+#[pyo3(name = "matches_all_texts")]
+fn py_matches_all_texts(py: Python<'_>, texts: Texts<'_>, pattern: Text<'_>) -> Vec<bool> {
+    py.allow_threads(|| matches_all_texts(&texts, &pattern))
+}
+
+/// Checks if the text matches at least one of the patterns.
+///
+/// An empty list of patterns never matches. The GIL is released while matching.
+#[pyfunction]
+#[pyo3(name = "match_any")]
+fn py_match_any(py: Python<'_>, text: Text<'_>, patterns: Texts<'_>) -> bool {
+    py.allow_threads(|| match_any(&text, &patterns))
+}
+
+/// Returns the index of the first pattern that matches the text, or `None`.
+///
+/// The GIL is released while matching.
+#[pyfunction]
+#[pyo3(name = "first_match")]
+fn py_first_match(py: Python<'_>, text: Text<'_>, patterns: Texts<'_>) -> Option<usize> {
+    py.allow_threads(|| first_match(&text, &patterns))
+}
+
+/// Returns every pattern that matches the text, in the order they were given.
+///
+/// The GIL is released while matching.
+#[pyfunction]
+#[pyo3(name = "matching_patterns")]
+fn py_matching_patterns<'a>(py: Python<'_>, text: Text<'_>, patterns: Texts<'a>) -> Vec<&'a str> {
+    py.allow_threads(|| matching_patterns(&text, &patterns))
+}
+
+/// Returns whether the text matches each of the patterns, as a list of the same length
+/// and in the same order as the patterns.
+///
+/// The GIL is released while matching.
+#[pyfunction]
+#[pyo3(name = "match_each")]
+fn py_match_each(py: Python<'_>, text: Text<'_>, patterns: Texts<'_>) -> Vec<bool> {
+    py.allow_threads(|| match_each(&text, &patterns))
+}
+
+/// Checks if the text matches every one of the patterns.
+///
+/// An empty list of patterns always matches. The GIL is released while matching.
+#[pyfunction]
+#[pyo3(name = "match_all")]
+fn py_match_all(py: Python<'_>, text: Text<'_>, patterns: Texts<'_>) -> bool {
+    py.allow_threads(|| match_all(&text, &patterns))
+}
+
+/// Checks if the text matches at least one pattern out of each of the groups.
+///
+/// A group without any patterns never matches, while no groups at all always match. The
+/// GIL is released while matching.
+#[pyfunction]
+#[pyo3(name = "matches_at_least_one_of_each")]
+fn py_matches_at_least_one_of_each(py: Python<'_>, text: Text<'_>, groups: Vec<Texts<'_>>) -> bool {
+    let groups = groups
+        .iter()
+        .map(|group| &group[..])
+        .collect::<Vec<&[&str]>>();
+    py.allow_threads(|| matches_at_least_one_of_each(&text, &groups))
+}
+
+/// Checks if the text matches at least one of the include patterns and none of the exclude
+/// patterns.
+///
+/// Without any include patterns everything is included, and without any exclude patterns
+/// nothing is excluded. The GIL is released while matching.
+#[pyfunction]
+#[pyo3(name = "matches_filtered")]
+fn py_matches_filtered(
+    py: Python<'_>,
+    text: Text<'_>,
+    includes: Texts<'_>,
+    excludes: Texts<'_>,
+) -> bool {
+    py.allow_threads(|| matches_filtered(&text, &includes, &excludes))
+}
+
+/// Checks that a pattern is well formed, raising a `ValueError` describing the problem
+/// if it has an unterminated class or a dangling escape.
+#[pyfunction]
+#[pyo3(name = "validate")]
+fn py_validate(pattern: Text<'_>) -> PyResult<()> {
+    Ok(validate(&pattern)?)
+}
+
+/// Escapes a string, so that it can be used as a pattern which only matches the string
+/// itself, like `re.escape`.
+#[pyfunction]
+#[pyo3(name = "escape")]
+fn py_escape(literal: Text<'_>) -> String {
+    escape(&literal)
+}
+
+/// Removes the escapes from a pattern, so that `unescape(escape(text)) == text`.
+///
+/// Wildcards which aren't escaped are left as they are.
+#[pyfunction]
+#[pyo3(name = "unescape")]
+fn py_unescape(pattern: Text<'_>) -> String {
+    unescape(&pattern)
+}
+
+/// Translates a pattern in to a regular expression for Python's `re` module, like
+/// `fnmatch.translate`.
+///
+/// `*` becomes `.*`, `?` becomes `.`, classes become regular expression classes, and
+/// everything else is escaped. Note that `?` in a pattern matches a whole grapheme, while
+/// `.` in a regular expression only matches a single code point, so the two can disagree
+/// on text with combining characters.
+#[pyfunction]
+fn translate(pattern: Text<'_>) -> String {
+    to_python_regex(WildcardPattern::new(&pattern).tokens())
+}
+
+#[pymodule]
+fn pyglob(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(py_is_wildcard_match, m)?)?;
+    m.add_function(wrap_pyfunction!(py_is_wildcard_search, m)?)?;
+    m.add_function(wrap_pyfunction!(py_find, m)?)?;
+    m.add_function(wrap_pyfunction!(py_is_wildcard_match_without_escapes, m)?)?;
+    m.add_function(wrap_pyfunction!(py_is_wildcard_match_case_insensitive, m)?)?;
+    m.add_function(wrap_pyfunction!(py_is_wildcard_match_normalized, m)?)?;
+    m.add_function(wrap_pyfunction!(py_is_wildcard_match_path, m)?)?;
+    m.add_function(wrap_pyfunction!(py_is_wildcard_match_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(py_is_wildcard_match_fnmatch, m)?)?;
+    m.add_function(wrap_pyfunction!(py_captures, m)?)?;
+    m.add_function(wrap_pyfunction!(compile, m)?)?;
+    m.add_function(wrap_pyfunction!(purge, m)?)?;
+    m.add_function(wrap_pyfunction!(py_filter, m)?)?;
+    m.add_function(wrap_pyfunction!(py_ifilter, m)?)?;
+    m.add_function(wrap_pyfunction!(py_filter_indices, m)?)?;
+    m.add_function(wrap_pyfunction!(py_partition, m)?)?;
+    m.add_function(wrap_pyfunction!(py_count_matches, m)?)?;
+    m.add_function(wrap_pyfunction!(py_matches_all_texts, m)?)?;
+    m.add_function(wrap_pyfunction!(py_match_any, m)?)?;
+    m.add_function(wrap_pyfunction!(py_match_all, m)?)?;
+    m.add_function(wrap_pyfunction!(py_first_match, m)?)?;
+    m.add_function(wrap_pyfunction!(py_matching_patterns, m)?)?;
+    m.add_function(wrap_pyfunction!(py_match_each, m)?)?;
+    m.add_function(wrap_pyfunction!(py_matches_at_least_one_of_each, m)?)?;
+    m.add_function(wrap_pyfunction!(py_matches_filtered, m)?)?;
+    m.add_function(wrap_pyfunction!(py_validate, m)?)?;
+    m.add_function(wrap_pyfunction!(py_escape, m)?)?;
+    m.add_function(wrap_pyfunction!(py_unescape, m)?)?;
+    m.add_function(wrap_pyfunction!(translate, m)?)?;
+    m.add_class::<WildcardPattern>()?;
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+use crate::WildcardPattern;
+
+/// A pattern is serialized as the string that `Display` gives, so the options it was
+/// compiled with are not kept. See the `Display` implementation of `WildcardPattern` for
+/// which patterns come back the same.
+impl Serialize for WildcardPattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A pattern is deserialized from a string with the default options, and a malformed
+/// pattern is an error rather than being matched literally, the same as `str::parse`.
+impl<'de> Deserialize<'de> for WildcardPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        pattern.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_round_trip_through_json() {
+        let patterns = ["*.rs", "src/[a-z]*", "\\*?"]
+            .into_iter()
+            .map(WildcardPattern::new)
+            .collect::<Vec<_>>();
+        let json = serde_json::to_string(&patterns).unwrap();
+        assert_eq!(r#"["*.rs","src/[a-z]*","\\*?"]"#, json);
+
+        let parsed = serde_json::from_str::<Vec<WildcardPattern>>(&json).unwrap();
+        assert_eq!(3, parsed.len());
+        assert_eq!(true, parsed[0].matches("main.rs"));
+        assert_eq!(true, parsed[1].matches("src/main"));
+        assert_eq!(true, parsed[2].matches("*x"));
+        assert_eq!(false, parsed[2].matches("ax"));
+    }
+
+    #[test]
+    fn serialized_pattern_is_canonical() {
+        let json = serde_json::to_string(&WildcardPattern::new("a***b")).unwrap();
+        assert_eq!(r#""a*b""#, json);
+    }
+
+    #[test]
+    fn invalid_pattern_fails_to_deserialize() {
+        let err = serde_json::from_str::<Vec<WildcardPattern>>(r#"["*.rs", "[a-z"]"#)
+            .map(|_| ())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            true,
+            err.starts_with("unterminated class starting at position 0"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn non_string_fails_to_deserialize() {
+        assert_eq!(true, serde_json::from_str::<WildcardPattern>("42").is_err());
+    }
+}
@@ -0,0 +1,1236 @@
+use std::fmt::{self, Debug, Write};
+use std::ops::Range;
+
+use crate::granularity::Granularity;
+use crate::posix::PosixClass;
+use crate::PatternError;
+
+/// A single element of a parsed pattern, which is matched against the text.
+///
+/// Patterns are usually matched grapheme by grapheme, in which case the literals are
+/// `String`s, but they can also be matched byte by byte, with `u8` literals. `parse` gives
+/// the tokens of a pattern, and `to_pattern` writes them back out, so a pattern can be
+/// looked at or changed a token at a time.
+///
+/// New kinds of token can be added as the syntax grows, so a `match` on a token needs a
+/// catch-all arm.
+///
+/// # Examples
+/// ```
+/// use pyglob::{parse, Token};
+/// let tokens = parse("a?*").unwrap();
+/// assert_eq!(
+///     tokens,
+///     vec![Token::Literal(String::from("a")), Token::AnyOne, Token::AnyRun]
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Token<O = String> {
+    /// A grapheme which has to appear in the text as-is
+    Literal(O),
+    /// `?`, which matches exactly one grapheme
+    AnyOne,
+    /// `*`, which matches any number of graphemes, including none
+    AnyRun,
+    /// `*` in path mode, which matches any number of graphemes other than the separator
+    AnyRunInSegment(char),
+    /// `**/` in path mode, which matches any number of whole path segments, each together
+    /// with the separator after it
+    AnySegments(char),
+    /// `[...]`, which matches exactly one grapheme out of a set, or `[!...]` which
+    /// matches exactly one grapheme that is not in the set
+    Class {
+        /// Whether the class matches the graphemes which aren't in it
+        negated: bool,
+        /// What is in the class, in the order it was written
+        items: Vec<ClassItem<O>>,
+    },
+    /// `{a,b}` when braces are turned on, which matches if any one of the alternatives
+    /// matches at that point. An alternative can be empty, and can contain more braces.
+    Alternatives(Vec<Vec<Token<O>>>),
+    /// A grapheme or class followed by `?` when question marks are optional, which matches
+    /// what the token before the `?` matches, or nothing
+    Optional(Box<Token<O>>),
+}
+
+/// A member of a `[...]` class
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ClassItem<O = String> {
+    /// A single grapheme, like the `a` in `[abc]`
+    Single(O),
+    /// An inclusive range of unicode scalar values, like `[a-z]`
+    Range(char, char),
+    /// A named class, like `[[:digit:]]`, together with the separator if it has to be
+    /// left out of the class in path mode
+    Named(PosixClass, Option<char>),
+}
+
+/// A piece of text that a pattern is matched on one at a time, such as a grapheme. Units
+/// are small, so they're passed around by value.
+pub(crate) trait Unit: Copy {
+    /// How the unit is stored in a compiled pattern
+    type Owned: Clone + Debug + PartialEq + Eq;
+
+    fn to_owned_unit(&self) -> Self::Owned;
+
+    /// Checks if this is the same unit as one stored in a pattern
+    fn is(&self, owned: &Self::Owned) -> bool;
+
+    /// The scalar value of the unit, if it is a single one. This is what ranges compare,
+    /// and it's also how the wildcards of a pattern are recognised.
+    fn scalar(&self) -> Option<char>;
+}
+
+impl Unit for &str {
+    type Owned = String;
+
+    fn to_owned_unit(&self) -> String {
+        String::from(*self)
+    }
+
+    fn is(&self, owned: &String) -> bool {
+        *self == owned
+    }
+
+    fn scalar(&self) -> Option<char> {
+        let mut chars = self.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    }
+}
+
+/// Bytes compare in ranges by their value, so `[\x80-\xff]` matches any non-ASCII byte
+impl Unit for u8 {
+    type Owned = u8;
+
+    fn to_owned_unit(&self) -> u8 {
+        *self
+    }
+
+    fn is(&self, owned: &u8) -> bool {
+        self == owned
+    }
+
+    fn scalar(&self) -> Option<char> {
+        Some(char::from(*self))
+    }
+}
+
+impl<O> ClassItem<O> {
+    fn contains<U: Unit<Owned = O>>(&self, unit: &U) -> bool {
+        match self {
+            ClassItem::Single(member) => unit.is(member),
+            ClassItem::Range(start, end) => match unit.scalar() {
+                Some(c) => *start <= c && c <= *end,
+                None => false,
+            },
+            ClassItem::Named(class, excluded) => match unit.scalar() {
+                Some(c) => class.contains(c) && Some(c) != *excluded,
+                None => false,
+            },
+        }
+    }
+}
+
+impl<O> Token<O> {
+    /// The same token with each of its graphemes, including those in classes and groups,
+    /// replaced with what `f` gives for it
+    pub(crate) fn map_units<P>(&self, f: &mut impl FnMut(&O) -> P) -> Token<P> {
+        match self {
+            Token::Literal(literal) => Token::Literal(f(literal)),
+            Token::AnyOne => Token::AnyOne,
+            Token::AnyRun => Token::AnyRun,
+            Token::AnyRunInSegment(separator) => Token::AnyRunInSegment(*separator),
+            Token::AnySegments(separator) => Token::AnySegments(*separator),
+            Token::Class { negated, items } => Token::Class {
+                negated: *negated,
+                items: items
+                    .iter()
+                    .map(|item| match item {
+                        ClassItem::Single(member) => ClassItem::Single(f(member)),
+                        ClassItem::Range(start, end) => ClassItem::Range(*start, *end),
+                        ClassItem::Named(class, excluded) => ClassItem::Named(*class, *excluded),
+                    })
+                    .collect(),
+            },
+            Token::Alternatives(alternatives) => Token::Alternatives(
+                alternatives
+                    .iter()
+                    .map(|alternative| alternative.iter().map(|token| token.map_units(f)).collect())
+                    .collect(),
+            ),
+            Token::Optional(token) => Token::Optional(Box::new(token.map_units(f))),
+        }
+    }
+
+    /// Checks if this is one of the stars, which can match any number of graphemes
+    pub(crate) fn is_star(&self) -> bool {
+        matches!(
+            self,
+            Token::AnyRun | Token::AnyRunInSegment(_) | Token::AnySegments(_)
+        )
+    }
+
+    /// Checks if this token can match an empty text, not counting groups
+    pub(crate) fn matches_empty(&self) -> bool {
+        self.is_star() || matches!(self, Token::Optional(_))
+    }
+
+    /// Checks if there is any text at all that this token matches. Only a class can match
+    /// nothing, like `[]` or `[z-a]`, or a group of which every alternative matches nothing.
+    pub(crate) fn can_match(&self) -> bool {
+        match self {
+            Token::Class {
+                negated: false,
+                items,
+            } => items.iter().any(|item| match item {
+                ClassItem::Range(start, end) => start <= end,
+                _ => true,
+            }),
+            Token::Alternatives(alternatives) => alternatives
+                .iter()
+                .any(|alternative| alternative.iter().all(Token::can_match)),
+            _ => true,
+        }
+    }
+
+    /// Checks if this token consumes the given grapheme of the text.
+    ///
+    /// The stars are not handled here, since they may consume any number of graphemes.
+    pub(crate) fn matches_one<U: Unit<Owned = O>>(&self, unit: &U) -> bool {
+        match self {
+            Token::Literal(literal) => unit.is(literal),
+            Token::AnyOne => true,
+            Token::AnyRun | Token::AnyRunInSegment(_) | Token::AnySegments(_) => false,
+            // Alternatives can consume any number of graphemes, depending on which matches
+            Token::Alternatives(_) => false,
+            // And an optional token can consume nothing at all
+            Token::Optional(_) => false,
+            Token::Class { negated, items } => {
+                items.iter().any(|item| item.contains(unit)) != *negated
+            }
+        }
+    }
+}
+
+/// The parts of the pattern syntax which can be turned on or off
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Syntax {
+    /// Whether a `\\` makes the grapheme after it a literal
+    pub(crate) escapes: bool,
+    /// The separator between the segments of a path, in path mode
+    pub(crate) separator: Option<char>,
+    /// Whether `*` and `?` don't match a newline, and `**` does, outside of path mode
+    pub(crate) multiline: bool,
+    /// Whether `{a,b}` is a group of alternatives
+    pub(crate) braces: bool,
+    /// Whether a `|` outside of classes and groups splits the pattern in to alternatives
+    pub(crate) alternation: bool,
+    /// Whether `?` makes the grapheme or class before it optional, rather than matching
+    /// any one grapheme
+    pub(crate) optional_question_marks: bool,
+    /// Whether every grapheme is a literal, so that there are no wildcards at all
+    pub(crate) literal: bool,
+    /// Whether classes are parsed like Python's `fnmatch` does
+    pub(crate) fnmatch: bool,
+    /// The wildcard which matches any number of graphemes, normally `*`
+    pub(crate) any_run: char,
+    /// The wildcard which matches any one grapheme, normally `?`
+    pub(crate) any_one: char,
+}
+
+impl Default for Syntax {
+    fn default() -> Self {
+        Syntax {
+            escapes: false,
+            separator: None,
+            multiline: false,
+            braces: false,
+            alternation: true,
+            optional_question_marks: false,
+            literal: false,
+            fnmatch: false,
+            any_run: '*',
+            any_one: '?',
+        }
+    }
+}
+
+/// Write tokens back out as a pattern, which parses to equivalent tokens again.
+///
+/// Anything that would otherwise be a wildcard gets a backslash in front of it, unless the
+/// separator is a backslash, in which case there are no escapes. In path mode an `AnyRun`
+/// can only have come from a `**` at the end of the pattern, so it's written that way.
+/// With braces, the `{`, `}` and `,` which aren't part of a group are escaped too, and
+/// with alternation every literal `|`. Without braces, a pattern that is one group of
+/// alternatives can only have come from `|` between them, so it's written that way.
+pub(crate) fn write_pattern(
+    f: &mut fmt::Formatter<'_>,
+    tokens: &[Token],
+    syntax: &Syntax,
+) -> fmt::Result {
+    match tokens {
+        [Token::Alternatives(alternatives)] if syntax.alternation && !syntax.braces => {
+            for (i, alternative) in alternatives.iter().enumerate() {
+                if i > 0 {
+                    f.write_str("|")?;
+                }
+                write_tokens(f, alternative, syntax)?;
+            }
+            Ok(())
+        }
+        _ => write_tokens(f, tokens, syntax),
+    }
+}
+
+/// Write tokens back out as a part of a pattern, see `write_pattern`.
+fn write_tokens(f: &mut fmt::Formatter<'_>, tokens: &[Token], syntax: &Syntax) -> fmt::Result {
+    let Syntax {
+        separator,
+        multiline,
+        braces,
+        alternation,
+        optional_question_marks,
+        any_run,
+        any_one,
+        ..
+    } = *syntax;
+    let escapes = separator != Some('\\');
+    let special = format!(
+        "{}{}[\\{}{}",
+        any_run,
+        any_one,
+        if braces { "{}," } else { "" },
+        if alternation { "|" } else { "" }
+    );
+    for token in tokens {
+        match token {
+            Token::Literal(literal) => write_escaped(f, literal, &special, escapes)?,
+            // In multiline mode a `?` doesn't match a newline, and this does
+            Token::AnyOne if multiline => f.write_str("[!]")?,
+            Token::AnyOne => write!(f, "{}", any_one)?,
+            Token::AnyRun if separator.is_some() || multiline => {
+                write!(f, "{}{}", any_run, any_run)?
+            }
+            Token::AnyRun | Token::AnyRunInSegment(_) => write!(f, "{}", any_run)?,
+            Token::AnySegments(separator) => write!(f, "{}{}{}", any_run, any_run, separator)?,
+            Token::Class { negated, items } => {
+                // A negated class in path mode ends with the separator, which is added back
+                // when the class is parsed again
+                let items = match (separator, items.split_last()) {
+                    (Some(separator), Some((ClassItem::Range(start, end), rest)))
+                        if *negated && *start == separator && *end == separator =>
+                    {
+                        rest
+                    }
+                    // And in multiline mode a `?` is a class of everything but a newline
+                    (None, Some((ClassItem::Range('\n', '\n'), []))) if *negated && multiline => {
+                        &[]
+                    }
+                    _ => &items[..],
+                };
+                // Which is the same as a class that excludes nothing, unless `?` means
+                // something else
+                if *negated && items.is_empty() && !optional_question_marks {
+                    write!(f, "{}", any_one)?;
+                    continue;
+                }
+                f.write_str(if *negated { "[!" } else { "[" })?;
+                for item in items {
+                    match item {
+                        ClassItem::Single(member) => {
+                            write_escaped(f, member, CLASS_SPECIAL, escapes)?
+                        }
+                        ClassItem::Range(start, end) => {
+                            write_escaped(f, &start.to_string(), CLASS_SPECIAL, escapes)?;
+                            f.write_str("-")?;
+                            write_escaped(f, &end.to_string(), CLASS_SPECIAL, escapes)?;
+                        }
+                        ClassItem::Named(class, _) => write!(f, "[:{}:]", class.name())?,
+                    }
+                }
+                f.write_str("]")?;
+            }
+            Token::Alternatives(alternatives) => {
+                f.write_str("{")?;
+                for (i, alternative) in alternatives.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_tokens(f, alternative, syntax)?;
+                }
+                f.write_str("}")?;
+            }
+            Token::Optional(token) => {
+                write_tokens(f, std::slice::from_ref(token), syntax)?;
+                write!(f, "{}", any_one)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The characters which mean something inside of a class
+const CLASS_SPECIAL: &str = "]\\-!^";
+
+fn write_escaped(
+    f: &mut fmt::Formatter<'_>,
+    grapheme: &str,
+    special: &str,
+    escapes: bool,
+) -> fmt::Result {
+    if escapes && matches!(grapheme.scalar(), Some(c) if special.contains(c)) {
+        f.write_str("\\")?;
+    }
+    f.write_str(grapheme)
+}
+
+/// Describes the tokens, one to a line, for seeing how a pattern was parsed.
+///
+/// Literals are written as Rust string literals, so that a grapheme made of several
+/// scalars shows its combining marks, and the alternatives of a group are indented under
+/// it.
+pub(crate) fn explain(tokens: &[Token], granularity: Granularity) -> String {
+    let mut explanation = String::new();
+    explain_tokens(&mut explanation, tokens, granularity.noun(), 0);
+    explanation
+}
+
+/// Describe the tokens, each on its own line indented by the given depth
+fn explain_tokens(out: &mut String, tokens: &[Token], unit: &str, depth: usize) {
+    if tokens.is_empty() {
+        writeln!(out, "{:indent$}nothing", "", indent = 2 * depth).unwrap();
+    }
+    for token in tokens {
+        write!(out, "{:indent$}", "", indent = 2 * depth).unwrap();
+        explain_token(out, token, unit, depth);
+    }
+}
+
+/// Describe one token, finishing the line it's on
+fn explain_token(out: &mut String, token: &Token, unit: &str, depth: usize) {
+    // Writing to a string can't fail
+    match token {
+        Token::Literal(literal) => writeln!(out, "literal {:?}", literal).unwrap(),
+        Token::AnyOne => writeln!(out, "any one {}", unit).unwrap(),
+        Token::AnyRun => writeln!(out, "any run of {}s", unit).unwrap(),
+        Token::AnyRunInSegment(separator) => {
+            writeln!(out, "any run of {}s except {:?}", unit, separator).unwrap()
+        }
+        Token::AnySegments(separator) => {
+            writeln!(out, "any run of segments, each ending in {:?}", separator).unwrap()
+        }
+        Token::Class { negated, items } => {
+            let within = if *negated { "not in" } else { "in" };
+            write!(out, "one {} {}", unit, within).unwrap();
+            for (i, item) in items.iter().enumerate() {
+                out.push_str(if i == 0 { " " } else { ", " });
+                match item {
+                    ClassItem::Single(member) => write!(out, "{:?}", member),
+                    ClassItem::Range(start, end) => {
+                        write!(out, "{:?} to {:?}", start.to_string(), end.to_string())
+                    }
+                    ClassItem::Named(class, None) => write!(out, "[:{}:]", class.name()),
+                    ClassItem::Named(class, Some(separator)) => {
+                        write!(out, "[:{}:] except {:?}", class.name(), separator)
+                    }
+                }
+                .unwrap();
+            }
+            if items.is_empty() {
+                out.push_str(" nothing");
+            }
+            out.push('\n');
+        }
+        Token::Alternatives(alternatives) => {
+            writeln!(out, "one of {} alternatives", alternatives.len()).unwrap();
+            for (i, alternative) in alternatives.iter().enumerate() {
+                writeln!(out, "{:indent$}{}:", "", i + 1, indent = 2 * depth + 2).unwrap();
+                explain_tokens(out, alternative, unit, depth + 2);
+            }
+        }
+        Token::Optional(token) => {
+            out.push_str("optionally ");
+            explain_token(out, token, unit, depth);
+        }
+    }
+}
+
+/// Split a pattern in to the tokens that make it up.
+///
+/// If `escapes` is set, a `\\` makes the grapheme after it a literal.
+pub(crate) fn parse<U: Unit>(units: &[U], escapes: bool) -> Vec<Token<U::Owned>> {
+    parse_path(
+        units,
+        &Syntax {
+            escapes,
+            ..Syntax::default()
+        },
+    )
+}
+
+/// Split a pattern in to tokens, in path mode if there is a separator.
+///
+/// In path mode `*` doesn't match the separator, and neither do `?` or classes. A `**`
+/// which makes up a whole segment of the pattern matches across separators, and any
+/// other `**` is the same as `*`.
+///
+/// With braces, a `{...}` group with a matching `}` is split at its commas in to
+/// alternatives, and anywhere else `{`, `}` and `,` are literals.
+///
+/// With optional question marks, a `?` after a grapheme or class makes it optional, and
+/// after a group it adds an empty alternative. After a star it does nothing, since the
+/// star can already match nothing, and with nothing before it it's a literal.
+///
+/// With alternation, a `|` which isn't inside of a class or a group splits the whole
+/// pattern in to alternatives, which are parsed on their own and put in a single group.
+///
+/// In literal mode every grapheme is a literal, and none of the above applies.
+pub(crate) fn parse_path<U: Unit>(units: &[U], syntax: &Syntax) -> Vec<Token<U::Owned>> {
+    if syntax.literal {
+        return units
+            .iter()
+            .map(|unit| Token::Literal(unit.to_owned_unit()))
+            .collect();
+    }
+    if syntax.alternation {
+        let ranges = split_top_level(units, syntax);
+        if ranges.len() > 1 {
+            let alternatives = ranges
+                .into_iter()
+                .map(|range| parse_tokens(&units[range], syntax, true))
+                .collect();
+            return vec![Token::Alternatives(alternatives)];
+        }
+    }
+    parse_tokens(units, syntax, true)
+}
+
+/// Find the alternatives that the `|` outside of classes and groups split a pattern in to.
+/// A pattern without any is a single alternative.
+fn split_top_level<U: Unit>(units: &[U], syntax: &Syntax) -> Vec<Range<usize>> {
+    let mut alternatives = Vec::new();
+    let mut start: usize = 0;
+    let mut i: usize = 0;
+    while i < units.len() {
+        match units[i].scalar() {
+            Some('\\') if syntax.escapes && i + 1 < units.len() => i += 1,
+            Some('[') => {
+                let class = parse_class(
+                    &units[i + 1..],
+                    syntax.escapes,
+                    syntax.separator,
+                    syntax.fnmatch,
+                );
+                if let Some((_, length)) = class {
+                    i += length;
+                }
+            }
+            Some('{') if syntax.braces => {
+                if let Some((_, length)) = split_alternatives(&units[i + 1..], syntax.escapes) {
+                    i += length;
+                }
+            }
+            Some('|') => {
+                alternatives.push(start..i);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    alternatives.push(start..units.len());
+    alternatives
+}
+
+/// Split a pattern in to tokens, where `segment_start` says whether the pattern starts a
+/// segment of the path. The alternatives of a group are parsed the same way, and start a
+/// segment if the group does.
+fn parse_tokens<U: Unit>(
+    units: &[U],
+    syntax: &Syntax,
+    mut segment_start: bool,
+) -> Vec<Token<U::Owned>> {
+    let Syntax {
+        escapes,
+        separator,
+        multiline,
+        braces,
+        optional_question_marks,
+        fnmatch,
+        any_run,
+        any_one,
+        ..
+    } = *syntax;
+    let mut tokens = Vec::with_capacity(units.len());
+    let mut i: usize = 0;
+    while i < units.len() {
+        let scalar = units[i].scalar();
+        match scalar {
+            // A trailing backslash has nothing to escape, so it's a literal backslash
+            Some('\\') if escapes && i + 1 < units.len() => {
+                tokens.push(Token::Literal(units[i + 1].to_owned_unit()));
+                segment_start = separator.is_some() && units[i + 1].scalar() == separator;
+                i += 2;
+                continue;
+            }
+            Some(c) if c == any_run => match separator {
+                Some(separator) => {
+                    let stars = units[i..]
+                        .iter()
+                        .take_while(|unit| unit.scalar() == Some(any_run))
+                        .count();
+                    let next = units.get(i + stars).map(|unit| unit.scalar());
+                    i += stars;
+                    if stars > 1 && segment_start && next == Some(Some(separator)) {
+                        // The separator after the stars is part of `AnySegments`, so the
+                        // next token still starts a segment
+                        tokens.push(Token::AnySegments(separator));
+                        i += 1;
+                    } else if stars > 1 && segment_start && next.is_none() {
+                        tokens.push(Token::AnyRun);
+                    } else {
+                        tokens.push(Token::AnyRunInSegment(separator));
+                        segment_start = false;
+                    }
+                    continue;
+                }
+                // A run of stars matches across lines, and a single star doesn't
+                None if multiline => {
+                    let stars = units[i..]
+                        .iter()
+                        .take_while(|unit| unit.scalar() == Some(any_run))
+                        .count();
+                    i += stars;
+                    if stars > 1 {
+                        tokens.push(Token::AnyRun);
+                    } else {
+                        tokens.push(Token::AnyRunInSegment('\n'));
+                    }
+                    continue;
+                }
+                None => tokens.push(Token::AnyRun),
+            },
+            Some(c) if c == any_one && optional_question_marks => match tokens.pop() {
+                Some(Token::Alternatives(mut alternatives)) => {
+                    alternatives.push(Vec::new());
+                    tokens.push(Token::Alternatives(alternatives));
+                }
+                Some(star) if star.is_star() => tokens.push(star),
+                Some(token) => tokens.push(Token::Optional(Box::new(token))),
+                None => tokens.push(Token::Literal(units[i].to_owned_unit())),
+            },
+            Some(c) if c == any_one => match separator.or(multiline.then_some('\n')) {
+                Some(separator) => tokens.push(Token::Class {
+                    negated: true,
+                    items: vec![ClassItem::Range(separator, separator)],
+                }),
+                None => tokens.push(Token::AnyOne),
+            },
+            Some('[') => {
+                // An unterminated class is treated as a literal `[`
+                match parse_class(&units[i + 1..], escapes, separator, fnmatch) {
+                    // A class that excludes nothing is the same as `?`, so it's parsed as one,
+                    // unless `?` means something else
+                    Some((
+                        Token::Class {
+                            negated: true,
+                            items,
+                        },
+                        length,
+                    )) if items.is_empty() && !optional_question_marks => {
+                        tokens.push(Token::AnyOne);
+                        i += length;
+                    }
+                    Some((class, length)) => {
+                        tokens.push(class);
+                        i += length;
+                    }
+                    None => tokens.push(Token::Literal(units[i].to_owned_unit())),
+                }
+            }
+            // And so is an unterminated group
+            Some('{') if braces => match split_alternatives(&units[i + 1..], escapes) {
+                Some((ranges, length)) => {
+                    let group = &units[i + 1..];
+                    let alternatives = ranges
+                        .into_iter()
+                        .map(|range| parse_tokens(&group[range], syntax, segment_start))
+                        .collect();
+                    tokens.push(Token::Alternatives(alternatives));
+                    i += length;
+                }
+                None => tokens.push(Token::Literal(units[i].to_owned_unit())),
+            },
+            _ => tokens.push(Token::Literal(units[i].to_owned_unit())),
+        }
+        segment_start = separator.is_some() && scalar == separator;
+        i += 1;
+    }
+    tokens
+}
+
+/// Find the alternatives of a group, which starts right after the opening `{`.
+///
+/// Returns where each alternative is, and the number of graphemes the group used up
+/// including the closing `}`. The alternatives are separated by the commas which aren't
+/// escaped, inside of a class, or inside of a nested group.
+fn split_alternatives<U: Unit>(units: &[U], escapes: bool) -> Option<(Vec<Range<usize>>, usize)> {
+    let mut alternatives = Vec::new();
+    let mut start: usize = 0;
+    let mut depth: usize = 0;
+    let mut i: usize = 0;
+    loop {
+        match units.get(i)?.scalar() {
+            Some('\\') if escapes && i + 1 < units.len() => i += 1,
+            Some('[') => {
+                if let Some((_, length)) = parse_class(&units[i + 1..], escapes, None, false) {
+                    i += length;
+                }
+            }
+            Some('{') => depth += 1,
+            Some('}') if depth == 0 => {
+                alternatives.push(start..i);
+                return Some((alternatives, i + 1));
+            }
+            Some('}') => depth -= 1,
+            Some(',') if depth == 0 => {
+                alternatives.push(start..i);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+/// Check that a pattern has no unterminated classes or dangling escapes.
+///
+/// `parse` accepts these by treating them as literals, so this walks the pattern the same
+/// way and reports the first one it finds instead.
+pub(crate) fn validate<U: Unit>(units: &[U], escapes: bool) -> Result<(), PatternError> {
+    let mut i: usize = 0;
+    while i < units.len() {
+        match units[i].scalar() {
+            Some('\\') if escapes => {
+                if i + 1 == units.len() {
+                    return Err(PatternError::DanglingEscape { pos: i });
+                }
+                i += 1;
+            }
+            Some('[') => match parse_class(&units[i + 1..], escapes, None, false) {
+                Some((_, length)) => i += length,
+                None => return Err(PatternError::UnterminatedClass { pos: i }),
+            },
+            _ => {}
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+/// Parse the inside of a class, which starts right after the opening `[`.
+///
+/// Returns the class, and the number of graphemes it used up including the closing `]`.
+/// If there is a separator, the class is made to never match it.
+///
+/// If `fnmatch` is set, the class is parsed the way Python's `fnmatch` does: only `!`
+/// negates it, a `]` right after the `[` or `[!` is a member rather than the end of the
+/// class, and there are no named classes.
+fn parse_class<U: Unit>(
+    units: &[U],
+    escapes: bool,
+    separator: Option<char>,
+    fnmatch: bool,
+) -> Option<(Token<U::Owned>, usize)> {
+    // Where a `]` is a member, because it comes before anything else in the class
+    let leading_bracket = match units.first().and_then(Unit::scalar) {
+        Some('!') if fnmatch => 1,
+        _ => 0,
+    };
+    // Collect the members up to the closing bracket
+    let mut members: Vec<Member<U>> = Vec::new();
+    let mut i: usize = 0;
+    loop {
+        let unit = units.get(i)?;
+        match unit.scalar() {
+            Some(']') if fnmatch && i == leading_bracket => members.push(Member::Unit(unit, false)),
+            Some(']') => break,
+            Some('\\') if escapes && i + 1 < units.len() => {
+                members.push(Member::Unit(&units[i + 1], true));
+                i += 1;
+            }
+            Some('[') if fnmatch => members.push(Member::Unit(unit, false)),
+            Some('[') => match parse_named_class(&units[i + 1..]) {
+                Some((class, length)) => {
+                    members.push(Member::Named(class));
+                    i += length;
+                }
+                None => members.push(Member::Unit(unit, false)),
+            },
+            _ => members.push(Member::Unit(unit, false)),
+        }
+        i += 1;
+    }
+    let length = i + 1;
+
+    // Only a `!` or `^` right after the opening bracket negates the class
+    let negated = matches!(
+        members.first(),
+        Some(Member::Unit(unit, false))
+            if matches!(unit.scalar(), Some('!')) || (!fnmatch && unit.scalar() == Some('^'))
+    );
+    let members = if negated { &members[1..] } else { &members[..] };
+
+    // In path mode the separator is left out of a class, or added to a negated one
+    let excluded = separator.filter(|_| !negated);
+
+    let mut items = Vec::new();
+    let mut i: usize = 0;
+    while i < members.len() {
+        // A `-` between two graphemes makes a range, anywhere else it's a literal `-`
+        if let (
+            Some(Member::Unit(start, _)),
+            Some(Member::Unit(dash, false)),
+            Some(Member::Unit(end, _)),
+        ) = (members.get(i), members.get(i + 1), members.get(i + 2))
+        {
+            // Ranges only make sense between single scalar values, so if either end is a
+            // grapheme made of several, we fall back to matching all three literally
+            if let (Some('-'), Some(start), Some(end)) =
+                (dash.scalar(), start.scalar(), end.scalar())
+            {
+                match excluded {
+                    Some(excluded) if start <= excluded && excluded <= end => {
+                        split_range(&mut items, start, end, excluded)
+                    }
+                    _ => items.push(ClassItem::Range(start, end)),
+                }
+                i += 3;
+                continue;
+            }
+        }
+        match members[i] {
+            Member::Unit(unit, _) => {
+                if excluded.is_none() || unit.scalar() != excluded {
+                    items.push(ClassItem::Single(unit.to_owned_unit()));
+                }
+            }
+            Member::Named(class) => {
+                let excluded = excluded.filter(|excluded| class.contains(*excluded));
+                items.push(ClassItem::Named(class, excluded));
+            }
+        }
+        i += 1;
+    }
+    if let (true, Some(separator)) = (negated, separator) {
+        items.push(ClassItem::Range(separator, separator));
+    }
+    Some((Token::Class { negated, items }, length))
+}
+
+/// A member of a class, before the ranges in it are found
+enum Member<'u, U> {
+    /// A grapheme, and whether it was escaped
+    Unit(&'u U, bool),
+    /// A named class, which can't be the end of a range
+    Named(PosixClass),
+}
+
+/// Parse a named class like `[:digit:]` inside of a class, which starts right after its
+/// opening `[`.
+///
+/// Returns the class, and the number of graphemes it used up including the closing `]`. If
+/// the name isn't one of the classes, the `[` is just a member of the outer class.
+fn parse_named_class<U: Unit>(units: &[U]) -> Option<(PosixClass, usize)> {
+    if units.first()?.scalar() != Some(':') {
+        return None;
+    }
+    let name = units[1..]
+        .iter()
+        .map_while(|unit| unit.scalar().filter(char::is_ascii_lowercase))
+        .collect::<String>();
+    let end = name.len() + 1;
+    let closing = (
+        units.get(end).and_then(Unit::scalar),
+        units.get(end + 1).and_then(Unit::scalar),
+    );
+    if closing != (Some(':'), Some(']')) {
+        return None;
+    }
+    Some((PosixClass::from_name(&name)?, end + 2))
+}
+
+/// Add a range to a class, leaving out one scalar value in the middle of it
+fn split_range<O>(items: &mut Vec<ClassItem<O>>, start: char, end: char, excluded: char) {
+    if let Some(before) = (excluded as u32)
+        .checked_sub(1)
+        .and_then(char::from_u32)
+        .filter(|c| start <= *c)
+    {
+        items.push(ClassItem::Range(start, before));
+    }
+    if let Some(after) = char::from_u32(excluded as u32 + 1).filter(|c| *c <= end) {
+        items.push(ClassItem::Range(after, end));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    fn parse_str(pattern: &str) -> Vec<Token> {
+        parse(&pattern.graphemes(true).collect::<Vec<&str>>(), true)
+    }
+
+    fn literal(grapheme: &str) -> Token {
+        Token::Literal(String::from(grapheme))
+    }
+
+    fn class(items: Vec<ClassItem>) -> Token {
+        Token::Class {
+            negated: false,
+            items,
+        }
+    }
+
+    fn single(grapheme: &str) -> ClassItem {
+        ClassItem::Single(String::from(grapheme))
+    }
+
+    #[test]
+    fn wildcards_are_parsed() {
+        assert_eq!(
+            vec![literal("a"), Token::AnyRun, Token::AnyOne],
+            parse_str("a*?")
+        );
+    }
+
+    #[test]
+    fn class_is_parsed() {
+        assert_eq!(
+            vec![
+                literal("x"),
+                class(vec![single("a"), single("b")]),
+                literal("y")
+            ],
+            parse_str("x[ab]y")
+        );
+    }
+
+    #[test]
+    fn unterminated_class_is_literal() {
+        assert_eq!(vec![literal("["), literal("a")], parse_str("[a"));
+    }
+
+    #[test]
+    fn empty_class_is_parsed() {
+        assert_eq!(vec![class(vec![])], parse_str("[]"));
+    }
+
+    #[test]
+    fn range_is_parsed() {
+        assert_eq!(
+            vec![class(vec![
+                ClassItem::Range('a', 'z'),
+                ClassItem::Range('0', '9')
+            ])],
+            parse_str("[a-z0-9]")
+        );
+    }
+
+    #[test]
+    fn dash_at_edges_of_class_is_literal() {
+        assert_eq!(
+            vec![class(vec![single("-"), single("a")])],
+            parse_str("[-a]")
+        );
+        assert_eq!(
+            vec![class(vec![single("a"), single("-")])],
+            parse_str("[a-]")
+        );
+    }
+
+    #[test]
+    fn range_between_multi_scalar_graphemes_is_literal() {
+        assert_eq!(
+            vec![class(vec![single("a"), single("-"), single("e\u{301}")])],
+            parse_str("[a-e\u{301}]")
+        );
+    }
+
+    #[test]
+    fn range_compares_scalar_values() {
+        let class = class(vec![ClassItem::Range('a', 'c')]);
+        assert_eq!(true, class.matches_one(&"b"));
+        assert_eq!(false, class.matches_one(&"d"));
+        assert_eq!(false, class.matches_one(&"b\u{301}"));
+    }
+
+    #[test]
+    fn negated_class_is_parsed() {
+        let negated = Token::Class {
+            negated: true,
+            items: vec![single("a"), single("b")],
+        };
+        assert_eq!(vec![negated.clone()], parse_str("[!ab]"));
+        assert_eq!(vec![negated], parse_str("[^ab]"));
+    }
+
+    #[test]
+    fn exclamation_mark_later_in_class_is_literal() {
+        assert_eq!(
+            vec![class(vec![single("a"), single("!")])],
+            parse_str("[a!]")
+        );
+    }
+
+    #[test]
+    fn escaped_wildcards_are_literals() {
+        assert_eq!(
+            vec![literal("*"), literal("?"), literal("["), literal("\\")],
+            parse_str("\\*\\?\\[\\\\")
+        );
+    }
+
+    #[test]
+    fn trailing_backslash_is_literal() {
+        assert_eq!(vec![literal("a"), literal("\\")], parse_str("a\\"));
+    }
+
+    #[test]
+    fn escapes_can_be_disabled() {
+        let graphemes = "\\*".graphemes(true).collect::<Vec<&str>>();
+        assert_eq!(vec![literal("\\"), Token::AnyRun], parse(&graphemes, false));
+    }
+
+    #[test]
+    fn escapes_inside_class() {
+        assert_eq!(
+            vec![class(vec![single("]"), single("a")])],
+            parse_str("[\\]a]")
+        );
+        assert_eq!(
+            vec![class(vec![single("!"), single("a")])],
+            parse_str("[\\!a]")
+        );
+        assert_eq!(
+            vec![class(vec![single("a"), single("-"), single("z")])],
+            parse_str("[a\\-z]")
+        );
+    }
+
+    #[test]
+    fn valid_patterns_pass_validation() {
+        for pattern in ["", "a*b?", "[a-z]", "[]", "\\[a", "[\\]]", "a\\\\"] {
+            let graphemes = pattern.graphemes(true).collect::<Vec<&str>>();
+            assert_eq!(Ok(()), validate(&graphemes, true), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn unterminated_class_fails_validation() {
+        let graphemes = "ab[cd".graphemes(true).collect::<Vec<&str>>();
+        assert_eq!(
+            Err(PatternError::UnterminatedClass { pos: 2 }),
+            validate(&graphemes, true)
+        );
+        // The backslash escapes the closing bracket
+        let graphemes = "[a\\]".graphemes(true).collect::<Vec<&str>>();
+        assert_eq!(
+            Err(PatternError::UnterminatedClass { pos: 0 }),
+            validate(&graphemes, true)
+        );
+    }
+
+    #[test]
+    fn dangling_escape_fails_validation() {
+        let graphemes = "e\u{301}*\\".graphemes(true).collect::<Vec<&str>>();
+        assert_eq!(
+            Err(PatternError::DanglingEscape { pos: 2 }),
+            validate(&graphemes, true)
+        );
+        assert_eq!(Ok(()), validate(&graphemes, false));
+    }
+
+    fn parse_path_str(pattern: &str) -> Vec<Token> {
+        parse_path(
+            &pattern.graphemes(true).collect::<Vec<&str>>(),
+            &Syntax {
+                escapes: true,
+                separator: Some('/'),
+                ..Syntax::default()
+            },
+        )
+    }
+
+    #[test]
+    fn path_stars_are_parsed() {
+        assert_eq!(
+            vec![
+                literal("a"),
+                literal("/"),
+                Token::AnySegments('/'),
+                Token::AnyRunInSegment('/'),
+            ],
+            parse_path_str("a/**/*")
+        );
+        assert_eq!(
+            vec![Token::AnySegments('/'), literal("a")],
+            parse_path_str("**/a")
+        );
+        assert_eq!(vec![literal("/"), Token::AnyRun], parse_path_str("/**"));
+        assert_eq!(vec![Token::AnyRun], parse_path_str("***"));
+    }
+
+    #[test]
+    fn double_star_inside_segment_is_single_star() {
+        assert_eq!(
+            vec![literal("a"), Token::AnyRunInSegment('/'), literal("/")],
+            parse_path_str("a**/")
+        );
+        assert_eq!(
+            vec![literal("/"), Token::AnyRunInSegment('/'), literal("b")],
+            parse_path_str("/**b")
+        );
+    }
+
+    #[test]
+    fn escaped_separator_starts_segment() {
+        assert_eq!(
+            vec![literal("a"), literal("/"), Token::AnyRun],
+            parse_path_str("a\\/**")
+        );
+    }
+
+    #[test]
+    fn path_classes_exclude_separator() {
+        let negated = Token::Class {
+            negated: true,
+            items: vec![single("a"), ClassItem::Range('/', '/')],
+        };
+        assert_eq!(vec![negated], parse_path_str("[!a]"));
+        assert_eq!(vec![class(vec![single("a")])], parse_path_str("[/a]"));
+        assert_eq!(
+            vec![class(vec![
+                ClassItem::Range('-', '.'),
+                ClassItem::Range('0', '1')
+            ])],
+            parse_path_str("[--1]")
+        );
+        assert_eq!(
+            vec![Token::Class {
+                negated: true,
+                items: vec![ClassItem::Range('/', '/')],
+            }],
+            parse_path_str("?")
+        );
+    }
+
+    #[test]
+    fn named_classes_are_parsed() {
+        assert_eq!(
+            vec![class(vec![
+                ClassItem::Named(PosixClass::Digit, None),
+                single("a")
+            ])],
+            parse_str("[[:digit:]a]")
+        );
+        assert_eq!(
+            vec![Token::Class {
+                negated: true,
+                items: vec![ClassItem::Named(PosixClass::Alpha, None)],
+            }],
+            parse_str("[![:alpha:]]")
+        );
+    }
+
+    #[test]
+    fn unknown_named_class_is_members() {
+        // The `]` after `:foo:` closes the class, and the last `]` is a literal
+        assert_eq!(
+            vec![
+                class(vec![
+                    single("["),
+                    single(":"),
+                    single("f"),
+                    single("o"),
+                    single("o"),
+                    single(":")
+                ]),
+                literal("]")
+            ],
+            parse_str("[[:foo:]]")
+        );
+    }
+
+    #[test]
+    fn path_named_classes_exclude_separator() {
+        assert_eq!(
+            vec![class(vec![ClassItem::Named(PosixClass::Punct, Some('/'))])],
+            parse_path_str("[[:punct:]]")
+        );
+        assert_eq!(
+            vec![class(vec![ClassItem::Named(PosixClass::Digit, None)])],
+            parse_path_str("[[:digit:]]")
+        );
+    }
+
+    #[test]
+    fn wildcards_inside_class_are_members() {
+        assert_eq!(
+            vec![class(vec![single("*"), single("?")])],
+            parse_str("[*?]")
+        );
+    }
+
+    #[test]
+    fn optional_question_marks_are_parsed() {
+        let syntax = Syntax {
+            escapes: true,
+            optional_question_marks: true,
+            ..Syntax::default()
+        };
+        let parse_optional =
+            |pattern: &str| parse_path(&pattern.graphemes(true).collect::<Vec<&str>>(), &syntax);
+        assert_eq!(
+            vec![
+                literal("a"),
+                Token::Optional(Box::new(literal("b"))),
+                Token::Optional(Box::new(class(vec![single("c")]))),
+            ],
+            parse_optional("ab?[c]?")
+        );
+        assert_eq!(
+            vec![literal("?"), Token::AnyRun, literal("?")],
+            parse_optional("?*?\\?")
+        );
+    }
+
+    #[test]
+    fn literal_mode_has_no_wildcards() {
+        let syntax = Syntax {
+            escapes: true,
+            braces: true,
+            literal: true,
+            ..Syntax::default()
+        };
+        assert_eq!(
+            vec![
+                literal("*"),
+                literal("?"),
+                literal("["),
+                literal("a"),
+                literal("]"),
+                literal("\\"),
+                literal("{"),
+            ],
+            parse_path(&["*", "?", "[", "a", "]", "\\", "{"], &syntax)
+        );
+    }
+}
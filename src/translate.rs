@@ -0,0 +1,324 @@
+use crate::token::{ClassItem, Token};
+
+/// Characters which have to be escaped to be matched literally in a regular expression.
+///
+/// Both Python's `re` and the `regex` crate accept all of these escaped, both inside and
+/// outside of character classes.
+const REGEX_META: &str = "\\.+*?()|[]{}^$#&-~";
+
+/// Convert the tokens of a pattern in to a regular expression for Python's `re` module.
+///
+/// Like `fnmatch.translate`, the result is wrapped in `(?s:...)\Z`, so that `.` also
+/// matches newlines and the expression has to match all of the text when used with
+/// `re.match`.
+pub(crate) fn to_python_regex(tokens: &[Token]) -> String {
+    let mut regex = String::from("(?s:");
+    tokens_into(&mut regex, tokens);
+    regex.push_str(")\\Z");
+    regex
+}
+
+/// Convert the tokens of a pattern in to a regular expression for the `regex` crate.
+///
+/// The `regex` crate has no `\\Z`, so the result is anchored with `^` and `$` instead, and
+/// has to match all of the text when used with `Regex::is_match`.
+pub(crate) fn to_rust_regex(tokens: &[Token]) -> String {
+    let mut regex = String::from("^(?s:");
+    tokens_into(&mut regex, tokens);
+    regex.push_str(")$");
+    regex
+}
+
+fn tokens_into(regex: &mut String, tokens: &[Token]) {
+    for token in tokens {
+        match token {
+            Token::Literal(literal) => escape_into(regex, literal),
+            Token::AnyOne => regex.push('.'),
+            Token::AnyRun => regex.push_str(".*"),
+            Token::AnyRunInSegment(separator) => {
+                regex.push_str("[^");
+                escape_into(regex, &separator.to_string());
+                regex.push_str("]*");
+            }
+            Token::AnySegments(separator) => {
+                regex.push_str("(?:.*");
+                escape_into(regex, &separator.to_string());
+                regex.push_str(")?");
+            }
+            Token::Class { negated, items } => class_into(regex, *negated, items),
+            Token::Alternatives(alternatives) => {
+                regex.push_str("(?:");
+                for (i, alternative) in alternatives.iter().enumerate() {
+                    if i > 0 {
+                        regex.push('|');
+                    }
+                    tokens_into(regex, alternative);
+                }
+                regex.push(')');
+            }
+            // The token can be a grapheme of several scalar values, so it's grouped
+            Token::Optional(token) => {
+                regex.push_str("(?:");
+                tokens_into(regex, std::slice::from_ref(token));
+                regex.push_str(")?");
+            }
+        }
+    }
+}
+
+fn escape_into(regex: &mut String, literal: &str) {
+    for c in literal.chars() {
+        if REGEX_META.contains(c) {
+            regex.push('\\');
+        }
+        regex.push(c);
+    }
+}
+
+/// Regular expression classes match a single scalar value rather than a grapheme, so
+/// members made up of several scalar values are matched as alternatives instead.
+fn class_into(regex: &mut String, negated: bool, items: &[ClassItem]) {
+    let mut alternatives = Vec::new();
+    let mut members = String::new();
+    for item in items {
+        match item {
+            ClassItem::Single(grapheme) if grapheme.chars().count() == 1 => {
+                escape_into(&mut members, grapheme)
+            }
+            ClassItem::Single(grapheme) => alternatives.push(grapheme.as_str()),
+            // A reversed range doesn't match anything, and `re` refuses to compile it
+            ClassItem::Range(start, end) if start > end => (),
+            ClassItem::Range(start, end) => {
+                escape_into(&mut members, &start.to_string());
+                members.push('-');
+                escape_into(&mut members, &end.to_string());
+            }
+            // `re` has no named classes, so they're written out as ranges of ASCII
+            ClassItem::Named(class, excluded) => {
+                let mut chars = (0..=127u8)
+                    .map(char::from)
+                    .filter(|c| class.contains(*c) && Some(*c) != *excluded)
+                    .peekable();
+                while let Some(start) = chars.next() {
+                    let mut end = start;
+                    while let Some(next) = chars.next_if(|c| *c as u32 == end as u32 + 1) {
+                        end = next;
+                    }
+                    escape_into(&mut members, &start.to_string());
+                    if end != start {
+                        members.push('-');
+                        escape_into(&mut members, &end.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    if negated {
+        // A single `.` can never match one of the longer alternatives, so they don't need
+        // to be excluded
+        if members.is_empty() {
+            regex.push('.');
+        } else {
+            regex.push_str("[^");
+            regex.push_str(&members);
+            regex.push(']');
+        }
+        return;
+    }
+
+    if alternatives.is_empty() {
+        if members.is_empty() {
+            // An empty class never matches. The `regex` crate has no `(?!)`, but both it
+            // and `re` accept a class of nothing
+            regex.push_str("[^\\s\\S]");
+        } else {
+            regex.push('[');
+            regex.push_str(&members);
+            regex.push(']');
+        }
+        return;
+    }
+
+    regex.push_str("(?:");
+    for (i, alternative) in alternatives.iter().enumerate() {
+        if i > 0 {
+            regex.push('|');
+        }
+        escape_into(regex, alternative);
+    }
+    if !members.is_empty() {
+        regex.push_str("|[");
+        regex.push_str(&members);
+        regex.push(']');
+    }
+    regex.push(')');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MatchOptions, WildcardPattern};
+
+    fn translate(pattern: &str) -> String {
+        to_python_regex(WildcardPattern::new(pattern).tokens())
+    }
+
+    #[test]
+    fn wildcards_are_translated() {
+        assert_eq!("(?s:a.*b.)\\Z", translate("a*b?"));
+    }
+
+    #[test]
+    fn duplicate_stars_are_translated_once() {
+        assert_eq!("(?s:.*)\\Z", translate("***"));
+    }
+
+    #[test]
+    fn metacharacters_are_escaped() {
+        assert_eq!("(?s:a\\.b\\+c\\(d\\)\\$)\\Z", translate("a.b+c(d)$"));
+    }
+
+    #[test]
+    fn escaped_wildcards_are_literals() {
+        assert_eq!("(?s:\\*\\?\\[)\\Z", translate("\\*\\?\\["));
+    }
+
+    #[test]
+    fn classes_are_translated() {
+        assert_eq!("(?s:[abc])\\Z", translate("[abc]"));
+        assert_eq!("(?s:[a-z0-9])\\Z", translate("[a-z0-9]"));
+        assert_eq!("(?s:[^a\\-])\\Z", translate("[!a-]"));
+        assert_eq!("(?s:[\\]\\^])\\Z", translate("[\\]^]"));
+    }
+
+    #[test]
+    fn empty_classes_are_translated() {
+        assert_eq!("(?s:[^\\s\\S])\\Z", translate("[]"));
+        assert_eq!("(?s:.)\\Z", translate("[!]"));
+        assert_eq!("(?s:[^\\s\\S])\\Z", translate("[z-a]"));
+    }
+
+    #[test]
+    fn multi_scalar_class_members_are_alternatives() {
+        assert_eq!("(?s:(?:e\u{301}|[x]))\\Z", translate("[e\u{301}x]"));
+        assert_eq!("(?s:[^x])\\Z", translate("[!e\u{301}x]"));
+    }
+
+    #[test]
+    fn braces_are_translated() {
+        let pattern =
+            WildcardPattern::with_options("*.{rs,t?ml,}", &MatchOptions::new().braces(true));
+        assert_eq!(
+            "(?s:.*\\.(?:rs|t.ml|))\\Z",
+            to_python_regex(pattern.tokens())
+        );
+        assert_eq!("(?s:\\{a,b\\})\\Z", translate("{a,b}"));
+    }
+
+    #[test]
+    fn named_classes_are_translated() {
+        assert_eq!("(?s:[0-9])\\Z", translate("[[:digit:]]"));
+        assert_eq!("(?s:[^A-Za-z_])\\Z", translate("[![:alpha:]_]"));
+        assert_eq!("(?s:[0-9A-Fa-f])\\Z", translate("[[:xdigit:]]"));
+    }
+
+    #[test]
+    fn optional_question_marks_are_translated() {
+        let options = MatchOptions::new().optional_question_marks(true);
+        let pattern = WildcardPattern::with_options("colou?r[0-9]?", &options);
+        assert_eq!(
+            "(?s:colo(?:u)?r(?:[0-9])?)\\Z",
+            to_python_regex(pattern.tokens())
+        );
+    }
+
+    #[test]
+    fn rust_regex_agrees_with_matcher() {
+        let patterns = [
+            "*.rs",
+            "a?c",
+            "[a-c]*[!x]",
+            "a.b+c(d)|e$^",
+            "\\*\\?\\[",
+            "[]",
+            "[!]",
+            "[z-a]",
+            "[[:digit:]]*",
+            "[![:alpha:]_]",
+            "{}#&-~",
+            "*a*a*b",
+            "a\0?",
+            "[\0-\x1f]*",
+            "",
+        ];
+        let texts = [
+            "",
+            "main.rs",
+            "abc",
+            "a.b+c(d)|e$^",
+            "*?[",
+            "x",
+            "7up",
+            "_",
+            "{}#&-~",
+            "aab",
+            "\n",
+            "a\nc",
+            "ab",
+            "a\0\0",
+            "\0\x1b\x07",
+        ];
+        for pattern in patterns {
+            let compiled = WildcardPattern::new(pattern);
+            let regex = regex::Regex::new(&to_rust_regex(compiled.tokens())).unwrap();
+            for text in texts {
+                assert_eq!(
+                    compiled.matches(text),
+                    regex.is_match(text),
+                    "{:?} against {:?}",
+                    pattern,
+                    text
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rust_regex_agrees_with_matcher_with_options() {
+        let cases = [
+            (MatchOptions::new().separator('/'), "src/**/*.rs"),
+            (MatchOptions::new().separator('/'), "**"),
+            (MatchOptions::new().braces(true), "*.{rs,t?ml,}"),
+            (
+                MatchOptions::new().optional_question_marks(true),
+                "colou?r[0-9]?",
+            ),
+        ];
+        let texts = [
+            "src/main.rs",
+            "src/a/b/main.rs",
+            "main.rs",
+            "a/b",
+            "x.rs",
+            "x.toml",
+            "x.",
+            "color",
+            "colour7",
+            "colr",
+        ];
+        for (options, pattern) in cases {
+            let compiled = WildcardPattern::with_options(pattern, &options);
+            let regex = regex::Regex::new(&to_rust_regex(compiled.tokens())).unwrap();
+            for text in texts {
+                assert_eq!(
+                    compiled.matches(text),
+                    regex.is_match(text),
+                    "{:?} against {:?}",
+                    pattern,
+                    text
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::WildcardPattern;
+
+/// Walks a directory tree depth first, yielding the paths whose path relative to the root
+/// matches a path pattern, see `glob`.
+pub(crate) struct Walk {
+    pattern: WildcardPattern,
+    // The entries still to be visited, with their paths relative to the root, the next one
+    // last
+    pending: Vec<(PathBuf, String)>,
+}
+
+impl Walk {
+    pub(crate) fn new(root: &Path, pattern: &str) -> Self {
+        let mut walk = Walk {
+            pattern: WildcardPattern::path(pattern, '/'),
+            pending: Vec::new(),
+        };
+        walk.push_children(root, "");
+        walk
+    }
+
+    /// Queue up the entries of a directory, in order of their names. A directory which
+    /// can't be read is skipped, as if it were empty.
+    fn push_children(&mut self, directory: &Path, relative: &str) {
+        let Ok(entries) = fs::read_dir(directory) else {
+            return;
+        };
+        let mut children = entries
+            .filter_map(Result::ok)
+            .map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let relative = match relative {
+                    "" => name,
+                    parent => format!("{}/{}", parent, name),
+                };
+                (entry.path(), relative)
+            })
+            .collect::<Vec<_>>();
+        // Reversed, so that the first name is popped first
+        children.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+        self.pending.extend(children);
+    }
+}
+
+impl Iterator for Walk {
+    type Item = PathBuf;
+
+    fn next(&mut self) -> Option<PathBuf> {
+        while let Some((path, relative)) = self.pending.pop() {
+            // Symbolic links aren't followed, so a link back up the tree can't loop forever
+            let is_directory = fs::symlink_metadata(&path)
+                .map(|metadata| metadata.is_dir())
+                .unwrap_or(false);
+            if is_directory {
+                self.push_children(&path, &relative);
+            }
+            if self.pattern.matches(&relative) {
+                return Some(path);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory tree in the temporary directory, which is removed again when dropped
+    struct Fixture(PathBuf);
+
+    impl Fixture {
+        fn new(name: &str, files: &[&str]) -> Self {
+            let name = format!("pyglob-{}-{}", name, std::process::id());
+            let root = std::env::temp_dir().join(name);
+            let _ = fs::remove_dir_all(&root);
+            for file in files {
+                let path = root.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, "").unwrap();
+            }
+            Fixture(root)
+        }
+
+        fn glob(&self, pattern: &str) -> Vec<String> {
+            Walk::new(&self.0, pattern)
+                .map(|path| {
+                    let relative = path.strip_prefix(&self.0).unwrap();
+                    let parts = relative
+                        .components()
+                        .map(|part| part.as_os_str().to_string_lossy().into_owned());
+                    parts.collect::<Vec<_>>().join("/")
+                })
+                .collect()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const FILES: [&str; 6] = [
+        "Cargo.toml",
+        "src/lib.rs",
+        "src/main.rs",
+        "src/a/b/deep.rs",
+        "src/a/notes.txt",
+        "tests/test.rs",
+    ];
+
+    #[test]
+    fn glob_matches_within_a_segment() {
+        let fixture = Fixture::new("segment", &FILES);
+        assert_eq!(vec!["src/lib.rs", "src/main.rs"], fixture.glob("src/*.rs"));
+        assert_eq!(vec!["Cargo.toml"], fixture.glob("*.toml"));
+        assert_eq!(vec!["src", "tests"], fixture.glob("[st]*"));
+    }
+
+    #[test]
+    fn glob_matches_across_segments() {
+        let fixture = Fixture::new("globstar", &FILES);
+        assert_eq!(
+            vec![
+                "src/a/b/deep.rs",
+                "src/lib.rs",
+                "src/main.rs",
+                "tests/test.rs"
+            ],
+            fixture.glob("**/*.rs")
+        );
+        assert_eq!(
+            vec!["src/a/b", "src/a/b/deep.rs", "src/a/notes.txt"],
+            fixture.glob("src/a/**")
+        );
+    }
+
+    #[test]
+    fn glob_of_missing_root_is_empty() {
+        let fixture = Fixture::new("missing", &[]);
+        assert_eq!(Vec::<String>::new(), fixture.glob("**"));
+    }
+}
@@ -0,0 +1,4 @@
+fn main() {
+    let pattern = pyglob::wildcard!("*.rs\\");
+    assert!(pattern.matches("main.rs"));
+}
@@ -0,0 +1,5 @@
+fn main() {
+    // The `]` closes the named class, so the class around it is never closed
+    let pattern = pyglob::wildcard!("[[:digit:]");
+    assert!(pattern.matches("1"));
+}
@@ -0,0 +1,4 @@
+fn main() {
+    let pattern = pyglob::wildcard!("src/[a-z.rs");
+    assert!(pattern.matches("src/a.rs"));
+}
@@ -0,0 +1,86 @@
+//! Matching huge texts, checking that the memory used doesn't grow with the text.
+//!
+//! This is its own test binary so that the allocator only counts what these tests allocate.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use pyglob::{MatchOptions, WildcardPattern};
+
+/// The system allocator, keeping track of the most that has been allocated at once
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+        PEAK.fetch_max(allocated, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+// The tests are run one at a time, so that they don't count each other's allocations
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// Returns whether the pattern matches the text, and the most that was allocated at once
+/// while matching, on top of what was allocated before
+fn measure(pattern: &WildcardPattern, text: &str) -> (bool, usize) {
+    let before = ALLOCATED.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+    let matched = pattern.matches(text);
+    (matched, PEAK.load(Ordering::SeqCst) - before)
+}
+
+/// A million graphemes, each an `e` with a combining acute accent
+fn accented(end: &str) -> String {
+    let mut text = "e\u{301}".repeat(1_000_000);
+    text.push_str(end);
+    text
+}
+
+#[test]
+fn long_run_of_one_grapheme_is_matched_in_bounded_memory() {
+    let _serial = SERIAL.lock().unwrap_or_else(|error| error.into_inner());
+    let text = accented("x");
+    for (source, expected) in [
+        ("*x", true),
+        ("e\u{301}*", true),
+        ("*e\u{301}x", true),
+        ("*e\u{301}", false),
+        ("e*x", false),
+        ("*?e\u{301}?", true),
+    ] {
+        let pattern = WildcardPattern::new(source);
+        let (matched, peak) = measure(&pattern, &text);
+        assert_eq!(expected, matched, "{}", source);
+        // Collecting the graphemes would take 16 bytes for each of them, 16MB in all
+        assert!(peak < 1 << 20, "{} allocated {} bytes", source, peak);
+    }
+}
+
+#[test]
+fn long_run_with_a_budget_is_matched_in_bounded_memory() {
+    let _serial = SERIAL.lock().unwrap_or_else(|error| error.into_inner());
+    let text = accented("");
+    let options = MatchOptions::new().budget(Some(1_000));
+    let small = WildcardPattern::with_options("*e\u{301}", &options);
+    assert!(small.try_matches(&text).is_err());
+
+    // The default budget is enough for two tokens and a million graphemes
+    let pattern = WildcardPattern::with_options("*e\u{301}", &MatchOptions::new());
+    let before = ALLOCATED.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+    assert_eq!(Ok(true), pattern.try_matches(&text));
+    assert!(PEAK.load(Ordering::SeqCst) - before < 1 << 20);
+}
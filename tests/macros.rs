@@ -0,0 +1,7 @@
+//! Checks that `wildcard!` rejects invalid patterns while compiling.
+
+#[test]
+fn invalid_patterns_fail_to_compile() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/compile_fail/*.rs");
+}
@@ -0,0 +1,480 @@
+"""Tests for the Python bindings, run with `pytest` after `maturin develop`."""
+import ast
+import fnmatch
+import itertools
+import os
+import pathlib
+import pickle
+import re
+
+import pytest
+
+import pyglob
+
+TRANSLATE_CASES = [
+    ("*.rs", ["main.rs", "main.py", ".rs", "a.rs.bak"]),
+    ("a?c", ["abc", "ac", "abbc", "a\nc"]),
+    ("file[0-9].txt", ["file1.txt", "filex.txt", "file10.txt"]),
+    ("[!abc]", ["a", "d", "-", ""]),
+    ("a.b+c(d)$", ["a.b+c(d)$", "axb+c(d)$", "abbc(d)"]),
+    ("\\*\\?", ["*?", "ab", "*x"]),
+    ("[]", ["", "]", "a"]),
+    ("da*da*da", ["daaadabadmanda", "dada", "dadada"]),
+]
+
+
+def test_filter():
+    names = ["a.rs", "b.py", "c.rs"]
+    assert pyglob.filter(names, "*.rs") == ["a.rs", "c.rs"]
+
+
+def test_filter_returns_same_objects():
+    names = ["a.rs", "b.py"]
+    assert pyglob.filter(names, "*.rs")[0] is names[0]
+
+
+def test_ifilter_yields_the_same_as_filter():
+    names = ["a.rs", "b.py", "c.rs", "", "d.rs.txt"]
+    matches = pyglob.ifilter(names, "*.rs")
+    assert iter(matches) is matches
+    assert list(matches) == pyglob.filter(names, "*.rs")
+    assert list(matches) == []
+
+
+def test_ifilter_is_lazy():
+    def names():
+        index = 0
+        while True:
+            yield f"{index}.rs" if index % 3 == 0 else f"{index}.py"
+            index += 1
+
+    matches = pyglob.ifilter(names(), "*.rs")
+    assert list(itertools.islice(matches, 3)) == ["0.rs", "3.rs", "6.rs"]
+    assert next(matches) == "9.rs"
+
+
+def test_ifilter_rejects_names_which_arent_strings():
+    matches = pyglob.ifilter(["a.rs", 1], "*")
+    assert next(matches) == "a.rs"
+    with pytest.raises(TypeError):
+        next(matches)
+
+
+def test_partition():
+    names = ["a.rs", "b.py", "c.rs", "d.txt"]
+    matching, rest = pyglob.partition(names, "*.rs")
+    assert matching == ["a.rs", "c.rs"]
+    assert rest == ["b.py", "d.txt"]
+    assert sorted(matching + rest) == sorted(names)
+    assert matching[0] is names[0]
+
+
+def test_filter_indices():
+    names = ["a.rs", "b.py", "c.rs", "d.txt"]
+    assert pyglob.filter_indices(names, "*.rs") == [0, 2]
+    assert pyglob.filter_indices([], "*") == []
+
+
+def test_count_matches():
+    assert pyglob.count_matches(["a.rs", "b.py", "c.rs"], "*.rs") == 2
+    assert pyglob.count_matches([], "*") == 0
+
+
+def test_matches_all_texts():
+    texts = ["a.rs", "b.py", "c.rs", "d.txt"]
+    matches = pyglob.matches_all_texts(texts, "*.rs")
+    assert len(matches) == len(texts)
+    assert matches == [True, False, True, False]
+    assert pyglob.matches_all_texts([], "*") == []
+
+
+def test_translate_compiles_and_agrees_with_matcher():
+    for pattern, texts in TRANSLATE_CASES:
+        regex = re.compile(pyglob.translate(pattern))
+        for text in texts:
+            expected = pyglob.is_wildcard_match(text, pattern)
+            assert (regex.match(text) is not None) == expected, (pattern, text)
+
+
+def test_translate_output():
+    assert pyglob.translate("a*b?") == "(?s:a.*b.)\\Z"
+
+
+def test_captures():
+    assert pyglob.captures("src/main.rs", "src/*.rs") == ["main"]
+    assert pyglob.captures("src/main.py", "src/*.rs") is None
+    assert pyglob.compile("*-*").captures("a-b-c") == ["a-b", "c"]
+    assert pyglob.captures("a-b-c", "*-*", lazy=True) == ["a", "b-c"]
+
+
+def test_bytes_match():
+    assert pyglob.is_wildcard_match_bytes(b"file\xff.txt", b"file?.txt")
+    assert not pyglob.is_wildcard_match_bytes("é".encode(), b"?")
+
+
+def test_match_any():
+    assert pyglob.match_any("main.rs", ["*.py", "*.rs"])
+    assert not pyglob.match_any("main.c", ["*.py", "*.rs"])
+    assert not pyglob.match_any("main.rs", [])
+
+
+def test_match_all():
+    assert pyglob.match_all("src/main.rs", ["src/*", "*.rs"])
+    assert not pyglob.match_all("src/main.py", ["src/*", "*.rs"])
+    assert pyglob.match_all("src/main.py", [])
+
+
+def test_matches_at_least_one_of_each():
+    rule = [["*.rs", "*.toml"], ["src/*", "lib/*"]]
+    assert pyglob.matches_at_least_one_of_each("src/main.rs", rule)
+    assert not pyglob.matches_at_least_one_of_each("tests/main.rs", rule)
+    assert pyglob.matches_at_least_one_of_each("src/main.rs", [])
+
+
+def test_matches_filtered():
+    assert pyglob.matches_filtered("src/main.rs", ["*.rs"], ["*_test.rs"])
+    assert not pyglob.matches_filtered("src/main_test.rs", ["*.rs"], ["*_test.rs"])
+    assert pyglob.matches_filtered("notes.txt", [], ["*.rs"])
+    assert not pyglob.matches_filtered("notes.txt", ["*.rs"], [])
+
+
+def test_first_match():
+    patterns = ["*.txt", "main.*", "*.rs"]
+    assert pyglob.first_match("main.rs", patterns) == 1
+    assert pyglob.first_match("lib.rs", patterns) == 2
+    assert pyglob.first_match("main.c", ["*.py", "*.rs"]) is None
+    assert pyglob.first_match("main.rs", []) is None
+
+
+def test_matching_patterns():
+    patterns = ["*", "*.txt", "main.*", "*.rs"]
+    assert pyglob.matching_patterns("main.rs", patterns) == ["*", "main.*", "*.rs"]
+    assert pyglob.matching_patterns("lib.rs", patterns) == ["*", "*.rs"]
+    assert pyglob.matching_patterns("main.c", ["*.py", "*.rs"]) == []
+    assert pyglob.matching_patterns("main.rs", []) == []
+
+
+def test_match_each():
+    patterns = ["*", "*.txt", "main.*", "*.rs"]
+    assert pyglob.match_each("main.rs", patterns) == [True, False, True, True]
+    assert pyglob.match_each("lib.rs", patterns) == [True, False, False, True]
+    assert pyglob.match_each("main.rs", []) == []
+
+
+def test_validate():
+    pyglob.validate("[a-z]*.txt")
+    with pytest.raises(ValueError, match="unterminated class starting at position 1"):
+        pyglob.validate("a[bc")
+    with pytest.raises(ValueError, match="dangling escape at position 2"):
+        pyglob.validate("ab\\")
+
+
+def test_matching_from_several_threads():
+    from concurrent.futures import ThreadPoolExecutor
+
+    text = "a" * 10_000 + "b"
+    pattern = pyglob.compile("*a*b")
+    with ThreadPoolExecutor(max_workers=4) as executor:
+        results = list(executor.map(lambda _: pattern.matches(text), range(8)))
+        results += executor.map(lambda _: pyglob.is_wildcard_match(text, "*a?b"), range(8))
+    assert all(results)
+
+
+def test_is_wildcard_match_normalized():
+    assert pyglob.is_wildcard_match_normalized("é", "é")
+    assert pyglob.is_wildcard_match_normalized("café.txt", "café.*")
+    assert not pyglob.is_wildcard_match("é", "é")
+
+
+def test_is_wildcard_match_path():
+    assert not pyglob.is_wildcard_match_path("src/a/b.rs", "src/*.rs")
+    assert pyglob.is_wildcard_match_path("src/a/b.rs", "src/**/*.rs")
+    assert pyglob.is_wildcard_match_path("src\\a\\b.rs", "src\\**\\*.rs", "\\")
+    assert pyglob.is_wildcard_match_path("src/a/b.rs", "src/**/*.rs", separator="/")
+
+
+def test_is_wildcard_match_fnmatch_agrees_with_fnmatchcase():
+    patterns = ["[]]", "[]a]", "[!]]", "[^a]", "[[:digit:]]", "a\\*b", "[--0]", "*[!.]", "[!"]
+    texts = ["]", "a", "^", "[", "[]", ":]", "a\\b", "a*b", "-", "/", "x.", "xy", "[!"]
+    for pattern in patterns:
+        for text in texts:
+            expected = fnmatch.fnmatchcase(text, pattern)
+            assert pyglob.is_wildcard_match_fnmatch(text, pattern) == expected, (pattern, text)
+
+
+def test_is_wildcard_search():
+    assert pyglob.is_wildcard_search("abcdef", "cd")
+    assert not pyglob.is_wildcard_match("abcdef", "cd")
+    assert not pyglob.is_wildcard_search("abcdef", "dc")
+
+
+def test_find():
+    text = "caf\u00e9 main.rs"
+    start, end = pyglob.find(text, "m*.rs")
+    assert text[start:end] == "main.rs"
+    assert pyglob.find(text, "*.py") is None
+
+
+def test_null_bytes_are_not_terminators():
+    assert pyglob.is_wildcard_match("a\0b", "a?b")
+    assert not pyglob.is_wildcard_match("a\0b", "a\0")
+    assert pyglob.compile("a\0*").matches("a\0b")
+    assert pyglob.is_wildcard_match_bytes(b"a\0b", b"a?b")
+    assert pyglob.captures("a\0b\0", "a*") == ["\0b\0"]
+    assert pyglob.filter(["a\0", "a"], "a?") == ["a\0"]
+
+
+def test_text_comes_before_pattern():
+    assert pyglob.is_wildcard_match("main.rs", "*.rs")
+    assert not pyglob.is_wildcard_match("*.rs", "main.rs")
+    assert pyglob.is_wildcard_match(text="main.rs", pattern="*.rs")
+
+
+def test_is_wildcard_match_keyword_arguments():
+    assert pyglob.is_wildcard_match("MAIN.RS", "*.rs", case_insensitive=True)
+    assert pyglob.is_wildcard_match("dir\\file", "dir\\*", escapes=False)
+    assert pyglob.is_wildcard_match("é", "é", normalization="NFC")
+    assert not pyglob.is_wildcard_match("src/a/b.rs", "src/*.rs", separator="/")
+    assert pyglob.is_wildcard_match(
+        "SRC/a/B.RS", "src/**/*.rs", case_insensitive=True, separator="/"
+    )
+    assert pyglob.is_wildcard_match("image.png", "image.{jpg,png}", braces=True)
+    assert not pyglob.is_wildcard_match("image.png", "image.{jpg,png}")
+    assert pyglob.is_wildcard_match("color", "colou?r", optional_question_marks=True)
+    assert not pyglob.is_wildcard_match("color", "colou?r")
+    assert pyglob.is_wildcard_match("a*b", "a*b", literal=True)
+    assert not pyglob.is_wildcard_match("axb", "a*b", literal=True)
+    with pytest.raises(ValueError, match="unsupported normalization form"):
+        pyglob.is_wildcard_match("a", "a", normalization="NFKC")
+
+
+def test_is_wildcard_match_with_compiled_pattern():
+    for pattern in ["*.rs", "a?c", "[!a]*", "\\*"]:
+        compiled = pyglob.compile(pattern)
+        for text in ["main.rs", "abc", "b", "*", ""]:
+            expected = pyglob.is_wildcard_match(text, pattern)
+            assert pyglob.is_wildcard_match(text, compiled) == expected
+    compiled = pyglob.compile("*.RS", case_insensitive=True)
+    assert pyglob.is_wildcard_match("main.rs", compiled)
+    with pytest.raises(ValueError, match="keyword arguments"):
+        pyglob.is_wildcard_match("main.rs", compiled, case_insensitive=True)
+
+
+def test_prefix():
+    assert pyglob.compile("src/foo*").prefix() == "src/foo"
+    assert pyglob.compile("*x").prefix() == ""
+
+
+def test_suffix():
+    assert pyglob.compile("*.rs").suffix() == ".rs"
+    assert pyglob.compile("foo").suffix() == "foo"
+    assert pyglob.compile("?").suffix() == ""
+
+
+def test_literal_segments():
+    assert pyglob.compile("src/*/main_?.rs").literal_segments() == ["src/", "/main_", ".rs"]
+    assert pyglob.compile("*a\\*b*").literal_segments() == ["a*b"]
+    assert pyglob.compile("*").literal_segments() == []
+
+
+def test_is_literal():
+    assert pyglob.compile("main.rs").is_literal()
+    assert pyglob.compile("\\*").is_literal()
+    assert not pyglob.compile("*.rs").is_literal()
+    assert pyglob.compile("a?").has_wildcards()
+    assert not pyglob.compile("a").has_wildcards()
+
+
+def test_num_stars_and_complexity_hint():
+    pattern = pyglob.compile("a***b*")
+    assert pattern.num_stars() == 2
+    assert pattern.complexity_hint() == 4
+    assert pattern.num_wildcards() == 2
+    assert pyglob.compile("*_[0-9]?").num_wildcards() == 3
+
+
+def test_top_level_alternatives():
+    assert pyglob.is_wildcard_match("bartender", "foo|bar*")
+    assert not pyglob.is_wildcard_match("baz", "foo|bar*")
+    assert pyglob.is_wildcard_match("a|b", pyglob.escape("a|b"))
+
+
+def test_min_and_max_len():
+    assert pyglob.compile("a?c").min_len() == 3
+    assert pyglob.compile("a?c").max_len() == 3
+    assert pyglob.compile("a*c").min_len() == 2
+    assert pyglob.compile("a*c").max_len() is None
+
+
+def test_specificity():
+    rules = [pyglob.compile(rule) for rule in ["*.rs", "main.rs", "*"]]
+    rules.sort(key=lambda rule: rule.specificity(), reverse=True)
+    assert [str(rule) for rule in rules] == ["main.rs", "*.rs", "*"]
+
+
+def test_explain():
+    explanation = pyglob.compile("{a,}e\u0301*", braces=True).explain()
+    assert explanation.splitlines() == [
+        "one of 2 alternatives",
+        "  1:",
+        '    literal "a"',
+        "  2:",
+        "    nothing",
+        'literal "e\\u{301}"',
+        "any run of graphemes",
+    ]
+
+
+def test_compile_is_cached():
+    pyglob.purge()
+    pattern = pyglob.compile("*.rs")
+    assert pyglob.compile("*.rs") is pattern
+    assert pyglob.compile("*.rs", case_insensitive=True) is not pattern
+    # Matching with the same string finds it in the cache rather than compiling again
+    assert pyglob.is_wildcard_match("main.rs", "*.rs")
+    assert pyglob.compile("*.rs") is pattern
+    pyglob.purge()
+    assert pyglob.compile("*.rs") is not pattern
+    assert pyglob.compile("*.rs") == pattern
+
+
+def test_compile_cache_drops_least_recently_used():
+    pyglob.purge()
+    first = pyglob.compile("first")
+    second = pyglob.compile("second")
+    for i in range(511):
+        pyglob.compile(f"{i}")
+        # Keep the first pattern in use, so that the second is the oldest
+        pyglob.is_wildcard_match("first", "first")
+    assert pyglob.compile("first") is first
+    assert pyglob.compile("second") is not second
+
+
+def test_compile_keyword_arguments():
+    pattern = pyglob.compile("src/*.RS", case_insensitive=True, separator="/")
+    assert pattern.matches("src/main.rs")
+    assert not pattern.matches("src/a/main.rs")
+
+
+def test_escape():
+    assert pyglob.escape("what?[1].txt") == "what\\?\\[1].txt"
+    for text in ["*", "a?b", "[!x]", "back\\slash", "é*"]:
+        assert pyglob.is_wildcard_match(text, pyglob.escape(text))
+    assert not pyglob.is_wildcard_match("ab", pyglob.escape("a?"))
+
+
+def test_unescape():
+    assert pyglob.unescape("what\\?\\[1].txt") == "what?[1].txt"
+    assert pyglob.unescape("*.rs") == "*.rs"
+    for text in ["*", "a?b", "[!x]", "back\\slash", "é*", "trailing\\"]:
+        assert pyglob.unescape(pyglob.escape(text)) == text
+
+
+def test_pattern_class_contains():
+    pattern = pyglob.WildcardPattern("*.rs")
+    assert "main.rs" in pattern
+    assert "main.py" not in pattern
+    names = ["a.rs", "b.py", "c.rs"]
+    assert [name for name in names if name in pattern] == ["a.rs", "c.rs"]
+    assert any(name in pattern for name in names)
+
+
+def test_pattern_class_repr():
+    assert repr(pyglob.WildcardPattern("*.rs")) == "WildcardPattern('*.rs')"
+    assert repr(pyglob.WildcardPattern("a***'")) == "WildcardPattern(\"a*'\")"
+
+
+def test_pattern_class_eq():
+    assert pyglob.WildcardPattern("a**") == pyglob.WildcardPattern("a*")
+    assert pyglob.WildcardPattern("a*") != pyglob.WildcardPattern("b*")
+    assert pyglob.WildcardPattern("a*") == pyglob.compile("a*")
+    assert pyglob.WildcardPattern("a*") != pyglob.compile("a*", case_insensitive=True)
+    assert pyglob.WildcardPattern("a*") != "a*"
+    assert len({pyglob.WildcardPattern("a**"), pyglob.WildcardPattern("a*")}) == 1
+
+
+def test_pattern_pickles():
+    pattern = pyglob.WildcardPattern("*.rs")
+    unpickled = pickle.loads(pickle.dumps(pattern))
+    assert unpickled == pattern
+    assert unpickled.matches("main.rs")
+    assert not unpickled.matches("main.py")
+
+
+def test_pattern_pickles_with_options():
+    pattern = pyglob.compile(
+        "SRC/**/*.RS", case_insensitive=True, separator="/", normalization="NFC"
+    )
+    unpickled = pickle.loads(pickle.dumps(pattern))
+    assert unpickled == pattern
+    assert unpickled.matches("src/a/b/main.rs")
+    assert not unpickled.matches("src/a/main.py")
+    pattern = pyglob.compile("dir\\*", escapes=False)
+    unpickled = pickle.loads(pickle.dumps(pattern))
+    assert unpickled == pattern
+    assert unpickled.matches("dir\\file")
+
+
+def test_str_of_compiled_pattern():
+    assert str(pyglob.compile("a***[!]")) == "a*?"
+    assert str(pyglob.compile("\\**")) == "\\**"
+
+
+def test_empty_patterns():
+    assert pyglob.is_wildcard_match("", "")
+    assert not pyglob.is_wildcard_match("test", "")
+    assert pyglob.compile("").matches("")
+    with pytest.raises(ValueError, match="empty"):
+        pyglob.compile("", strict=True)
+    assert pyglob.compile("*", strict=True).matches("")
+
+
+def test_canonical_string():
+    assert pyglob.compile("a***b").canonical_string() == "a*b"
+    assert pyglob.compile("a***b") == pyglob.compile("a*b")
+
+
+def test_stub_declares_every_export():
+    stub = pathlib.Path(__file__).parent.parent / "pyglob.pyi"
+    tree = ast.parse(stub.read_text())
+    declared = {
+        node.name
+        for node in tree.body
+        if isinstance(node, (ast.FunctionDef, ast.ClassDef))
+    }
+    exported = {name for name in dir(pyglob) if not name.startswith("_")}
+    assert exported == declared
+
+    methods = {
+        node.name
+        for cls in tree.body
+        if isinstance(cls, ast.ClassDef) and cls.name == "WildcardPattern"
+        for node in cls.body
+        if isinstance(node, ast.FunctionDef) and not node.name.startswith("_")
+    }
+    public = {name for name in dir(pyglob.WildcardPattern) if not name.startswith("_")}
+    assert public == methods
+
+
+
+def test_lone_surrogates_raise_value_error():
+    # What os.listdir returns for a file name with a byte which isn't valid UTF-8
+    name = b"caf\xe9.txt".decode("utf-8", "surrogateescape")
+    with pytest.raises(ValueError, match="surrogate at position 3"):
+        pyglob.is_wildcard_match(name, "*.txt")
+    with pytest.raises(ValueError, match="surrogate"):
+        pyglob.is_wildcard_match("cafe.txt", "caf\udce9*")
+    with pytest.raises(ValueError, match="surrogate"):
+        pyglob.filter(["a.txt", name], "*.txt")
+    with pytest.raises(ValueError, match="surrogate"):
+        pyglob.match_any(name, ["*.txt"])
+    with pytest.raises(ValueError, match="surrogate"):
+        pyglob.compile("*.txt").matches(name)
+    with pytest.raises(ValueError, match="surrogate"):
+        pyglob.compile("caf\udce9*")
+    assert pyglob.is_wildcard_match_bytes(os.fsencode(name), b"*.txt")
+
+
+def test_pattern_of_the_wrong_type_raises_type_error():
+    with pytest.raises(TypeError, match="str or a WildcardPattern"):
+        pyglob.is_wildcard_match("a", 1)